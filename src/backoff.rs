@@ -0,0 +1,28 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+/// Exponential delay between retry attempts.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Backoff {
+    pub(crate) base: Duration,
+    pub(crate) multiplier: f64,
+    /// Fraction by which each delay is randomly stretched or shrunk, from 0 to 1.
+    pub(crate) jitter: f64,
+}
+
+impl Backoff {
+    /// The delay before retry number `retry` (1 for the second attempt): `base * multiplier^(retry - 1)`,
+    /// randomized by the jitter fraction.
+    pub(crate) fn delay(&self, retry: usize) -> Duration {
+        let exponent = i32::try_from(retry.saturating_sub(1)).unwrap_or(i32::MAX);
+        let seconds = self.base.as_secs_f64() * self.multiplier.powi(exponent);
+        let factor = 1.0 + self.jitter * (2.0 * random_unit() - 1.0);
+        Duration::try_from_secs_f64(seconds * factor).unwrap_or(Duration::MAX)
+    }
+}
+
+/// A random number in `[0, 1)`, from the randomly keyed std hasher so no RNG dependency is needed.
+fn random_unit() -> f64 {
+    (RandomState::new().hash_one(0u8) >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,21 @@
+use anyhow::Result;
+use futures::StreamExt;
+use futures::stream;
+use tracing::info;
+
+use crate::{ParseError, ParserClient};
+
+impl ParserClient {
+    /// Parses each `(document, instructions)` pair as `dynamic_parse` would, running at most
+    /// `concurrency` parses at once (at least one) against the shared model. Every generation
+    /// starts a fresh chat, so parses don't see each other's conversations. Results are returned
+    /// in input order, one per item, whether or not it succeeded.
+    pub async fn dynamic_parse_batch<D: AsRef<str>, I: AsRef<str>>(&self, items: &[(D, I)], concurrency: usize) -> Vec<Result<String, ParseError>> {
+        info!("📚 Parsing a batch of {} documents, {} at a time", items.len(), concurrency.max(1));
+        stream::iter(items)
+            .map(|(document, instructions)| self.dynamic_parse(document.as_ref(), instructions.as_ref()))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}
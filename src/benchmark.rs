@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::{CallOptions, ParserClient};
+
+/// Latency summary for one phase of a benchmark. All fields are zero when nothing was measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub p95: Duration,
+}
+
+impl LatencyStats {
+    fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+        let count = samples.len();
+        let total: Duration = samples.iter().sum();
+        // Nearest-rank percentile: the smallest sample with at least 95% of samples at or below it.
+        let p95_index = (count * 95).div_ceil(100) - 1;
+        Self {
+            min: samples[0],
+            max: samples[count - 1],
+            mean: total / count as u32,
+            p95: samples[p95_index],
+        }
+    }
+}
+
+/// Timing results from `ParserClient::benchmark`.
+#[derive(Debug, Clone)]
+pub struct BenchmarkReport {
+    /// Number of parses performed (samples × runs).
+    pub runs: usize,
+    /// Number of parses that failed after exhausting their retries.
+    pub failures: usize,
+    /// Time spent generating each script, per attempt.
+    pub generation: LatencyStats,
+    /// Time spent executing and validating each generated script, per attempt.
+    pub execution: LatencyStats,
+    /// End-to-end time per parse, including retries.
+    pub total: LatencyStats,
+}
+
+impl ParserClient {
+    /// Parses each `(document, instructions)` sample `runs` times and reports generation,
+    /// execution and end-to-end latencies. Failed parses are counted but still contribute
+    /// their attempt timings.
+    pub async fn benchmark(&self, samples: &[(&str, &str)], runs: usize) -> BenchmarkReport {
+        info!("⏱️  Benchmarking {} samples × {} runs", samples.len(), runs);
+        let options = CallOptions::default();
+        let mut generation = Vec::new();
+        let mut execution = Vec::new();
+        let mut total = Vec::new();
+        let mut failures = 0;
+
+        for (document, instructions) in samples {
+            for _ in 0..runs {
+                let start = Instant::now();
+                let (result, attempts) = self.run_attempts(document, instructions, &options).await;
+                total.push(start.elapsed());
+                if result.is_err() {
+                    failures += 1;
+                }
+                for attempt in &attempts {
+                    generation.push(attempt.generation_time);
+                    execution.extend(attempt.execution_time);
+                }
+            }
+        }
+
+        let report = BenchmarkReport {
+            runs: total.len(),
+            failures,
+            generation: LatencyStats::from_samples(generation),
+            execution: LatencyStats::from_samples(execution),
+            total: LatencyStats::from_samples(total),
+        };
+        info!("📊 Benchmark finished: {} runs, {} failures, p95 total {:.2}s",
+            report.runs, report.failures, report.total.p95.as_secs_f64());
+        report
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+
+use crate::{ParseError, ParserClient, ParserClientBuilder};
+
+/// Runs `future` to completion from synchronous code.
+///
+/// Outside any Tokio runtime, every blocking call shares one multi-threaded runtime, created on
+/// first use and kept for the life of the process, so repeated calls don't pay for a new runtime
+/// and a client built with it can run parses later from any thread. Inside a multi-threaded
+/// runtime, the call blocks its worker in place with `block_in_place` instead of starting a
+/// nested runtime, which would panic. Inside a current-thread runtime there is no safe way to
+/// block, so an error is returned; `.await` the async method there.
+fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            Ok(tokio::task::block_in_place(|| handle.block_on(future)))
+        }
+        Ok(_) => anyhow::bail!("blocking calls can't be made from a current-thread Tokio runtime; await the async method instead"),
+        Err(_) => {
+            let runtime = match RUNTIME.get() {
+                Some(runtime) => runtime,
+                None => {
+                    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+                    RUNTIME.get_or_init(|| runtime)
+                }
+            };
+            Ok(runtime.block_on(future))
+        }
+    }
+}
+
+impl ParserClient {
+    /// Blocking `new`, for callers without an async runtime. See `dynamic_parse_blocking` for
+    /// how the runtime is managed.
+    pub fn new_blocking() -> Result<Self> {
+        block_on(Self::new())?
+    }
+
+    /// Blocking `dynamic_parse`. Outside an async context, calls share a single Tokio runtime
+    /// started on first use, so there's no per-call runtime to set up. From a worker thread of a
+    /// multi-threaded Tokio runtime the parse runs on that runtime via `block_in_place`; from a
+    /// current-thread runtime it returns an error rather than panicking, since blocking there
+    /// would stall the runtime. Prefer `dynamic_parse(...).await` in async code.
+    pub fn dynamic_parse_blocking(&self, document: &str, instructions: &str) -> Result<String, ParseError> {
+        block_on(self.dynamic_parse(document, instructions))?
+    }
+}
+
+impl ParserClientBuilder {
+    /// Blocking `build`, with the same runtime handling as `ParserClient::dynamic_parse_blocking`.
+    pub fn build_blocking(self) -> Result<ParserClient> {
+        block_on(self.build())?
+    }
+}
@@ -0,0 +1,813 @@
+use anyhow::{Context, Result};
+use kalosm::language::*;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::backoff::Backoff;
+use crate::cache::ScriptCache;
+use crate::cassette::CassetteGenerator;
+use crate::generator::NoModel;
+use crate::rlimit::ResourceLimits;
+use crate::shadow::ShadowCallback;
+use crate::tokenizer::ApproximateTokenizer;
+use crate::{
+    AttemptCallback, BinaryMode, CandidateScorer, ChatTranscript, DEFAULT_INTERPRETER, DEFAULT_PROMPT_DOCUMENT_CHARS, DocumentPreprocessor, EventCallback, InputMode, InstructionRephraser, JsonComparator, LlamaGenerator, MAX_RETRIES, MAX_STDERR_BYTES,
+    ModelInterface, Normalization, OutputFormat, OutputValidator, ParseAttempt, ParseError, ParseEvent, ParserClient, RetryPredicate, ScriptExecutor, ScriptGenerator, ScriptLanguage, Serialization, ShadowComparison,
+    StdinProgress, TieBreak, TokenSink, TranscriptSink,
+};
+
+/// Configures and constructs a `ParserClient`.
+pub struct ParserClientBuilder {
+    generator: Option<Box<dyn ScriptGenerator>>,
+    interpreter: PathBuf,
+    max_retries: usize,
+    confidence_threshold: Option<f32>,
+    serialization: Serialization,
+    error_key: Option<String>,
+    binary_prompt_mode: Option<BinaryMode>,
+    tie_break: TieBreak,
+    max_valid_json_attempts: Option<usize>,
+    stdin_progress: Option<StdinProgress>,
+    cassette: Option<PathBuf>,
+    json_comparator: Option<JsonComparator>,
+    script_preamble: Option<String>,
+    max_stderr_bytes: usize,
+    executor: Option<Box<dyn ScriptExecutor>>,
+    instruction_rephraser: Option<InstructionRephraser>,
+    inline_timeout: Option<Duration>,
+    output_example: Option<serde_json::Value>,
+    model_cache_dir: Option<PathBuf>,
+    model_source: Option<LlamaSource>,
+    model_interface: ModelInterface,
+    python_version: Option<(u8, u8)>,
+    language_fallback: Vec<ScriptLanguage>,
+    language_executors: HashMap<ScriptLanguage, Box<dyn ScriptExecutor>>,
+    log_sampling: Option<f64>,
+    structure_reference: Option<String>,
+    field_patterns: HashMap<String, regex::Regex>,
+    deduplicate_output: bool,
+    dedup_key: Option<String>,
+    min_output_bytes: Option<usize>,
+    normalize_line_endings: bool,
+    event_callback: Option<EventCallback>,
+    allow_trailing_data: bool,
+    script_progress: bool,
+    candidate_scorer: Option<CandidateScorer>,
+    candidate_score_threshold: Option<f64>,
+    transcript_sink: Option<TranscriptSink>,
+    prompt_document_chars: Option<usize>,
+    max_json_depth: Option<usize>,
+    model_error_cooldown: Option<Duration>,
+    unicode_normalization: Option<Normalization>,
+    import_allowlist: Option<Vec<String>>,
+    attempt_callback: Option<AttemptCallback>,
+    script_cache: bool,
+    input_mode: InputMode,
+    resource_limits: ResourceLimits,
+    token_sink: Option<TokenSink>,
+    system_prompt: Option<String>,
+    system_rules: Vec<String>,
+    retry_backoff: Option<Backoff>,
+    validators: Vec<OutputValidator>,
+    total_deadline: Option<Duration>,
+    output_format: OutputFormat,
+    self_verification: bool,
+    preprocessor: Option<DocumentPreprocessor>,
+    retry_predicate: Option<RetryPredicate>,
+    banned_modules: Option<Vec<String>>,
+    shadow: Option<(Arc<ParserClient>, ShadowCallback)>,
+    #[cfg(feature = "readability")]
+    readability: bool,
+}
+
+impl Default for ParserClientBuilder {
+    fn default() -> Self {
+        Self {
+            generator: None,
+            interpreter: PathBuf::from(DEFAULT_INTERPRETER),
+            max_retries: MAX_RETRIES,
+            confidence_threshold: None,
+            serialization: Serialization::default(),
+            error_key: None,
+            binary_prompt_mode: None,
+            tie_break: TieBreak::default(),
+            max_valid_json_attempts: None,
+            stdin_progress: None,
+            cassette: None,
+            json_comparator: None,
+            script_preamble: None,
+            max_stderr_bytes: MAX_STDERR_BYTES,
+            executor: None,
+            instruction_rephraser: None,
+            inline_timeout: None,
+            output_example: None,
+            model_cache_dir: None,
+            model_source: None,
+            model_interface: ModelInterface::Chat,
+            python_version: None,
+            language_fallback: Vec::new(),
+            language_executors: HashMap::new(),
+            log_sampling: None,
+            structure_reference: None,
+            field_patterns: HashMap::new(),
+            deduplicate_output: false,
+            dedup_key: None,
+            min_output_bytes: None,
+            normalize_line_endings: false,
+            event_callback: None,
+            allow_trailing_data: false,
+            script_progress: false,
+            candidate_scorer: None,
+            candidate_score_threshold: None,
+            transcript_sink: None,
+            prompt_document_chars: None,
+            max_json_depth: None,
+            model_error_cooldown: None,
+            unicode_normalization: None,
+            import_allowlist: None,
+            attempt_callback: None,
+            script_cache: false,
+            input_mode: InputMode::Stdin,
+            resource_limits: ResourceLimits::default(),
+            token_sink: None,
+            system_prompt: None,
+            system_rules: Vec::new(),
+            retry_backoff: None,
+            validators: Vec::new(),
+            total_deadline: None,
+            output_format: OutputFormat::Json,
+            self_verification: false,
+            preprocessor: None,
+            retry_predicate: None,
+            banned_modules: None,
+            shadow: None,
+            #[cfg(feature = "readability")]
+            readability: false,
+        }
+    }
+}
+
+/// Environment variable holding the interpreter path read by `default_from_env`.
+pub const ENV_PYTHON_PATH: &str = "DYN_PARSE_PYTHON";
+/// Environment variable holding the maximum number of attempts read by `default_from_env`.
+pub const ENV_MAX_RETRIES: &str = "DYN_PARSE_MAX_RETRIES";
+/// Environment variable holding the inline script timeout in seconds read by `default_from_env`.
+pub const ENV_SCRIPT_TIMEOUT_SECS: &str = "DYN_PARSE_SCRIPT_TIMEOUT_SECS";
+
+impl ParserClientBuilder {
+    /// Returns a builder pre-populated from the environment, so deployments can configure every
+    /// client in one place. Unset variables keep their defaults:
+    ///
+    /// - `DYN_PARSE_PYTHON`: interpreter path, as `with_python_path`
+    /// - `DYN_PARSE_MAX_RETRIES`: maximum attempts, as `with_max_retries`
+    /// - `DYN_PARSE_SCRIPT_TIMEOUT_SECS`: inline script timeout in (fractional) seconds, as
+    ///   `with_inline_timeout` (Unix only)
+    ///
+    /// Fails if a variable is set to an unparsable value, or if `DYN_PARSE_SCRIPT_TIMEOUT_SECS` is
+    /// set on a platform without inline timeouts, rather than running scripts without the limit.
+    pub fn default_from_env() -> Result<Self> {
+        Self::from_env_with(|name| std::env::var_os(name))
+    }
+
+    /// `default_from_env` reading variables through `var`, so tests don't have to touch the
+    /// process environment. Values that aren't valid Unicode are ignored, except the path.
+    pub(crate) fn from_env_with(var: impl Fn(&str) -> Option<OsString>) -> Result<Self> {
+        let text = |name| var(name).and_then(|value| value.into_string().ok());
+        let mut builder = Self::default();
+        if let Some(path) = var(ENV_PYTHON_PATH) {
+            builder = builder.with_python_path(path);
+        }
+        if let Some(retries) = text(ENV_MAX_RETRIES) {
+            let retries = retries.trim().parse().with_context(|| format!("Invalid {}: {:?}", ENV_MAX_RETRIES, retries))?;
+            builder = builder.with_max_retries(retries);
+        }
+        if let Some(seconds) = text(ENV_SCRIPT_TIMEOUT_SECS) {
+            let timeout = seconds
+                .trim()
+                .parse()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .with_context(|| format!("Invalid {}: {:?}", ENV_SCRIPT_TIMEOUT_SECS, seconds))?;
+            #[cfg(unix)]
+            {
+                builder = builder.with_inline_timeout(timeout);
+            }
+            #[cfg(not(unix))]
+            anyhow::bail!("{} is set to {:?}, but inline script timeouts are only supported on Unix", ENV_SCRIPT_TIMEOUT_SECS, timeout);
+        }
+        debug!("Loaded ParserClientBuilder configuration from the environment");
+        Ok(builder)
+    }
+
+    /// Builds a client that never loads a model, for servers that only run saved or hand-written
+    /// scripts through `CompiledParser::load` and `run_script`. Generating a script fails.
+    pub fn without_model(self) -> Self {
+        self.with_generator(NoModel)
+    }
+
+    /// Uses a custom script generator instead of loading the default Llama model, e.g. a client for
+    /// a remote model server. Accepts a `Box<dyn ScriptGenerator>` chosen at runtime, too.
+    pub fn with_generator(mut self, generator: impl ScriptGenerator + 'static) -> Self {
+        self.generator = Some(Box::new(generator));
+        self
+    }
+
+    /// Runs generated scripts with a custom executor instead of spawning a `python3` subprocess.
+    /// Interpreter and subprocess options don't apply to custom executors.
+    pub fn with_executor(mut self, executor: impl ScriptExecutor + 'static) -> Self {
+        self.executor = Some(Box::new(executor));
+        self
+    }
+
+    /// Runs every `dynamic_parse` with `shadow` as well, e.g. a candidate model or prompt, and
+    /// reports both outcomes to `on_comparison`. The shadow parse runs in a background task, so
+    /// the caller gets this client's result as soon as it's ready and `on_comparison` is called
+    /// later, from that task. Only `dynamic_parse` is shadowed; the other `dynamic_parse_*`
+    /// methods, whose per-call options the shadow client may not support, run this client alone.
+    pub fn with_shadow(mut self, shadow: ParserClient, on_comparison: impl Fn(ShadowComparison) + Send + Sync + 'static) -> Self {
+        self.shadow = Some((Arc::new(shadow), Arc::new(on_comparison)));
+        self
+    }
+
+    /// Downloads and caches the default model under `dir` instead of kalosm's default location,
+    /// e.g. a mounted volume so containers don't re-download it on every start. Ignored when a
+    /// custom generator is supplied.
+    pub fn with_model_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.model_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Loads `source` as the default model instead of TinyLlama 1.1B, e.g. a 7B model for higher
+    /// accuracy at the cost of speed and memory. The model cache directory still applies. Ignored
+    /// when a custom generator is supplied.
+    pub fn with_model_source(mut self, source: LlamaSource) -> Self {
+        self.model_source = Some(source);
+        self
+    }
+
+    /// Waits `cooldown` after the model fails to generate a script before asking it again, giving
+    /// a struggling backend (e.g. one out of GPU memory) time to recover. Failed script runs
+    /// are retried immediately.
+    pub fn with_model_error_cooldown(mut self, cooldown: Duration) -> Self {
+        self.model_error_cooldown = Some(cooldown);
+        self
+    }
+
+    /// Drives the default model through its chat API (the default) or its raw completion API with
+    /// a single combined prompt. Ignored when a custom generator is supplied.
+    pub fn with_model_interface(mut self, interface: ModelInterface) -> Self {
+        self.model_interface = interface;
+        self
+    }
+
+    /// Cycles through `languages` across attempts, e.g. `[Python, JavaScript]` writes the first
+    /// attempt in Python, the second in JavaScript, and so on. The prompt and executor switch with
+    /// the language. Only Python is used when unset.
+    pub fn with_language_fallback(mut self, languages: Vec<ScriptLanguage>) -> Self {
+        self.language_fallback = languages;
+        self
+    }
+
+    /// Writes every attempt's script in `language`, e.g. `ScriptLanguage::JavaScript` to generate
+    /// standard-library Node scripts run with `node -e` where Python isn't available. Shorthand
+    /// for a single-language `with_language_fallback`; Python remains the default.
+    pub fn with_language(self, language: ScriptLanguage) -> Self {
+        self.with_language_fallback(vec![language])
+    }
+
+    /// Runs scripts written in `language` with `executor` instead of the default subprocess.
+    /// Takes precedence over `with_executor` for that language.
+    pub fn with_language_executor(mut self, language: ScriptLanguage, executor: impl ScriptExecutor + 'static) -> Self {
+        self.language_executors.insert(language, Box::new(executor));
+        self
+    }
+
+    /// Sets the default interpreter used to run generated scripts (`python3` when unset).
+    pub fn with_python_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.interpreter = path.into();
+        self
+    }
+
+    /// Asks the model for scripts compatible with Python `(major, minor)` and rejects scripts using
+    /// newer syntax (e.g. the walrus operator before 3.8) before running them.
+    pub fn with_python_version(mut self, version: (u8, u8)) -> Self {
+        self.python_version = Some(version);
+        self
+    }
+
+    /// Sets the maximum number of generate/execute attempts per parse (10 when unset).
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Treats a successful attempt whose generation had a mean token log-probability below
+    /// `threshold` as a soft failure and makes one more attempt. If that attempt fails, the
+    /// low-confidence result is returned. The built-in model reports log-probabilities from its
+    /// sampler unless generation is streamed (`with_token_sink`); this has no effect with
+    /// generators that don't report them.
+    pub fn with_confidence_threshold(mut self, threshold: f32) -> Self {
+        self.confidence_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets what scripts print, e.g. `OutputFormat::Csv` for tabular extraction (JSON when unset).
+    /// The built-in system prompt and output validation follow the format. Raw and CSV output is
+    /// returned verbatim: only the minimum size and `with_validator` checks apply, so APIs that
+    /// return typed or schema-checked results need JSON.
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.output_format = format;
+        self
+    }
+
+    /// Converts successful results to `serialization` before returning them (JSON when unset).
+    pub fn with_output_serialization(mut self, serialization: Serialization) -> Self {
+        self.serialization = serialization;
+        self
+    }
+
+    /// Treats output whose top-level object contains `key` (e.g. `{"error": "not found"}`) as a
+    /// failed attempt, feeding the reported message back to the model on retry.
+    pub fn with_error_key(mut self, key: impl Into<String>) -> Self {
+        self.error_key = Some(key.into());
+        self
+    }
+
+    /// Distills HTML pages to their main content before parsing, dropping navigation, headers,
+    /// footers, sidebars and scripts. Both the prompt and the script's stdin see the distilled
+    /// document; documents that aren't HTML pages pass through unchanged. Off by default.
+    #[cfg(feature = "readability")]
+    pub fn with_readability(mut self, enabled: bool) -> Self {
+        self.readability = enabled;
+        self
+    }
+
+    /// Converts CRLF and lone CR line endings in the document to LF before it's shown to the model
+    /// or written to a script's stdin, for scripts that split on `\n`.
+    pub fn with_normalize_line_endings(mut self, normalize: bool) -> Self {
+        self.normalize_line_endings = normalize;
+        self
+    }
+
+    /// Normalizes every string in the result to the Unicode normalization `form`, so text that
+    /// arrives composed in one document and decomposed in another compares equal.
+    pub fn with_unicode_normalization(mut self, form: Normalization) -> Self {
+        self.unicode_normalization = Some(form);
+        self
+    }
+
+    /// Only runs Python scripts whose imports are all in `modules`, e.g.
+    /// `DEFAULT_IMPORT_ALLOWLIST` (`sys`, `json`, `re`). The allowed modules are listed in the
+    /// prompt, and a script importing anything else fails its attempt without being run. This is
+    /// a static check on the generated code, not a sandbox. Imports are unrestricted when unset.
+    pub fn with_import_allowlist<S: Into<String>>(mut self, modules: impl IntoIterator<Item = S>) -> Self {
+        self.import_allowlist = Some(modules.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Rejects output nested more than `depth` levels deep (a flat object is 1 level), which
+    /// usually means a runaway recursive script, and asks the model to flatten it on retry.
+    pub fn with_max_json_depth(mut self, depth: usize) -> Self {
+        self.max_json_depth = Some(depth);
+        self
+    }
+
+    /// Shows the model only the first and last `chars / 2` characters of documents longer than
+    /// `chars`, marking the cut. Every failed script doubles the excerpt for the next attempt, in
+    /// case the data it needed was cut out; generation failures don't. Scripts still receive the
+    /// full document on stdin. Defaults to 3000 characters with the built-in model, so large
+    /// documents don't overflow its context window, and to no limit with a custom generator. The
+    /// default excerpt is never widened; set a size explicitly to opt in.
+    pub fn with_prompt_document_chars(mut self, chars: usize) -> Self {
+        self.prompt_document_chars = Some(chars);
+        self
+    }
+
+    /// Shows documents containing non-printable characters to the model in `mode` instead of raw.
+    /// Only the prompt excerpt is affected; scripts still receive the raw document on stdin.
+    pub fn with_binary_prompt_mode(mut self, mode: BinaryMode) -> Self {
+        self.binary_prompt_mode = Some(mode);
+        self
+    }
+
+    /// Rewrites the instructions before each attempt, called with the original instructions and
+    /// the 1-based attempt number. Useful for progressively simplifying a task that keeps failing.
+    pub fn with_instruction_rephraser(mut self, rephraser: impl Fn(&str, usize) -> String + Send + Sync + 'static) -> Self {
+        self.instruction_rephraser = Some(Arc::new(rephraser));
+        self
+    }
+
+    /// Shows `reference`, a representative document with the same structure, to the model so it
+    /// writes a general script. The reference is never parsed; scripts only receive the target document.
+    pub fn with_structure_reference(mut self, reference: impl Into<String>) -> Self {
+        self.structure_reference = Some(reference.into());
+        self
+    }
+
+    /// Shows `example` to the model as the expected output and rejects results whose keys or value
+    /// types differ from it. `null` in the example marks a field whose type isn't checked.
+    pub fn with_output_example(mut self, example: serde_json::Value) -> Self {
+        self.output_example = Some(example);
+        self
+    }
+
+    /// Rejects output whose top-level string field `name` doesn't match `patterns[name]`, e.g. to
+    /// check phone numbers or postal codes without a full schema. Missing and non-string fields
+    /// aren't checked. The mismatch is fed back to the model on retry.
+    pub fn with_field_patterns(mut self, patterns: HashMap<String, regex::Regex>) -> Self {
+        self.field_patterns = patterns;
+        self
+    }
+
+    /// Treats output shorter than `bytes` (ignoring surrounding whitespace) as an incomplete
+    /// extraction and retries, telling the model its output seems too small.
+    pub fn with_min_output_bytes(mut self, bytes: usize) -> Self {
+        self.min_output_bytes = Some(bytes);
+        self
+    }
+
+    /// Removes structurally identical duplicate elements when the result is an array, keeping the
+    /// first occurrence.
+    pub fn with_deduplicate_output(mut self, deduplicate: bool) -> Self {
+        self.deduplicate_output = deduplicate;
+        self
+    }
+
+    /// Deduplicates array results by the `key` field instead of whole-element equality, enabling
+    /// deduplication. Elements without the field are still compared structurally.
+    pub fn with_dedup_key(mut self, key: impl Into<String>) -> Self {
+        self.deduplicate_output = true;
+        self.dedup_key = Some(key.into());
+        self
+    }
+
+    /// Gives up once `attempts` attempts have produced valid JSON that a post-validation check
+    /// rejected, separately from the overall retry limit. Retrying the same instructions often
+    /// reproduces the same valid-but-wrong output.
+    pub fn with_max_valid_json_attempts(mut self, attempts: usize) -> Self {
+        self.max_valid_json_attempts = Some(attempts);
+        self
+    }
+
+    /// Calls `progress` with the total number of bytes written to the script's stdin after each
+    /// chunk, e.g. to show upload progress for very large documents.
+    pub fn with_stdin_progress(mut self, progress: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.stdin_progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Accepts script output that starts with a valid JSON value followed by other text, such as a
+    /// stray log line, keeping just the value. Off by default, so trailing text fails validation.
+    pub fn with_allow_trailing_data(mut self, allow: bool) -> Self {
+        self.allow_trailing_data = allow;
+        self
+    }
+
+    /// Asks the model to report progress by printing `PROGRESS: n/total` lines to stderr, and
+    /// forwards each such line to the event callback as `ParseEvent::ScriptProgress` while the
+    /// script runs. Scripts run by a custom executor don't report progress.
+    pub fn with_script_progress(mut self, enabled: bool) -> Self {
+        self.script_progress = enabled;
+        self
+    }
+
+    /// Calls `sink` at the end of every parse, successful or not, with the full conversation held
+    /// with the model: system prompt, user prompts and raw responses, e.g. to archive for audits.
+    pub fn with_transcript_sink(mut self, sink: impl Fn(&ChatTranscript) + Send + Sync + 'static) -> Self {
+        self.transcript_sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Calls `callback` with a `ParseEvent` as each attempt starts and finishes.
+    pub fn with_event_callback(mut self, callback: impl Fn(&ParseEvent) + Send + Sync + 'static) -> Self {
+        self.event_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Calls `callback` with each attempt as soon as it finishes, whether it failed to generate,
+    /// failed to run or succeeded, e.g. to show "attempt 2 failed: ..." while the parse continues.
+    pub fn with_attempt_callback(mut self, callback: impl Fn(&ParseAttempt) + Send + Sync + 'static) -> Self {
+        self.attempt_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Remembers the script that successfully parsed each instructions/document pair and reruns it
+    /// when the same pair comes up again, skipping generation. If the cached script fails, the
+    /// entry is dropped and the parse falls back to generating a new one. Disabled by default;
+    /// see `ParserClient::clear_cache`.
+    pub fn with_script_cache(mut self, enabled: bool) -> Self {
+        self.script_cache = enabled;
+        self
+    }
+
+    /// Chooses how subprocess scripts receive the document: piped to stdin (the default), or in a
+    /// temporary file passed as the first argument, for documents too large to pipe comfortably.
+    /// The system prompt tells the model which to expect. Custom executors always get the
+    /// document directly.
+    pub fn with_input_mode(mut self, mode: InputMode) -> Self {
+        self.input_mode = mode;
+        self
+    }
+
+    /// Caps the address space of every script subprocess at `bytes` (Unix only). A script that
+    /// runs out fails with `ParseError::MemoryLimitExceeded`, and the next attempt is told so.
+    /// The interpreter itself needs some room, so very small limits stop any script from running.
+    pub fn with_memory_limit(mut self, bytes: u64) -> Self {
+        self.resource_limits.memory_bytes = Some(bytes);
+        self
+    }
+
+    /// Caps the CPU time of every script subprocess at `limit`, rounded up to whole seconds (Unix
+    /// only). A script that exceeds it is killed and fails with `ParseError::CpuLimitExceeded`.
+    /// Unlike the inline timeout, time spent waiting doesn't count.
+    pub fn with_cpu_limit(mut self, limit: Duration) -> Self {
+        self.resource_limits.cpu = Some(limit);
+        self
+    }
+
+    /// Streams generation: `sink` receives each token of the model's response as it's produced,
+    /// e.g. to forward over a channel to a UI. The assembled response is used exactly as without
+    /// streaming, but backends don't report confidence while streaming, so the confidence
+    /// threshold doesn't apply.
+    pub fn with_token_sink(mut self, sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.token_sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Replaces the built-in system prompt, e.g. to allow a parsing library. The replacement is
+    /// used for every script language and input mode, so it should say how the script receives
+    /// the document.
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Appends a rule to the system prompt, built-in or custom, without replacing it. Rules are
+    /// listed in the order they were added.
+    pub fn add_system_rule(mut self, rule: impl Into<String>) -> Self {
+        self.system_rules.push(rule.into());
+        self
+    }
+
+    /// Sleeps between attempts, `base` before the second and `multiplier` times longer before
+    /// each one after, e.g. 1s, 2s, 4s with `(1s, 2.0)`, so transient failures such as resource
+    /// contention have time to clear. Attempts follow each other immediately when unset.
+    pub fn with_retry_backoff(mut self, base: Duration, multiplier: f64) -> Self {
+        let jitter = self.retry_backoff.map_or(0.0, |backoff| backoff.jitter);
+        self.retry_backoff = Some(Backoff { base, multiplier, jitter });
+        self
+    }
+
+    /// Randomly stretches or shrinks each backoff delay by up to `fraction` (clamped to 0..=1),
+    /// so clients failing together don't retry in lockstep. Only applies with `with_retry_backoff`.
+    pub fn with_retry_jitter(mut self, fraction: f64) -> Self {
+        let jitter = fraction.clamp(0.0, 1.0);
+        self.retry_backoff = Some(match self.retry_backoff {
+            Some(backoff) => Backoff { jitter, ..backoff },
+            None => Backoff { base: Duration::ZERO, multiplier: 1.0, jitter },
+        });
+        self
+    }
+
+    /// Adds a check every result must pass, e.g. that a price is positive. Validators receive the
+    /// script's JSON output and run in the order they were added, after the built-in checks; an
+    /// `Err` fails the attempt, and its message is shown to the model on the next one.
+    pub fn with_validator(mut self, validator: impl Fn(&str) -> std::result::Result<(), String> + Send + Sync + 'static) -> Self {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Bounds the wall-clock time of a whole parse across all its attempts. Once `deadline` has
+    /// passed, the in-flight generation or script is abandoned and the parse fails with
+    /// `ParseError::DeadlineExceeded` instead of running the remaining retries.
+    pub fn with_total_deadline(mut self, deadline: Duration) -> Self {
+        self.total_deadline = Some(deadline);
+        self
+    }
+
+    /// After each successful execution, asks the model whether the output satisfies the
+    /// instructions for the document, and fails the attempt if it answers no, e.g. to catch
+    /// valid JSON with empty or wrong fields. Costs an extra model call per successful attempt;
+    /// the verdict is recorded on each `ParseAttempt`.
+    pub fn with_self_verification(mut self, enabled: bool) -> Self {
+        self.self_verification = enabled;
+        self
+    }
+
+    /// Rewrites the document shown to the model, e.g. `strip_html_tags` to spend the model's
+    /// context on content instead of markup. Scripts still receive the original document, and
+    /// the prompt says so.
+    pub fn with_preprocessor(mut self, preprocessor: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        self.preprocessor = Some(Arc::new(preprocessor));
+        self
+    }
+
+    /// Decides which failures trigger another attempt. When `predicate` returns false for a failed
+    /// attempt, the parse stops and returns that attempt's error. By default everything is
+    /// retried except a missing interpreter, which would fail identically every time.
+    pub fn with_retry_predicate(mut self, predicate: impl Fn(&ParseError) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Rejects Python scripts that use any of `modules` without running them, e.g.
+    /// `DEFAULT_BANNED_MODULES` to forbid network and process access. Entries may name modules or
+    /// functions (`os.system`) and are caught even when imported under an alias; the failed
+    /// attempt tells the model no network or process access is allowed. Like the import
+    /// allowlist, this is a static check on the generated code, not a sandbox.
+    pub fn with_banned_modules<S: Into<String>>(mut self, modules: impl IntoIterator<Item = S>) -> Self {
+        self.banned_modules = Some(modules.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets how `dynamic_parse_ensemble` resolves tied votes (prefers the earliest client when unset).
+    pub fn with_ensemble_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Prepends a fixed, vetted `preamble` (imports, stdin helpers, error handling) to every
+    /// generated script. The prompt shows the preamble so the model only writes the extraction logic.
+    pub fn with_script_preamble(mut self, preamble: impl Into<String>) -> Self {
+        self.script_preamble = Some(preamble.into());
+        self
+    }
+
+    /// Injects a `SIGALRM` guard into every script that interrupts it after `timeout` and prints
+    /// a JSON error report, so a runaway loop fails as `ParseError::InlineTimeout` instead of
+    /// hanging. Only effective with the default subprocess executor.
+    #[cfg(unix)]
+    pub fn with_inline_timeout(mut self, timeout: Duration) -> Self {
+        self.inline_timeout = Some(timeout);
+        self
+    }
+
+    /// Caps how much of a script's stderr is kept for error reports (64 KiB when unset). The rest
+    /// is still drained so noisy scripts can't block on a full pipe.
+    pub fn with_max_stderr_bytes(mut self, limit: usize) -> Self {
+        self.max_stderr_bytes = limit;
+        self
+    }
+
+    /// Decides when two JSON results are equal in `assert_golden` and ensemble voting, e.g. to
+    /// ignore float jitter with `json_approx_eq`. Exact equality is used when unset.
+    pub fn with_json_comparator(
+        mut self,
+        comparator: impl Fn(&serde_json::Value, &serde_json::Value) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.json_comparator = Some(Arc::new(comparator));
+        self
+    }
+
+    /// Ranks the results of `dynamic_parse_candidates`; higher scores rank first. Defaults to
+    /// `completeness`, the fraction of fields that hold data.
+    pub fn with_candidate_scorer(mut self, scorer: impl Fn(&serde_json::Value) -> f64 + Send + Sync + 'static) -> Self {
+        self.candidate_scorer = Some(Arc::new(scorer));
+        self
+    }
+
+    /// Makes `dynamic_parse_best_candidate` settle for the first result scoring at least
+    /// `threshold`, cancelling the candidates still running instead of waiting for all of them.
+    pub fn with_candidate_score_threshold(mut self, threshold: f64) -> Self {
+        self.candidate_score_threshold = Some(threshold);
+        self
+    }
+
+    /// Logs the info/debug/trace lines of only a `rate` fraction of parses (0.0 to 1.0), cutting
+    /// log volume under heavy load. Warnings and errors are always logged.
+    pub fn with_log_sampling(mut self, rate: f64) -> Self {
+        self.log_sampling = Some(rate);
+        self
+    }
+
+    /// Records every model request/response to the JSON file at `path` and replays recorded
+    /// responses on later runs, so tests are reproducible without a live model.
+    pub fn with_cassette(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cassette = Some(path.into());
+        self
+    }
+
+    /// Builds the client, loading the AI model if no generator was supplied.
+    pub async fn build(self) -> Result<ParserClient> {
+        let default_model = self.generator.is_none();
+        let (prompt_document_chars, widen_prompt_excerpt) = self.prompt_window(default_model);
+        let mut generator = match self.generator {
+            Some(generator) => generator,
+            None => Box::new(load_default_generator(self.model_source, self.model_cache_dir, self.model_interface).await?),
+        };
+        if let Some(path) = self.cassette {
+            generator = Box::new(CassetteGenerator::open(path, generator)?);
+        }
+
+        let tokenizer = generator.tokenizer().unwrap_or_else(|| Arc::new(ApproximateTokenizer));
+
+        Ok(ParserClient {
+            generator,
+            tokenizer,
+            interpreter: self.interpreter,
+            max_retries: self.max_retries,
+            confidence_threshold: self.confidence_threshold,
+            serialization: self.serialization,
+            error_key: self.error_key,
+            binary_prompt_mode: self.binary_prompt_mode,
+            tie_break: self.tie_break,
+            max_valid_json_attempts: self.max_valid_json_attempts,
+            stdin_progress: self.stdin_progress,
+            json_comparator: self.json_comparator,
+            script_preamble: self.script_preamble,
+            max_stderr_bytes: self.max_stderr_bytes,
+            executor: self.executor,
+            instruction_rephraser: self.instruction_rephraser,
+            inline_timeout: self.inline_timeout,
+            output_example: self.output_example,
+            python_version: self.python_version,
+            language_fallback: self.language_fallback,
+            language_executors: self.language_executors,
+            log_sampling: self.log_sampling,
+            structure_reference: self.structure_reference,
+            field_patterns: self.field_patterns,
+            deduplicate_output: self.deduplicate_output,
+            dedup_key: self.dedup_key,
+            min_output_bytes: self.min_output_bytes,
+            normalize_line_endings: self.normalize_line_endings,
+            event_callback: self.event_callback,
+            allow_trailing_data: self.allow_trailing_data,
+            script_progress: self.script_progress,
+            candidate_scorer: self.candidate_scorer,
+            candidate_score_threshold: self.candidate_score_threshold,
+            transcript_sink: self.transcript_sink,
+            prompt_document_chars,
+            widen_prompt_excerpt,
+            max_json_depth: self.max_json_depth,
+            model_error_cooldown: self.model_error_cooldown,
+            unicode_normalization: self.unicode_normalization,
+            import_allowlist: self.import_allowlist,
+            attempt_callback: self.attempt_callback,
+            script_cache: self.script_cache.then(ScriptCache::default),
+            input_mode: self.input_mode,
+            resource_limits: self.resource_limits,
+            token_sink: self.token_sink,
+            system_prompt: self.system_prompt,
+            system_rules: self.system_rules,
+            retry_backoff: self.retry_backoff,
+            validators: self.validators,
+            total_deadline: self.total_deadline,
+            output_format: self.output_format,
+            self_verification: self.self_verification,
+            preprocessor: self.preprocessor,
+            retry_predicate: self.retry_predicate,
+            banned_modules: self.banned_modules,
+            subprocess_limit: None,
+            shadow: self.shadow,
+            #[cfg(feature = "readability")]
+            readability: self.readability,
+        })
+    }
+
+    /// The prompt excerpt size and whether retries may widen it. Without an explicit size, the
+    /// built-in model gets `DEFAULT_PROMPT_DOCUMENT_CHARS`, which is never widened: doubling it
+    /// would overflow the context window the default exists to protect.
+    pub(crate) fn prompt_window(&self, default_model: bool) -> (Option<usize>, bool) {
+        match self.prompt_document_chars {
+            Some(chars) => (Some(chars), true),
+            None => (default_model.then_some(DEFAULT_PROMPT_DOCUMENT_CHARS), false),
+        }
+    }
+}
+
+/// Loads the default TinyLlama-backed generator, caching the model files under `cache_dir` when set
+/// and generating through `interface`.
+async fn load_default_generator(source: Option<LlamaSource>, cache_dir: Option<PathBuf>, interface: ModelInterface) -> Result<LlamaGenerator> {
+    let start_time = Instant::now();
+    info!("Starting ParserClient initialization...");
+
+    let mut source = match source {
+        Some(source) => {
+            info!("Using the configured model source");
+            source
+        }
+        None => {
+            info!("Using TinyLlama 1.1B Chat model for faster performance");
+            LlamaSource::tiny_llama_1_1b_chat() // Use the chat version which has correct URL format
+        }
+    };
+    if let Some(dir) = cache_dir {
+        info!("Caching model files in {}", dir.display());
+        source = source.with_cache(Cache::new(dir));
+    }
+
+    debug!("Building Llama model...");
+    let model = Llama::builder()
+        .with_source(source)
+        .build()
+        .await?;
+
+    let elapsed = start_time.elapsed();
+    info!("✅ ParserClient initialized successfully in {:.2}s", elapsed.as_secs_f64());
+
+    Ok(LlamaGenerator::new(model).with_interface(interface))
+}
@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+
+use crate::ScriptLanguage;
+
+/// Successful scripts keyed by a hash of the instructions and document they parsed.
+#[derive(Default)]
+pub(crate) struct ScriptCache {
+    scripts: Mutex<HashMap<u64, (String, ScriptLanguage)>>,
+}
+
+impl ScriptCache {
+    pub(crate) fn key(instructions: &str, document: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (instructions, document).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<(String, ScriptLanguage)> {
+        self.scripts.lock().unwrap().get(&key).cloned()
+    }
+
+    pub(crate) fn insert(&self, key: u64, script: String, language: ScriptLanguage) {
+        self.scripts.lock().unwrap().insert(key, (script, language));
+    }
+
+    pub(crate) fn remove(&self, key: u64) {
+        self.scripts.lock().unwrap().remove(&key);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.scripts.lock().unwrap().clear();
+    }
+}
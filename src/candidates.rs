@@ -0,0 +1,124 @@
+use anyhow::{Result, anyhow};
+use futures::StreamExt;
+use futures::future::join_all;
+use futures::stream::FuturesUnordered;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::{CallOptions, ParseError, ParserClient, Serialization};
+
+impl ParserClient {
+    /// Runs `n` independent parses concurrently and returns each distinct successful result paired
+    /// with its score, highest first, for human review when the instructions are ambiguous.
+    /// Results are scored by the configured candidate scorer, or by `completeness` when unset;
+    /// equal results (per the JSON comparator) are listed once. Fails only if every parse fails.
+    pub async fn dynamic_parse_candidates(&self, document: &str, instructions: &str, n: usize) -> Result<Vec<(Value, f64)>, ParseError> {
+        info!("🎲 Generating {} candidate parses", n);
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            ..Default::default()
+        };
+
+        let outcomes = join_all((0..n).map(|_| self.parse_with_attempts(document, instructions, &options))).await;
+        let mut candidates: Vec<(Value, f64)> = Vec::new();
+        let mut errors = Vec::new();
+        for (index, outcome) in outcomes.into_iter().enumerate() {
+            match outcome.and_then(|(result, _)| Ok(serde_json::from_str::<Value>(&result)?)) {
+                Ok(value) if candidates.iter().any(|(existing, _)| self.json_equal(existing, &value)) => {
+                    debug!("Candidate {} duplicates an earlier result", index);
+                }
+                Ok(value) => {
+                    let score = self.score_candidate(&value);
+                    debug!("Candidate {} scored {:.3}", index, score);
+                    candidates.push((value, score));
+                }
+                Err(e) => {
+                    warn!("⚠️  Candidate {} failed: {}", index, e);
+                    errors.push(format!("Candidate {}: {}", index, e));
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            return Err(anyhow!("All {} candidate parses failed:\n{}", n, errors.join("\n")).into());
+        }
+        candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        info!("🏅 Returning {} ranked candidates", candidates.len());
+        Ok(candidates)
+    }
+
+    /// Runs `n` independent parses concurrently and returns the highest-scoring result with its
+    /// score. Once a result reaches the configured candidate score threshold it is returned
+    /// immediately and the parses still running are cancelled, killing their scripts; without a
+    /// threshold every parse runs to completion. Fails only if every parse fails.
+    pub async fn dynamic_parse_best_candidate(&self, document: &str, instructions: &str, n: usize) -> Result<(Value, f64), ParseError> {
+        info!("🎲 Racing {} candidate parses", n);
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            ..Default::default()
+        };
+
+        let mut running: FuturesUnordered<_> = (0..n)
+            .map(|index| {
+                let options = &options;
+                async move { (index, self.parse_with_attempts(document, instructions, options).await) }
+            })
+            .collect();
+        let mut best: Option<(Value, f64)> = None;
+        let mut errors = Vec::new();
+        while let Some((index, outcome)) = running.next().await {
+            let value = match outcome.and_then(|(result, _)| Ok(serde_json::from_str::<Value>(&result)?)) {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("⚠️  Candidate {} failed: {}", index, e);
+                    errors.push(format!("Candidate {}: {}", index, e));
+                    continue;
+                }
+            };
+            let score = self.score_candidate(&value);
+            debug!("Candidate {} scored {:.3}", index, score);
+            if let Some(threshold) = self.candidate_score_threshold
+                && score >= threshold
+            {
+                info!("🏁 Candidate {} reached the score threshold; cancelling {} others", index, running.len());
+                return Ok((value, score));
+            }
+            if best.as_ref().is_none_or(|(_, best_score)| score > *best_score) {
+                best = Some((value, score));
+            }
+        }
+
+        match best {
+            Some(best) => {
+                info!("🏅 Best candidate scored {:.3}", best.1);
+                Ok(best)
+            }
+            None => Err(anyhow!("All {} candidate parses failed:\n{}", n, errors.join("\n")).into()),
+        }
+    }
+
+    /// Scores a result with the configured candidate scorer, or by `completeness` when unset.
+    pub(crate) fn score_candidate(&self, value: &Value) -> f64 {
+        match &self.candidate_scorer {
+            Some(scorer) => scorer(value),
+            None => completeness(value),
+        }
+    }
+}
+
+/// The default candidate score: the fraction of leaf values (scalars and empty containers) that
+/// hold data, i.e. aren't `null`, `""`, `[]` or `{}`. A lone empty result scores 0.
+pub fn completeness(value: &Value) -> f64 {
+    let (filled, total) = count_leaves(value);
+    if total == 0 { 0.0 } else { filled as f64 / total as f64 }
+}
+
+fn count_leaves(value: &Value) -> (usize, usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => map.values().map(count_leaves).fold((0, 0), |(f, t), (a, b)| (f + a, t + b)),
+        Value::Array(items) if !items.is_empty() => items.iter().map(count_leaves).fold((0, 0), |(f, t), (a, b)| (f + a, t + b)),
+        Value::Null | Value::Object(_) | Value::Array(_) => (0, 1),
+        Value::String(text) if text.is_empty() => (0, 1),
+        _ => (1, 1),
+    }
+}
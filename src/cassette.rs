@@ -0,0 +1,101 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+use crate::{Generation, ScriptGenerator, TextTokenizer, TokenCallback};
+
+/// A recorded model exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Recording {
+    system_prompt: String,
+    prompt: String,
+    response: String,
+    logprob: Option<f32>,
+}
+
+/// Wraps a generator so every exchange is recorded to a JSON file and replayed from it on later
+/// runs, keyed by a hash of the prompts. Only prompts missing from the file reach the model.
+pub(crate) struct CassetteGenerator {
+    inner: Box<dyn ScriptGenerator>,
+    path: PathBuf,
+    recordings: Mutex<BTreeMap<String, Recording>>,
+}
+
+impl CassetteGenerator {
+    /// Loads the recordings at `path`, starting an empty cassette if the file doesn't exist yet.
+    pub(crate) fn open(path: PathBuf, inner: Box<dyn ScriptGenerator>) -> Result<Self> {
+        let recordings = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        info!("📼 Opened cassette {} with {} recordings", path.display(), recordings.len());
+        Ok(Self { inner, path, recordings: Mutex::new(recordings) })
+    }
+}
+
+/// Stable FNV-1a hash of the prompts, so keys survive across builds and platforms.
+fn prompt_key(system_prompt: &str, prompt: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in system_prompt.bytes().chain([0]).chain(prompt.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[async_trait]
+impl ScriptGenerator for CassetteGenerator {
+    async fn generate(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+        Ok(self.generate_with_logprob(system_prompt, prompt).await?.text)
+    }
+
+    async fn generate_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        let key = prompt_key(system_prompt, prompt);
+        if let Some(recording) = self.recordings.lock().unwrap().get(&key) {
+            debug!("📼 Replaying recorded response {}", key);
+            return Ok(Generation { text: recording.response.clone(), logprob: recording.logprob });
+        }
+
+        let generation = self.inner.generate_with_logprob(system_prompt, prompt).await?;
+        debug!("📼 Recording response {}", key);
+        let mut recordings = self.recordings.lock().unwrap();
+        recordings.insert(key, Recording {
+            system_prompt: system_prompt.to_string(),
+            prompt: prompt.to_string(),
+            response: generation.text.clone(),
+            logprob: generation.logprob,
+        });
+        std::fs::write(&self.path, serde_json::to_string_pretty(&*recordings)?)?;
+        Ok(generation)
+    }
+
+    async fn generate_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        let key = prompt_key(system_prompt, prompt);
+        if let Some(recording) = self.recordings.lock().unwrap().get(&key) {
+            debug!("📼 Replaying recorded response {}", key);
+            on_token(&recording.response);
+            return Ok(recording.response.clone());
+        }
+
+        let text = self.inner.generate_streaming(system_prompt, prompt, on_token).await?;
+        debug!("📼 Recording response {}", key);
+        let mut recordings = self.recordings.lock().unwrap();
+        recordings.insert(key, Recording {
+            system_prompt: system_prompt.to_string(),
+            prompt: prompt.to_string(),
+            response: text.clone(),
+            logprob: None,
+        });
+        std::fs::write(&self.path, serde_json::to_string_pretty(&*recordings)?)?;
+        Ok(text)
+    }
+
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        self.inner.tokenizer()
+    }
+}
@@ -0,0 +1,84 @@
+use crate::imports::{self, Statement};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// What a feature's pattern is matched against.
+enum Scope {
+    /// A statement's code, with the contents of string literals blanked out.
+    Code,
+    /// The full text of each f-string literal.
+    FString,
+}
+
+/// Syntax features newer than some Python versions, each with the version that introduced it and
+/// a pattern spotting it. Patterns are heuristics run over logical statements rather than a real
+/// parse; they exist to catch the common cases models produce.
+const FEATURES: &[((u8, u8), &str, Scope, &str)] = &[
+    ((3, 6), "f-strings", Scope::Code, F_STRING),
+    ((3, 8), "the walrus operator (:=)", Scope::Code, r":="),
+    ((3, 8), "f-string `=` specifiers", Scope::FString, r"\{[^{}=!<>]*=\s*(![rsa])?(:[^{}]*)?\}"),
+    ((3, 10), "match statements", Scope::Code, r"^\s*match\s+\S.*:\s*$"),
+    ((3, 11), "except* clauses", Scope::Code, r"^\s*except\s*\*"),
+];
+
+/// An f-string prefix and the opening quote that follows it.
+const F_STRING: &str = r#"(^|[^\w])[rR]?[fF][rR]?["']"#;
+
+fn patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        FEATURES.iter().map(|(_, _, _, pattern)| Regex::new(pattern).expect("valid feature pattern")).collect()
+    })
+}
+
+/// The full text of each f-string literal in `statement`, found by their prefixes in the code view
+/// and read back from the raw text, which lines up with it byte for byte.
+fn f_strings(statement: &Statement) -> Vec<&str> {
+    static PREFIX: OnceLock<Regex> = OnceLock::new();
+    let prefix = PREFIX.get_or_init(|| Regex::new(F_STRING).unwrap());
+    prefix
+        .find_iter(&statement.code)
+        .map(|found| {
+            let start = found.end() - 1;
+            let quote = &statement.code[start..found.end()];
+            let triple = quote.repeat(3);
+            let delimiter = if statement.code[start..].starts_with(&triple) { triple.as_str() } else { quote };
+            let body = start + delimiter.len();
+            let end = statement.code[body..].find(delimiter).map_or(statement.code.len(), |at| body + at + delimiter.len());
+            &statement.raw[start..end]
+        })
+        .collect()
+}
+
+/// Lists the syntax features in `script` that `version` doesn't support. Text inside string
+/// literals only counts for checks on f-string contents, so a plain `'f'` or `"a := b"` never
+/// trips a check.
+pub(crate) fn incompatible_syntax(script: &str, version: (u8, u8)) -> Vec<String> {
+    let statements = imports::statements(script);
+    FEATURES
+        .iter()
+        .zip(patterns())
+        .filter(|((introduced, _, scope, _), pattern)| {
+            version < *introduced
+                && statements.iter().any(|statement| match scope {
+                    Scope::Code => pattern.is_match(&statement.code),
+                    Scope::FString => f_strings(statement).iter().any(|literal| pattern.is_match(literal)),
+                })
+        })
+        .map(|((introduced, name, _, _), _)| format!("{} (Python {}.{}+)", name, introduced.0, introduced.1))
+        .collect()
+}
+
+/// Prompt guidance for writing scripts that run on `version`.
+pub(crate) fn version_hint(version: (u8, u8)) -> String {
+    let avoid: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(introduced, _, _, _)| version < *introduced)
+        .map(|(_, name, _, _)| *name)
+        .collect();
+    let mut hint = format!("Target Python {}.{}.", version.0, version.1);
+    if !avoid.is_empty() {
+        hint.push_str(&format!(" Avoid {} and any other syntax or standard library features newer than Python {}.{}.", avoid.join(", "), version.0, version.1));
+    }
+    hint
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::{CallOptions, FailureCategory, ParserClient, ScriptLanguage, spawn_error, subprocess_command};
+
+/// The on-disk form of a `CompiledParser`.
+#[derive(Serialize, Deserialize)]
+struct SavedParser {
+    instructions: String,
+    script: String,
+    language: ScriptLanguage,
+    /// What `<interpreter> --version` printed where the parser was saved.
+    runtime_version: String,
+}
+
+/// A script validated against a sample document, reused to parse other documents with the same
+/// structure without invoking the model. Created with `ParserClient::compile`.
+pub struct CompiledParser<'a> {
+    client: &'a ParserClient,
+    instructions: String,
+    script: String,
+    language: ScriptLanguage,
+}
+
+impl ParserClient {
+    /// Generates a script that parses `document_sample` according to `instructions`, retrying as
+    /// `dynamic_parse` does, and returns it as a `CompiledParser` for documents of the same shape,
+    /// e.g. the other pages of a site.
+    pub async fn compile(&self, document_sample: &str, instructions: impl Into<String>) -> Result<CompiledParser<'_>> {
+        let instructions = instructions.into();
+        let (script, language) = self.compile_script(document_sample, &instructions).await?;
+        Ok(CompiledParser { client: self, instructions, script, language })
+    }
+
+    async fn compile_script(&self, document: &str, instructions: &str) -> Result<(String, ScriptLanguage)> {
+        info!("🧱 Compiling a script for reuse");
+        let (_, attempts) = self.parse_with_attempts(document, instructions, &CallOptions::default()).await?;
+        // A best-effort result comes from the low-confidence attempt rather than a successful one.
+        let attempt = attempts
+            .iter()
+            .rev()
+            .find(|attempt| attempt.succeeded() || attempt.failure_category() == Some(FailureCategory::LowConfidence))
+            .expect("a successful parse has an attempt that produced its result");
+        Ok((attempt.script().to_string(), attempt.language()))
+    }
+
+    /// The version reported by the interpreter that runs `language` scripts, e.g. `Python 3.12.3`.
+    async fn runtime_version(&self, language: ScriptLanguage) -> Result<String> {
+        let (program, _) = subprocess_command(&self.interpreter, language);
+        let output = Command::new(program).arg("--version").output().await.map_err(|e| spawn_error(e, program))?;
+        // Python 2 printed its version on stderr.
+        let version = String::from_utf8_lossy(if output.stdout.is_empty() { &output.stderr } else { &output.stdout }).trim().to_string();
+        Ok(version)
+    }
+}
+
+impl<'a> CompiledParser<'a> {
+    /// Runs the compiled script against `document` and returns the validated result. The model
+    /// is not involved, so a document the script can't handle fails; see `recompile`.
+    pub async fn parse(&self, document: &str) -> Result<String> {
+        self.client.run_script_as(&self.script, self.language, document).await
+    }
+
+    /// Replaces the script with one generated against `document`, e.g. after `parse` failed on a
+    /// page whose layout differs from the original sample. The current script is kept on failure.
+    pub async fn recompile(&mut self, document: &str) -> Result<()> {
+        let (script, language) = self.client.compile_script(document, &self.instructions).await?;
+        self.script = script;
+        self.language = language;
+        Ok(())
+    }
+
+    /// Saves the script, its instructions and the runtime it targets to `path` as JSON, so
+    /// `load` can run it in another process without a model.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let saved = SavedParser {
+            instructions: self.instructions.clone(),
+            script: self.script.clone(),
+            language: self.language,
+            runtime_version: self.client.runtime_version(self.language).await?,
+        };
+        tokio::fs::write(path.as_ref(), serde_json::to_vec_pretty(&saved)?).await?;
+        info!("💾 Saved compiled parser to {}", path.as_ref().display());
+        Ok(())
+    }
+
+    /// Loads a parser written by `save`, to run with `client`, which may be built `without_model`.
+    /// Fails if the script's runtime isn't installed for `client`, and warns if its version
+    /// differs from the one the parser was saved with.
+    pub async fn load(client: &'a ParserClient, path: impl AsRef<Path>) -> Result<Self> {
+        let saved: SavedParser = serde_json::from_slice(&tokio::fs::read(path.as_ref()).await?)?;
+        let installed = client.runtime_version(saved.language).await?;
+        if installed != saved.runtime_version {
+            warn!(
+                "Compiled parser was saved with {} but {} is installed; it may not run as before",
+                saved.runtime_version, installed
+            );
+        }
+        info!("📂 Loaded compiled {} parser from {}", saved.language.name(), path.as_ref().display());
+        Ok(CompiledParser { client, instructions: saved.instructions, script: saved.script, language: saved.language })
+    }
+
+    /// The compiled script.
+    pub fn script(&self) -> &str {
+        &self.script
+    }
+
+    /// The language the compiled script is written in.
+    pub fn language(&self) -> ScriptLanguage {
+        self.language
+    }
+}
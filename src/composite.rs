@@ -0,0 +1,34 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::{CallOptions, ParseError, ParserClient, Serialization};
+
+impl ParserClient {
+    /// Runs several extraction tasks with a single generated script. `tasks` maps a task name to
+    /// its instructions; the script must print one JSON object with a key per task name holding
+    /// that task's result. Attempts whose output lacks a task's key, or adds unknown keys, are
+    /// rejected and retried like any other invalid output.
+    pub async fn dynamic_parse_composite(&self, document: &str, tasks: HashMap<String, String>) -> Result<Value, ParseError> {
+        info!("🧩 Starting composite parse with {} tasks", tasks.len());
+        let mut names: Vec<&String> = tasks.keys().collect();
+        names.sort();
+
+        let mut instructions = String::from(
+            "Perform each of the following tasks on the document. Print a single JSON object with exactly one key per task, named after the task, holding that task's result.\n",
+        );
+        for name in &names {
+            instructions.push_str(&format!("\nTask \"{}\": {}", name, tasks[*name]));
+        }
+        let example = Value::Object(names.iter().map(|name| (name.to_string(), Value::Null)).collect::<Map<_, _>>());
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            output_example: Some(&example),
+            ..Default::default()
+        };
+
+        let (result, _) = self.parse_with_attempts(document, &instructions, &options).await?;
+        Ok(serde_json::from_str(&result)?)
+    }
+}
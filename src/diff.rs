@@ -0,0 +1,105 @@
+use serde_json::Value;
+use std::fmt;
+
+/// A single difference between two JSON values, located by a `$.a[0].b`-style path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonChange {
+    Added { path: String, value: Value },
+    Removed { path: String, value: Value },
+    Changed { path: String, old: Value, new: Value },
+}
+
+/// The structural differences between two JSON values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonDiff {
+    pub changes: Vec<JsonChange>,
+}
+
+impl JsonDiff {
+    /// Computes the changes needed to turn `old` into `new`.
+    pub fn between(old: &Value, new: &Value) -> Self {
+        let mut changes = Vec::new();
+        diff_into("$", old, new, &mut changes);
+        Self { changes }
+    }
+
+    /// Returns true when the two values were structurally equal.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Compares two JSON values structurally, treating numbers within `epsilon` of each other as equal.
+/// Useful as a JSON comparator when results jitter in float precision.
+pub fn json_approx_eq(a: &Value, b: &Value, epsilon: f64) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => (x - y).abs() <= epsilon,
+            _ => x == y,
+        },
+        (Value::Array(xs), Value::Array(ys)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| json_approx_eq(x, y, epsilon))
+        }
+        (Value::Object(xs), Value::Object(ys)) => {
+            xs.len() == ys.len()
+                && xs.iter().all(|(key, x)| ys.get(key).is_some_and(|y| json_approx_eq(x, y, epsilon)))
+        }
+        _ => a == b,
+    }
+}
+
+fn diff_into(path: &str, old: &Value, new: &Value, changes: &mut Vec<JsonChange>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child = format!("{}.{}", path, key);
+                match new_map.get(key) {
+                    Some(new_value) => diff_into(&child, old_value, new_value, changes),
+                    None => changes.push(JsonChange::Removed { path: child, value: old_value.clone() }),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    changes.push(JsonChange::Added { path: format!("{}.{}", path, key), value: new_value.clone() });
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for (index, old_value) in old_items.iter().enumerate() {
+                let child = format!("{}[{}]", path, index);
+                match new_items.get(index) {
+                    Some(new_value) => diff_into(&child, old_value, new_value, changes),
+                    None => changes.push(JsonChange::Removed { path: child, value: old_value.clone() }),
+                }
+            }
+            for (index, new_value) in new_items.iter().enumerate().skip(old_items.len()) {
+                changes.push(JsonChange::Added { path: format!("{}[{}]", path, index), value: new_value.clone() });
+            }
+        }
+        _ if old != new => changes.push(JsonChange::Changed {
+            path: path.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+impl fmt::Display for JsonChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonChange::Added { path, value } => write!(f, "+ {}: {}", path, value),
+            JsonChange::Removed { path, value } => write!(f, "- {}: {}", path, value),
+            JsonChange::Changed { path, old, new } => write!(f, "~ {}: {} -> {}", path, old, new),
+        }
+    }
+}
+
+impl fmt::Display for JsonDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{Result, anyhow};
+use futures::future::join_all;
+use tracing::{debug, info, warn};
+
+use crate::{CallOptions, ParseError, ParserClient, Serialization, output};
+
+/// How `dynamic_parse_ensemble` resolves a vote where several results share the highest count.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Pick the tied result produced by the earliest client (the caller first, then `clients` in order).
+    #[default]
+    PreferEarliest,
+    /// Fail rather than pick between tied results.
+    Error,
+}
+
+impl ParserClient {
+    /// Parses `document` with this client and every client in `clients` concurrently, returning the
+    /// result produced by the most clients. Results are compared after JSON normalization, so key
+    /// order and whitespace don't split the vote, using this client's JSON comparator when one is
+    /// configured. Clients that fail abstain; ties are resolved by this client's `TieBreak` policy.
+    pub async fn dynamic_parse_ensemble(&self, clients: &[&ParserClient], document: &str, instructions: &str) -> Result<String, ParseError> {
+        info!("🗳️  Starting ensemble parse with {} clients", clients.len() + 1);
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            ..Default::default()
+        };
+
+        let voters: Vec<&ParserClient> = std::iter::once(self).chain(clients.iter().copied()).collect();
+        let outcomes = join_all(
+            voters.iter().map(|client| client.parse_with_attempts(document, instructions, &options)),
+        )
+        .await;
+
+        // (votes, earliest voter index, value) per distinct result
+        let mut tally: Vec<(usize, usize, serde_json::Value)> = Vec::new();
+        let mut errors = Vec::new();
+        for (index, outcome) in outcomes.into_iter().enumerate() {
+            match outcome.and_then(|(result, _)| Ok(serde_json::from_str::<serde_json::Value>(&result)?)) {
+                Ok(value) => {
+                    debug!("Client {} voted for {}", index, value);
+                    match tally.iter_mut().find(|(_, _, existing)| self.json_equal(existing, &value)) {
+                        Some((votes, _, _)) => *votes += 1,
+                        None => tally.push((1, index, value)),
+                    }
+                }
+                Err(e) => {
+                    warn!("⚠️  Ensemble client {} failed and abstains: {}", index, e);
+                    errors.push(format!("Client {}: {}", index, e));
+                }
+            }
+        }
+
+        let Some(top_votes) = tally.iter().map(|(votes, _, _)| *votes).max() else {
+            return Err(anyhow!("All {} ensemble clients failed:\n{}", voters.len(), errors.join("\n")).into());
+        };
+        let mut leaders: Vec<_> = tally.into_iter().filter(|(votes, _, _)| *votes == top_votes).collect();
+        if leaders.len() > 1 && self.tie_break == TieBreak::Error {
+            return Err(anyhow!("Ensemble vote tied between {} results with {} votes each", leaders.len(), top_votes).into());
+        }
+
+        let (votes, index, value) = leaders.swap_remove(0);
+        info!("🏆 Ensemble picked client {}'s result with {}/{} votes", index, votes, voters.len());
+        Ok(output::serialize_value(&value, self.serialization)?)
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::ParseAttempt;
+
+/// Typed failures returned by the `dynamic_parse*` methods. Other methods return `anyhow::Error`,
+/// from which a `ParseError` is recoverable via `downcast_ref`; `ParseError` converts into
+/// `anyhow::Error` with `?`.
+///
+/// When every attempt fails, the error is `RetriesExhausted`, which reads "All N parsing attempts
+/// failed..." with the attempt history and carries the attempts themselves. A failure the retry
+/// predicate declines to retry is returned as is, e.g. as `InterpreterNotFound`.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The retry loop finished without a successful attempt. `attempts` holds every attempt made,
+    /// each with its error, and `summary` is the message the error displays.
+    RetriesExhausted { attempts: Vec<ParseAttempt>, summary: String },
+    /// The client's total deadline passed before any attempt succeeded; `attempts` had completed.
+    DeadlineExceeded { deadline: Duration, attempts: usize },
+    /// The model failed to generate a response.
+    Generation(String),
+    /// The interpreter exists but its process could not be started.
+    SpawnFailed { program: PathBuf, error: String },
+    /// The script printed valid JSON, but a post-validation check rejected it.
+    OutputRejected(String),
+    /// The script exited successfully without printing anything.
+    EmptyOutput,
+    /// The script's stdout was not valid JSON.
+    InvalidJson { error: String, output: String },
+    /// The script's stdout was not valid UTF-8 text.
+    InvalidOutput(String),
+    /// The script exited with a non-zero status.
+    NonZeroExit { code: i32, stderr: String, script: String },
+    /// The script was interrupted by the inline timeout guard; `report` is the JSON it printed.
+    InlineTimeout { limit: Duration, report: String },
+    /// The model answered with an explanation instead of a script, so nothing was run.
+    ProseResponse,
+    /// The model's response was empty or whitespace-only, so nothing was run.
+    EmptyResponse,
+    /// The script uses syntax the configured target Python version doesn't support.
+    IncompatibleSyntax { version: (u8, u8), issues: Vec<String> },
+    /// The interpreter binary could not be found, so the script never ran.
+    InterpreterNotFound { path: PathBuf },
+    /// The script imports modules outside the configured allowlist, so it was not run.
+    DisallowedImports { modules: Vec<String> },
+    /// The script uses banned modules or functions, such as network access, so it was not run.
+    BannedModules { modules: Vec<String> },
+    /// The script ran out of the address space allowed by the memory limit.
+    MemoryLimitExceeded { limit_bytes: u64 },
+    /// The script was killed for using more CPU time than the CPU limit allows.
+    CpuLimitExceeded { limit: Duration },
+    /// Any other failure, such as invalid arguments or a result that doesn't deserialize into the
+    /// requested type.
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::RetriesExhausted { summary, .. } => write!(f, "{}", summary),
+            ParseError::DeadlineExceeded { deadline, attempts } => write!(
+                f,
+                "Parse deadline of {:.2}s exceeded after {} completed attempts",
+                deadline.as_secs_f64(),
+                attempts
+            ),
+            ParseError::Generation(error) => write!(f, "Failed to generate script: {}", error),
+            ParseError::SpawnFailed { program, error } => {
+                write!(f, "Failed to start '{}': {}", program.display(), error)
+            }
+            ParseError::OutputRejected(reason) => write!(f, "{}", reason),
+            ParseError::EmptyOutput => write!(f, "Script executed successfully but produced no output"),
+            ParseError::InvalidJson { error, output } => {
+                write!(f, "Script output is not valid JSON: {}\nOutput was: {}", error, output)
+            }
+            ParseError::InvalidOutput(error) => write!(f, "Script output is not valid UTF-8: {}", error),
+            ParseError::NonZeroExit { code, stderr, script } => write!(
+                f,
+                "Script execution failed with exit code: {}\nSTDERR: {}\nSCRIPT:\n{}",
+                code, stderr, script
+            ),
+            ParseError::InlineTimeout { limit, report } => {
+                write!(f, "Script exceeded its inline timeout of {:.2}s: {}", limit.as_secs_f64(), report)
+            }
+            ParseError::ProseResponse => write!(f, "Model returned prose, not code"),
+            ParseError::EmptyResponse => write!(f, "Model returned an empty response"),
+            ParseError::IncompatibleSyntax { version, issues } => write!(
+                f,
+                "Script uses syntax unsupported by Python {}.{}: {}",
+                version.0,
+                version.1,
+                issues.join(", ")
+            ),
+            ParseError::InterpreterNotFound { path } => write!(
+                f,
+                "Interpreter not found at '{}'; install it or point with_python_path at it",
+                path.display()
+            ),
+            ParseError::DisallowedImports { modules } => write!(
+                f,
+                "Script imports modules that are not allowed: {}. Use only the allowed imports",
+                modules.join(", ")
+            ),
+            ParseError::BannedModules { modules } => write!(
+                f,
+                "Script uses {}: no network or process access allowed. Parse the document using only its text",
+                modules.join(", ")
+            ),
+            ParseError::MemoryLimitExceeded { limit_bytes } => write!(
+                f,
+                "Your script used too much memory (limit: {} MiB). Avoid building large intermediate copies of the document",
+                limit_bytes / (1024 * 1024)
+            ),
+            ParseError::CpuLimitExceeded { limit } => write!(
+                f,
+                "Your script used too much CPU time (limit: {:.1}s). Use a simpler approach without nested loops over the document",
+                limit.as_secs_f64()
+            ),
+            ParseError::Other(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+/// A result that doesn't deserialize as expected, e.g. into the caller's type.
+impl From<serde_json::Error> for ParseError {
+    fn from(error: serde_json::Error) -> Self {
+        ParseError::Other(error.into())
+    }
+}
+
+/// Recovers the typed failure behind an internal error; anything that isn't a `ParseError`
+/// becomes `ParseError::Other`.
+impl From<anyhow::Error> for ParseError {
+    fn from(error: anyhow::Error) -> Self {
+        error.downcast().unwrap_or_else(ParseError::Other)
+    }
+}
+
+/// Why an attempt failed, for aggregating retry causes across many runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum FailureCategory {
+    /// The model failed to produce a response.
+    Generation,
+    /// The script could not be compiled by the interpreter.
+    SyntaxError,
+    /// The script raised or exited with a non-zero status while running.
+    RuntimeError,
+    /// The model explained instead of writing code.
+    ProseResponse,
+    /// The model returned nothing.
+    EmptyResponse,
+    /// The script ran past its time limit.
+    Timeout,
+    /// The script exceeded its memory or CPU limit.
+    ResourceLimit,
+    /// The script imported a module outside the allowlist or a banned module.
+    DisallowedImport,
+    /// The script printed nothing.
+    EmptyOutput,
+    /// The script printed something that wasn't valid JSON.
+    InvalidJson,
+    /// The script printed valid JSON that a post-validation check rejected.
+    OutputRejected,
+    /// The attempt succeeded but the model's confidence was below the configured threshold.
+    LowConfidence,
+    /// Any other failure, such as the interpreter failing to start.
+    Other,
+}
+
+impl FailureCategory {
+    /// Classifies an execution or validation error.
+    pub(crate) fn of(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<ParseError>() {
+            Some(ParseError::Generation(_)) => FailureCategory::Generation,
+            Some(ParseError::OutputRejected(_)) => FailureCategory::OutputRejected,
+            Some(ParseError::EmptyOutput) => FailureCategory::EmptyOutput,
+            Some(ParseError::InvalidJson { .. } | ParseError::InvalidOutput(_)) => FailureCategory::InvalidJson,
+            Some(ParseError::NonZeroExit { stderr, .. })
+                if stderr.contains("SyntaxError") || stderr.contains("IndentationError") =>
+            {
+                FailureCategory::SyntaxError
+            }
+            Some(ParseError::ProseResponse) => FailureCategory::ProseResponse,
+            Some(ParseError::EmptyResponse) => FailureCategory::EmptyResponse,
+            Some(ParseError::IncompatibleSyntax { .. }) => FailureCategory::SyntaxError,
+            Some(ParseError::DisallowedImports { .. } | ParseError::BannedModules { .. }) => FailureCategory::DisallowedImport,
+            Some(ParseError::NonZeroExit { .. }) => FailureCategory::RuntimeError,
+            Some(ParseError::InlineTimeout { .. }) => FailureCategory::Timeout,
+            Some(ParseError::MemoryLimitExceeded { .. } | ParseError::CpuLimitExceeded { .. }) => FailureCategory::ResourceLimit,
+            _ => FailureCategory::Other,
+        }
+    }
+}
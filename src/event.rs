@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Caller-supplied key/value metadata attached to a parse, e.g. a request or tenant id.
+pub type ParseMetadata = Arc<HashMap<String, String>>;
+
+/// Progress notifications emitted while a parse runs, delivered to the callback configured with
+/// `ParserClientBuilder::with_event_callback`. Every event carries the parse's metadata (empty
+/// unless the parse was started with `dynamic_parse_tagged`).
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ParseEvent {
+    /// An attempt is about to generate a script.
+    AttemptStarted { attempt: usize, metadata: ParseMetadata },
+    /// An attempt finished, successfully or not.
+    AttemptFinished { attempt: usize, success: bool, metadata: ParseMetadata },
+    /// A running script reported progress by printing `PROGRESS: done/total` to stderr. Only
+    /// emitted when `ParserClientBuilder::with_script_progress` is enabled.
+    ScriptProgress { done: usize, total: usize, metadata: ParseMetadata },
+}
+
+impl ParseEvent {
+    /// The metadata of the parse that emitted this event.
+    pub fn metadata(&self) -> &ParseMetadata {
+        match self {
+            ParseEvent::AttemptStarted { metadata, .. }
+            | ParseEvent::AttemptFinished { metadata, .. }
+            | ParseEvent::ScriptProgress { metadata, .. } => metadata,
+        }
+    }
+}
+
+/// Renders metadata as sorted `key=value` pairs for a tracing span field.
+pub(crate) fn format_metadata(metadata: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<String> = metadata.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+    pairs.sort();
+    pairs.join(" ")
+}
+
+/// Parses a `PROGRESS: done/total` line printed to stderr by a script.
+pub(crate) fn parse_progress(line: &str) -> Option<(usize, usize)> {
+    let (done, total) = line.trim().strip_prefix("PROGRESS:")?.split_once('/')?;
+    Some((done.trim().parse().ok()?, total.trim().parse().ok()?))
+}
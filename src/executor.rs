@@ -0,0 +1,18 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// What a script printed when it exited successfully.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs generated scripts. The client spawns a `python3` subprocess unless another executor is
+/// configured, e.g. a sandboxed runner or an in-process fake for tests.
+#[async_trait]
+pub trait ScriptExecutor: Send + Sync {
+    /// Runs `script` with `document` as its input. A script that fails should be reported as an
+    /// error, such as `ParseError::NonZeroExit`; output validation is left to the client.
+    async fn execute(&self, script: &str, document: &str) -> Result<ScriptOutput>;
+}
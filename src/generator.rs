@@ -0,0 +1,346 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+use kalosm::language::*;
+use std::sync::{Arc, Mutex};
+
+use crate::TextTokenizer;
+use crate::tokenizer::ModelTokenizer;
+
+/// A model response together with the generation confidence, when the backend reports it.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub text: String,
+    /// Mean per-token log-probability of `text`.
+    pub logprob: Option<f32>,
+}
+
+/// Receives each piece of a streamed model response.
+pub type TokenCallback = dyn Fn(&str) + Send + Sync;
+
+/// A backend capable of writing parsing scripts in response to a prompt. `LlamaGenerator` drives
+/// a local kalosm model; implement `generate` to plug in anything else, such as an HTTP API, and
+/// pass it to `ParserClientBuilder::with_generator`.
+///
+/// The trait is object-safe, so clients backed by different generators share the single
+/// `ParserClient` type. Keep it that way: new methods must not be generic or return `Self`, and
+/// async methods go through `#[async_trait]`. Implementations must be `Send + Sync` because a
+/// client may be shared across tasks.
+#[async_trait]
+pub trait ScriptGenerator: Send + Sync {
+    /// Generates a response to `prompt` in a fresh conversation seeded with `system_prompt`.
+    /// Calls are independent: the client puts everything the model needs to know about earlier
+    /// attempts into `prompt`, so implementations need not keep a chat history.
+    async fn generate(&self, system_prompt: &str, prompt: &str) -> Result<String>;
+
+    /// Like `generate`, but also captures the mean token log-probability of the response.
+    /// Backends that cannot report log-probabilities leave it as `None`.
+    async fn generate_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        let text = self.generate(system_prompt, prompt).await?;
+        Ok(Generation { text, logprob: None })
+    }
+
+    /// Like `generate`, but calls `on_token` with each piece of the response as it's produced.
+    /// Backends that can't stream pass the whole response as a single piece.
+    async fn generate_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        let text = self.generate(system_prompt, prompt).await?;
+        on_token(&text);
+        Ok(text)
+    }
+
+    /// The tokenizer of the underlying model, if it has one. The client loads it once at
+    /// construction and falls back to an approximate tokenizer otherwise.
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        None
+    }
+}
+
+/// Stands in for a model on clients that only run existing scripts; every generation fails.
+pub(crate) struct NoModel;
+
+#[async_trait]
+impl ScriptGenerator for NoModel {
+    async fn generate(&self, _system_prompt: &str, _prompt: &str) -> Result<String> {
+        anyhow::bail!("this client was built without a model")
+    }
+}
+
+/// Which kalosm API the default generator drives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModelInterface {
+    /// The chat API: the system prompt and request are sent as separate chat messages and the
+    /// model's own chat template formats them.
+    #[default]
+    Chat,
+    /// The raw completion API: the system prompt and request are combined into a single prompt
+    /// (see `completion_prompt`), giving full control over how the model sees them.
+    Completion,
+}
+
+/// A loaded language model that exposes both a chat and a raw completion interface.
+#[async_trait]
+pub trait ModelBackend: Send + Sync {
+    /// Answers `prompt` in a fresh chat seeded with `system_prompt`.
+    async fn chat(&self, system_prompt: &str, prompt: &str) -> Result<String>;
+
+    /// Continues `prompt` verbatim.
+    async fn complete(&self, prompt: &str) -> Result<String>;
+
+    /// Like `chat`, but also reports the mean log-probability of the generated tokens. Backends
+    /// that can't observe token probabilities leave it as `None`.
+    async fn chat_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        let text = self.chat(system_prompt, prompt).await?;
+        Ok(Generation { text, logprob: None })
+    }
+
+    /// Like `complete`, but also reports the mean log-probability of the generated tokens.
+    async fn complete_with_logprob(&self, prompt: &str) -> Result<Generation> {
+        let text = self.complete(prompt).await?;
+        Ok(Generation { text, logprob: None })
+    }
+
+    /// Like `chat`, but calls `on_token` with each token as it's generated.
+    async fn chat_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        let text = self.chat(system_prompt, prompt).await?;
+        on_token(&text);
+        Ok(text)
+    }
+
+    /// Like `complete`, but calls `on_token` with each token as it's generated.
+    async fn complete_streaming(&self, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        let text = self.complete(prompt).await?;
+        on_token(&text);
+        Ok(text)
+    }
+
+    /// The model's tokenizer, if it exposes one.
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        None
+    }
+}
+
+#[async_trait]
+impl ModelBackend for Llama {
+    async fn chat(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+        let mut chat = ChatModelExt::chat(self).with_system_prompt(system_prompt);
+        chat.add_message(prompt)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        TextCompletionModelExt::complete(self, prompt)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn chat_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        let (sampler, logprobs) = LogprobRecorder::new();
+        let mut chat = ChatModelExt::chat(self).with_system_prompt(system_prompt);
+        let text = chat
+            .add_message(prompt)
+            .with_sampler(sampler)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(Generation { text, logprob: mean(&logprobs.lock().unwrap()) })
+    }
+
+    async fn complete_with_logprob(&self, prompt: &str) -> Result<Generation> {
+        let (sampler, logprobs) = LogprobRecorder::new();
+        let text = TextCompletionModelExt::complete(self, prompt)
+            .with_sampler(sampler)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(Generation { text, logprob: mean(&logprobs.lock().unwrap()) })
+    }
+
+    async fn chat_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        let mut chat = ChatModelExt::chat(self).with_system_prompt(system_prompt);
+        let mut tokens = chat.add_message(prompt);
+        let mut text = String::new();
+        while let Some(token) = tokens.next().await {
+            on_token(&token);
+            text.push_str(&token);
+        }
+        Ok(text)
+    }
+
+    async fn complete_streaming(&self, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        let mut tokens = TextCompletionModelExt::complete(self, prompt);
+        let mut text = String::new();
+        while let Some(token) = tokens.next().await {
+            on_token(&token);
+            text.push_str(&token);
+        }
+        Ok(text)
+    }
+
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        Some(Arc::new(ModelTokenizer(Llama::tokenizer(self).clone())))
+    }
+}
+
+/// Samples like kalosm's default sampler chain while recording the log-probability of every token
+/// it picks, as shared with the caller through the `Arc`.
+#[derive(Debug)]
+struct LogprobRecorder {
+    chain: SamplerChain,
+    logprobs: Arc<Mutex<Vec<f32>>>,
+}
+
+impl LogprobRecorder {
+    fn new() -> (Self, Arc<Mutex<Vec<f32>>>) {
+        let logprobs = Arc::new(Mutex::new(Vec::new()));
+        (Self { chain: GenerationParameters::default().sampler(), logprobs: logprobs.clone() }, logprobs)
+    }
+}
+
+impl Sampler for LogprobRecorder {
+    fn sample<'a>(&mut self, res: &mut dyn HasSamplerResources, logits: &'a mut Logits) -> anyhow::Result<&'a mut Logits> {
+        let logits = self.chain.sample(res, logits)?;
+        // The chain leaves softmaxed probabilities on the candidates it sampled from.
+        if let Some(token) = self.chain.sampled_token_id()
+            && let Some(sampled) = logits.iter().find(|logit| logit.token_id == token)
+        {
+            self.logprobs.lock().unwrap().push(sampled.prob.max(f32::MIN_POSITIVE).ln());
+        }
+        Ok(logits)
+    }
+
+    fn sampled_token_id(&self) -> Option<u32> {
+        self.chain.sampled_token_id()
+    }
+}
+
+/// The mean of `logprobs`, or `None` if no tokens were recorded.
+fn mean(logprobs: &[f32]) -> Option<f32> {
+    (!logprobs.is_empty()).then(|| logprobs.iter().sum::<f32>() / logprobs.len() as f32)
+}
+
+/// Combines a system prompt and request into a single completion prompt, using the Zephyr-style
+/// template the default TinyLlama chat model was trained on and ending on the assistant turn.
+pub fn completion_prompt(system_prompt: &str, prompt: &str) -> String {
+    format!(
+        "<|system|>\n{}</s>\n<|user|>\n{}</s>\n<|assistant|>\n",
+        system_prompt.trim(),
+        prompt.trim()
+    )
+}
+
+/// A generator backed by a local model, by default a kalosm `Llama`.
+pub struct LlamaGenerator<M: ModelBackend = Llama> {
+    model: M,
+    interface: ModelInterface,
+}
+
+impl<M: ModelBackend> LlamaGenerator<M> {
+    /// Wraps an already-loaded model, using its chat interface.
+    pub fn new(model: M) -> Self {
+        Self { model, interface: ModelInterface::Chat }
+    }
+
+    /// Selects whether scripts are generated through the chat or the completion interface.
+    pub fn with_interface(mut self, interface: ModelInterface) -> Self {
+        self.interface = interface;
+        self
+    }
+}
+
+#[async_trait]
+impl<M: ModelBackend> ScriptGenerator for LlamaGenerator<M> {
+    async fn generate(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+        match self.interface {
+            ModelInterface::Chat => self.model.chat(system_prompt, prompt).await,
+            ModelInterface::Completion => self.model.complete(&completion_prompt(system_prompt, prompt)).await,
+        }
+    }
+
+    async fn generate_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        match self.interface {
+            ModelInterface::Chat => self.model.chat_with_logprob(system_prompt, prompt).await,
+            ModelInterface::Completion => self.model.complete_with_logprob(&completion_prompt(system_prompt, prompt)).await,
+        }
+    }
+
+    async fn generate_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        match self.interface {
+            ModelInterface::Chat => self.model.chat_streaming(system_prompt, prompt, on_token).await,
+            ModelInterface::Completion => {
+                self.model.complete_streaming(&completion_prompt(system_prompt, prompt), on_token).await
+            }
+        }
+    }
+
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        self.model.tokenizer()
+    }
+}
+
+#[async_trait]
+impl<G: ScriptGenerator + ?Sized> ScriptGenerator for Box<G> {
+    async fn generate(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+        (**self).generate(system_prompt, prompt).await
+    }
+
+    async fn generate_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        (**self).generate_with_logprob(system_prompt, prompt).await
+    }
+
+    async fn generate_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        (**self).generate_streaming(system_prompt, prompt, on_token).await
+    }
+
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        (**self).tokenizer()
+    }
+}
+
+#[async_trait]
+impl<G: ScriptGenerator + ?Sized> ScriptGenerator for Arc<G> {
+    async fn generate(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+        (**self).generate(system_prompt, prompt).await
+    }
+
+    async fn generate_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        (**self).generate_with_logprob(system_prompt, prompt).await
+    }
+
+    async fn generate_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        (**self).generate_streaming(system_prompt, prompt, on_token).await
+    }
+
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        (**self).tokenizer()
+    }
+}
+
+#[async_trait]
+impl<M: ModelBackend + ?Sized> ModelBackend for Arc<M> {
+    async fn chat(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+        (**self).chat(system_prompt, prompt).await
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        (**self).complete(prompt).await
+    }
+
+    async fn chat_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        (**self).chat_with_logprob(system_prompt, prompt).await
+    }
+
+    async fn complete_with_logprob(&self, prompt: &str) -> Result<Generation> {
+        (**self).complete_with_logprob(prompt).await
+    }
+
+    async fn chat_streaming(&self, system_prompt: &str, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        (**self).chat_streaming(system_prompt, prompt, on_token).await
+    }
+
+    async fn complete_streaming(&self, prompt: &str, on_token: &TokenCallback) -> Result<String> {
+        (**self).complete_streaming(prompt, on_token).await
+    }
+
+    fn tokenizer(&self) -> Option<Arc<dyn TextTokenizer>> {
+        (**self).tokenizer()
+    }
+}
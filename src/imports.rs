@@ -0,0 +1,256 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Modules a restricted script may import by default: enough to read stdin, match text and print
+/// JSON.
+pub const DEFAULT_IMPORT_ALLOWLIST: &[&str] = &["sys", "json", "re"];
+
+/// Modules and functions giving a script network or process access, banned by
+/// `with_banned_modules`.
+pub const DEFAULT_BANNED_MODULES: &[&str] = &["socket", "urllib", "http", "requests", "subprocess", "os.system", "os.popen"];
+
+/// Stands in for a module whose name is computed at runtime, e.g. `__import__(name)`.
+const DYNAMIC_IMPORT: &str = "<dynamic>";
+
+/// A module imported by a script, and the names the import binds to qualified module paths.
+struct Import {
+    module: String,
+    bindings: Vec<(String, String)>,
+    /// Imported by a call such as `__import__("x")`, so the module isn't bound to a name.
+    dynamic: bool,
+}
+
+/// One logical statement of a script, as Python's tokenizer sees it.
+pub(crate) struct Statement {
+    /// The statement's code, with string literals kept verbatim.
+    pub(crate) raw: String,
+    /// `raw` with the contents of string literals blanked out, byte for byte, so that text inside
+    /// strings is never mistaken for code.
+    pub(crate) code: String,
+}
+
+/// Splits `script` into logical statements: comments are dropped, backslash continuations and
+/// line breaks inside brackets are joined, and statements end at newlines and `;` outside
+/// brackets. String literals, including triple-quoted ones, are tracked throughout, so a `#` or
+/// `;` inside a string doesn't end the code before it.
+pub(crate) fn statements(script: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut raw = String::new();
+    let mut code = String::new();
+    let mut depth = 0usize;
+    let mut chars = script.chars().peekable();
+    let mut end_statement = |raw: &mut String, code: &mut String| {
+        if !code.trim().is_empty() {
+            statements.push(Statement { raw: std::mem::take(raw), code: std::mem::take(code) });
+        }
+        raw.clear();
+        code.clear();
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|&next| next != '\n').is_some() {},
+            '\\' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                raw.push(' ');
+                code.push(' ');
+            }
+            '\'' | '"' => {
+                let mut quotes = 1;
+                while quotes < 3 && chars.next_if_eq(&c).is_some() {
+                    quotes += 1;
+                }
+                let opening = c.to_string().repeat(quotes);
+                raw.push_str(&opening);
+                code.push_str(&opening);
+                if quotes == 2 {
+                    // An empty string literal.
+                    continue;
+                }
+                let mut closing = 0;
+                while closing < quotes {
+                    let Some(next) = chars.next() else { break };
+                    if next == '\n' && quotes == 1 {
+                        // An unterminated string; Python rejects it, so just end it here.
+                        end_statement(&mut raw, &mut code);
+                        break;
+                    }
+                    if next == c {
+                        closing += 1;
+                        raw.push(next);
+                        code.push(next);
+                        continue;
+                    }
+                    // Quotes that didn't close the literal are part of its contents.
+                    let blank = " ".repeat(closing);
+                    code.truncate(code.len() - closing);
+                    code.push_str(&blank);
+                    closing = 0;
+                    raw.push(next);
+                    code.push_str(&" ".repeat(next.len_utf8()));
+                    if next == '\\'
+                        && let Some(escaped) = chars.next()
+                    {
+                        raw.push(escaped);
+                        code.push_str(&" ".repeat(escaped.len_utf8()));
+                    }
+                }
+            }
+            '\n' if depth > 0 => {
+                raw.push(' ');
+                code.push(' ');
+            }
+            '\n' | ';' if depth == 0 => end_statement(&mut raw, &mut code),
+            _ => {
+                match c {
+                    '(' | '[' | '{' => depth += 1,
+                    ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                raw.push(c);
+                code.push(c);
+            }
+        }
+    }
+    end_statement(&mut raw, &mut code);
+    statements
+}
+
+/// Finds the imports in `script`: `import x`, `from x import y`, `__import__("x")` and
+/// `importlib.import_module("x")`, with any `as` aliases, including imports after a compound
+/// statement's colon such as `try: import x`. Comments and string contents are skipped. An
+/// import whose module name is computed, or a call to `exec`, `eval` or `compile`, which can
+/// run arbitrary imports, is reported as `<dynamic>`.
+fn imports(script: &str) -> Vec<Import> {
+    static IMPORT: OnceLock<Regex> = OnceLock::new();
+    static FROM_IMPORT: OnceLock<Regex> = OnceLock::new();
+    static DYNAMIC: OnceLock<Regex> = OnceLock::new();
+    static EVALUATION: OnceLock<Regex> = OnceLock::new();
+    let import = IMPORT.get_or_init(|| Regex::new(r"(?:^|:)\s*import\s+(.+)$").unwrap());
+    let from_import = FROM_IMPORT.get_or_init(|| Regex::new(r"(?:^|:)\s*from\s+(\S+)\s+import\b(.*)$").unwrap());
+    let dynamic =
+        DYNAMIC.get_or_init(|| Regex::new(r#"(?:__import__|import_module)\s*\(\s*(?:['"]([\w.]+)['"]\s*[,)])?"#).unwrap());
+    let evaluation = EVALUATION.get_or_init(|| Regex::new(r"(?:^|[^\w.])(?:exec|eval|compile)\s*\(").unwrap());
+
+    let mut imports = Vec::new();
+    for Statement { raw, code } in statements(script) {
+        if let Some(names) = import.captures(&code) {
+            for name in names[1].split(',') {
+                let mut words = name.split_whitespace();
+                let module = words.next().unwrap_or_default().to_string();
+                let binding = match (words.next(), words.next()) {
+                    (Some("as"), Some(alias)) => (alias.to_string(), module.clone()),
+                    _ => {
+                        let package = module.split('.').next().unwrap_or_default().to_string();
+                        (package.clone(), package)
+                    }
+                };
+                imports.push(Import { module, bindings: vec![binding], dynamic: false });
+            }
+        } else if let Some(captures) = from_import.captures(&code) {
+            let module = captures[1].to_string();
+            let bindings = captures[2]
+                .split(',')
+                .filter_map(|name| {
+                    let mut words = name.split(|c: char| c.is_whitespace() || c == '(' || c == ')').filter(|word| !word.is_empty());
+                    let name = words.next().filter(|name| *name != "*")?;
+                    let alias = match (words.next(), words.next()) {
+                        (Some("as"), Some(alias)) => alias,
+                        _ => name,
+                    };
+                    Some((alias.to_string(), format!("{}.{}", module, name)))
+                })
+                .collect();
+            imports.push(Import { module, bindings, dynamic: false });
+        }
+        for call in dynamic.captures_iter(&raw) {
+            let module = call.get(1).map_or(DYNAMIC_IMPORT, |name| name.as_str()).to_string();
+            imports.push(Import { module, bindings: Vec::new(), dynamic: true });
+        }
+        if evaluation.is_match(&code) {
+            imports.push(Import { module: DYNAMIC_IMPORT.to_string(), bindings: Vec::new(), dynamic: true });
+        }
+    }
+    imports
+}
+
+/// Lists the modules `script` imports that aren't in `allowlist`, in order of appearance. Covers
+/// `import x`, `from x import y`, `__import__("x")` and `importlib.import_module("x")`;
+/// submodules are judged by their top-level package, so allowing `os` allows `os.path`. Relative
+/// imports and dynamic imports with a computed name can't be checked and are always reported.
+pub(crate) fn disallowed_imports(script: &str, allowlist: &[String]) -> Vec<String> {
+    let mut disallowed: Vec<String> = Vec::new();
+    for Import { module, .. } in imports(script) {
+        let package = module.split('.').next().unwrap_or_default();
+        if !allowlist.iter().any(|allowed| allowed == package) && !disallowed.contains(&module) {
+            disallowed.push(module);
+        }
+    }
+    disallowed
+}
+
+/// Lists the entries of `banned` that `script` uses, in order of appearance, however they're
+/// imported: directly, from their parent package, under an alias, or through an aliased parent,
+/// e.g. `import os as o; o.system(...)` uses `os.system`. An entry bans its submodules and
+/// attributes too, so `urllib` covers `urllib.request.urlopen`. Dynamic imports with a computed
+/// name, `exec`/`eval`/`compile` calls, and lookups that could reach a banned entry without
+/// naming it, such as `getattr(os, name)` or `__import__("os")`, are reported as `<dynamic>`,
+/// since they could load anything. A use already covered by a reported name, like
+/// `urllib.request` after `urllib`, isn't reported again.
+pub(crate) fn banned_uses(script: &str, banned: &[String]) -> Vec<String> {
+    static NAME_PATH: OnceLock<Regex> = OnceLock::new();
+    static GETATTR: OnceLock<Regex> = OnceLock::new();
+    let name_path = NAME_PATH.get_or_init(|| Regex::new(r"[A-Za-z_]\w*(?:\s*\.\s*[A-Za-z_]\w*)*").unwrap());
+    let getattr = GETATTR.get_or_init(|| {
+        Regex::new(r#"getattr\s*\(\s*([A-Za-z_]\w*(?:\s*\.\s*[A-Za-z_]\w*)*)\s*,\s*(?:['"](\w+)['"]\s*[,)])?"#).unwrap()
+    });
+    let is_banned = |name: &str| {
+        name == DYNAMIC_IMPORT
+            || banned.iter().any(|entry| name == entry || name.strip_prefix(entry.as_str()).is_some_and(|rest| rest.starts_with('.')))
+    };
+    // Whether a module's attributes include a banned entry, like `os` for `os.system`.
+    let leads_to_banned = |name: &str| {
+        banned.iter().any(|entry| entry.strip_prefix(name).is_some_and(|rest| rest.starts_with('.')))
+    };
+
+    let mut used: Vec<String> = Vec::new();
+    let mut found = |name: String| {
+        let covered = used.iter().any(|seen| name == *seen || name.strip_prefix(seen.as_str()).is_some_and(|rest| rest.starts_with('.')));
+        if is_banned(&name) && !covered {
+            used.push(name);
+        }
+    };
+    let mut bindings: HashMap<String, String> = HashMap::new();
+    for import in imports(script) {
+        if import.dynamic && leads_to_banned(&import.module) {
+            found(DYNAMIC_IMPORT.to_string());
+        }
+        found(import.module);
+        for (alias, qualified) in import.bindings {
+            found(qualified.clone());
+            bindings.insert(alias, qualified);
+        }
+    }
+    let resolve = |path: &str| {
+        let path: String = path.split_whitespace().collect();
+        let (head, rest) = path.split_once('.').map_or((path.as_str(), None), |(head, rest)| (head, Some(rest)));
+        let qualified = bindings.get(head)?;
+        Some(rest.map_or_else(|| qualified.clone(), |rest| format!("{}.{}", qualified, rest)))
+    };
+    for Statement { raw, code } in statements(script) {
+        for path in name_path.find_iter(&code) {
+            if let Some(qualified) = resolve(path.as_str()) {
+                found(qualified);
+            }
+        }
+        for lookup in getattr.captures_iter(&raw) {
+            let Some(qualified) = resolve(&lookup[1]) else { continue };
+            match lookup.get(2) {
+                Some(attribute) => found(format!("{}.{}", qualified, attribute.as_str())),
+                None if leads_to_banned(&qualified) => found(DYNAMIC_IMPORT.to_string()),
+                None => {}
+            }
+        }
+    }
+    used
+}
@@ -0,0 +1,129 @@
+/// A language the model can be asked to write parsing scripts in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ScriptLanguage {
+    /// Run with `python3 -c` unless another interpreter is configured.
+    #[default]
+    Python,
+    /// Run with `node -e`.
+    JavaScript,
+}
+
+/// How a subprocess receives the document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputMode {
+    /// Piped to the script's standard input.
+    #[default]
+    Stdin,
+    /// Written to a temporary file whose path is the script's first argument, which avoids
+    /// pushing multi-megabyte documents through a pipe. The file is deleted after the run.
+    TempFile,
+}
+
+impl ScriptLanguage {
+    /// Human-readable name used in prompts.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ScriptLanguage::Python => "Python",
+            ScriptLanguage::JavaScript => "JavaScript",
+        }
+    }
+
+    /// Markdown fence tags the model may use around code in this language.
+    pub(crate) fn fence_tags(self) -> &'static [&'static str] {
+        match self {
+            ScriptLanguage::Python => &["python", "py"],
+            ScriptLanguage::JavaScript => &["javascript", "js"],
+        }
+    }
+
+    /// Default program and inline-source flag used to run a script.
+    pub(crate) fn command(self) -> (&'static str, &'static str) {
+        match self {
+            ScriptLanguage::Python => (crate::DEFAULT_INTERPRETER, "-c"),
+            ScriptLanguage::JavaScript => ("node", "-e"),
+        }
+    }
+
+    /// Whether a model response is an explanation rather than code: it has none of the statements
+    /// typical of this language and at least one sentence-like line (five or more words ending in
+    /// `.`, `!` or `?`) outside comments. Bare snippets relying on a preamble still count as code.
+    pub(crate) fn is_prose(self, response: &str) -> bool {
+        let (markers, comment): (&[&str], &str) = match self {
+            ScriptLanguage::Python => (&["import ", "def ", "print(", "sys.stdin", "json."], "#"),
+            ScriptLanguage::JavaScript => (&["require(", "console.log", "process.stdin", "function", "=>", "JSON."], "//"),
+        };
+        if markers.iter().any(|marker| response.contains(marker)) {
+            return false;
+        }
+        response.lines().map(str::trim).any(|line| {
+            !line.starts_with(comment)
+                && line.split_whitespace().count() >= 5
+                && line.ends_with(['.', '!', '?'])
+        })
+    }
+
+    /// System prompt asking for a standalone script in this language reading its input as `input`.
+    pub(crate) fn system_prompt(self, input: InputMode) -> &'static str {
+        match (self, input) {
+            (ScriptLanguage::Python, InputMode::Stdin) => PYTHON_SYSTEM_PROMPT,
+            (ScriptLanguage::Python, InputMode::TempFile) => PYTHON_FILE_SYSTEM_PROMPT,
+            (ScriptLanguage::JavaScript, InputMode::Stdin) => JAVASCRIPT_SYSTEM_PROMPT,
+            (ScriptLanguage::JavaScript, InputMode::TempFile) => JAVASCRIPT_FILE_SYSTEM_PROMPT,
+        }
+    }
+}
+
+/// Expands to a system prompt whose first rule, describing how the script gets the document, is `$input`.
+macro_rules! python_system_prompt {
+    ($input:literal) => {
+        concat!(r#"
+You are an expert Python programmer that creates parsing scripts. Your task is to write a single, complete Python script based on the user's request.
+
+CRITICAL RULES:
+1. "#, $input, r#"
+2. The script must print a single, valid JSON object to standard output (stdout).
+3. The script MUST NOT use any external libraries like BeautifulSoup. Use only standard libraries like `sys`, `json`, and `re`.
+4. Your output must be ONLY the raw Python code. Do not include explanations, markdown, or code blocks.
+5. Always include proper error handling to avoid crashes.
+6. If you cannot find the requested data, return an empty JSON object {} rather than failing.
+7. Make sure your JSON output is properly formatted and valid.
+
+If this is a retry attempt, learn from the previous errors and fix them in your new script.
+"#)
+    };
+}
+
+const PYTHON_SYSTEM_PROMPT: &str =
+    python_system_prompt!("The script you write will receive the raw document text via standard input (stdin).");
+
+const PYTHON_FILE_SYSTEM_PROMPT: &str = python_system_prompt!(
+    "The script you write will receive the path of a file holding the raw document text as its first command-line argument; read the document with `open(sys.argv[1], encoding='utf-8').read()`, not from stdin."
+);
+
+/// Like `python_system_prompt`, for Node.js scripts.
+macro_rules! javascript_system_prompt {
+    ($input:literal) => {
+        concat!(r#"
+You are an expert JavaScript programmer that creates parsing scripts for Node.js. Your task is to write a single, complete script based on the user's request.
+
+CRITICAL RULES:
+1. "#, $input, r#"
+2. The script must print a single, valid JSON object to standard output (stdout) using `console.log(JSON.stringify(...))`.
+3. The script MUST NOT use any npm packages. Use only Node.js built-in modules.
+4. Your output must be ONLY the raw JavaScript code. Do not include explanations, markdown, or code blocks.
+5. Always include proper error handling to avoid crashes.
+6. If you cannot find the requested data, return an empty JSON object {} rather than failing.
+7. Make sure your JSON output is properly formatted and valid.
+
+If this is a retry attempt, learn from the previous errors and fix them in your new script.
+"#)
+    };
+}
+
+const JAVASCRIPT_SYSTEM_PROMPT: &str = javascript_system_prompt!(
+    "The script you write will receive the raw document text via standard input (stdin); read it with `require('fs').readFileSync(0, 'utf8')`."
+);
+
+const JAVASCRIPT_FILE_SYSTEM_PROMPT: &str = javascript_system_prompt!(
+    "The script you write will receive the path of a file holding the raw document text as its first command-line argument; read the document with `require('fs').readFileSync(process.argv[1], 'utf8')`, not from stdin."
+);
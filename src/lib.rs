@@ -1,106 +1,776 @@
-use anyhow::Result;
-use kalosm::language::*;
+use anyhow::{Result, anyhow};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use tokio::io::AsyncWriteExt;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command;
 use tracing::{info, warn, error, debug, trace};
-use std::time::Instant;
+use tracing::Instrument;
+use tracing::instrument::WithSubscriber;
+use std::time::{Duration, Instant};
 
-/// Maximum number of retry attempts for script generation and execution
+mod backoff;
+mod batch;
+mod benchmark;
+#[cfg(feature = "blocking")]
+mod blocking;
+mod builder;
+mod cache;
+mod candidates;
+mod cassette;
+mod compat;
+mod compiled;
+mod composite;
+mod diff;
+mod ensemble;
+mod error;
+mod event;
+mod executor;
+mod generator;
+mod imports;
+mod language;
+mod limit;
+mod logging;
+mod metrics;
+mod output;
+mod pagination;
+mod params;
+mod pipeline;
+mod prompt;
+mod quantity;
+#[cfg(feature = "readability")]
+mod readability;
+mod report;
+mod rlimit;
+mod schema;
+mod session;
+mod shadow;
+mod shape;
+mod streaming;
+mod temp;
+pub mod testing;
+mod tokenizer;
+mod transcript;
+
+pub use benchmark::{BenchmarkReport, LatencyStats};
+pub use candidates::completeness;
+pub use compiled::CompiledParser;
+pub use builder::{ENV_MAX_RETRIES, ENV_PYTHON_PATH, ENV_SCRIPT_TIMEOUT_SECS, ParserClientBuilder};
+pub use diff::{JsonChange, JsonDiff, json_approx_eq};
+pub use ensemble::TieBreak;
+pub use error::{FailureCategory, ParseError};
+pub use event::{ParseEvent, ParseMetadata};
+pub use executor::{ScriptExecutor, ScriptOutput};
+pub use imports::{DEFAULT_BANNED_MODULES, DEFAULT_IMPORT_ALLOWLIST};
+pub use generator::{Generation, LlamaGenerator, ModelBackend, ModelInterface, ScriptGenerator, TokenCallback, completion_prompt};
+pub use kalosm::language::LlamaSource;
+pub use language::{InputMode, ScriptLanguage};
+pub use limit::{clear_global_subprocess_limit, set_global_subprocess_limit};
+pub use metrics::{AttemptMetrics, ParseMetrics};
+pub use output::{Normalization, OutputFormat, ParseOutcome, Serialization};
+pub use pipeline::ParsePipeline;
+pub use prompt::{BinaryMode, strip_html_tags};
+pub use quantity::Quantity;
+pub use report::{AttemptReport, FailureReport};
+pub use session::ParseSession;
+pub use shadow::ShadowComparison;
+pub use tokenizer::TextTokenizer;
+pub use transcript::{ChatRole, ChatTranscript, ChatTurn};
+
+/// Default maximum number of retry attempts for script generation and execution
 const MAX_RETRIES: usize = 10;
 
+/// Interpreter used to run generated scripts when none is configured
+const DEFAULT_INTERPRETER: &str = "python3";
+
+/// Prompt excerpt size used with the built-in model, whose 2048-token context a larger document
+/// would overflow before the script is written
+const DEFAULT_PROMPT_DOCUMENT_CHARS: usize = 3000;
+
+/// Size of the chunks the document is written to a script's stdin in
+const STDIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Default cap on how much of a script's stderr is kept
+const MAX_STDERR_BYTES: usize = 64 * 1024;
+
+/// Exit status used by the inline timeout guard, matching coreutils `timeout`
+const INLINE_TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Callback receiving the total number of document bytes written to stdin so far
+type StdinProgress = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// Decides whether two JSON results should be considered equal
+type JsonComparator = Arc<dyn Fn(&serde_json::Value, &serde_json::Value) -> bool + Send + Sync>;
+
+/// Scores a candidate result; higher is better
+type CandidateScorer = Arc<dyn Fn(&serde_json::Value) -> f64 + Send + Sync>;
+
+/// Receives the full model conversation at the end of each parse
+type TranscriptSink = Arc<dyn Fn(&ChatTranscript) + Send + Sync>;
+
+/// Receives progress events while a parse runs
+type EventCallback = Arc<dyn Fn(&ParseEvent) + Send + Sync>;
+
+/// Receives the model's response token by token as it's generated
+type TokenSink = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Receives each attempt as soon as it finishes
+type AttemptCallback = Arc<dyn Fn(&ParseAttempt) + Send + Sync>;
+
+/// Rewrites the instructions for a given attempt number
+type InstructionRephraser = Arc<dyn Fn(&str, usize) -> String + Send + Sync>;
+
+/// Checks a JSON result against a domain invariant, explaining any violation
+type OutputValidator = Arc<dyn Fn(&str) -> std::result::Result<(), String> + Send + Sync>;
+
+/// Rewrites a document before it's shown to the model
+type DocumentPreprocessor = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Decides whether a failed attempt is worth another one
+type RetryPredicate = Arc<dyn Fn(&ParseError) -> bool + Send + Sync>;
+
+/// Accepts a result or explains why it was rejected
+type OutputCheck = fn(&serde_json::Value) -> std::result::Result<(), String>;
+
+/// System prompt used when asking the model to check a result
+const VERIFY_SYSTEM_PROMPT: &str = "You check the output of a data extraction script. Answer with a single word: yes if the output correctly and completely satisfies the instructions for the document, no if it doesn't.";
+
+/// System prompt used when asking the model to describe a script
+const EXPLAIN_SYSTEM_PROMPT: &str = "You are an expert Python reviewer. Summarize what a script does for a reader who will decide whether to trust it. Mention what input it reads, what it extracts, and what it prints. Do not rewrite the script.";
+
 /// A client that holds the AI model for dynamically generating parsing scripts.
 pub struct ParserClient {
-    model: Llama,
+    generator: Box<dyn ScriptGenerator>,
+    tokenizer: Arc<dyn TextTokenizer>,
+    interpreter: PathBuf,
+    max_retries: usize,
+    confidence_threshold: Option<f32>,
+    serialization: Serialization,
+    error_key: Option<String>,
+    binary_prompt_mode: Option<BinaryMode>,
+    tie_break: TieBreak,
+    max_valid_json_attempts: Option<usize>,
+    stdin_progress: Option<StdinProgress>,
+    json_comparator: Option<JsonComparator>,
+    script_preamble: Option<String>,
+    max_stderr_bytes: usize,
+    executor: Option<Box<dyn ScriptExecutor>>,
+    instruction_rephraser: Option<InstructionRephraser>,
+    inline_timeout: Option<Duration>,
+    output_example: Option<serde_json::Value>,
+    python_version: Option<(u8, u8)>,
+    language_fallback: Vec<ScriptLanguage>,
+    language_executors: HashMap<ScriptLanguage, Box<dyn ScriptExecutor>>,
+    log_sampling: Option<f64>,
+    structure_reference: Option<String>,
+    field_patterns: HashMap<String, regex::Regex>,
+    deduplicate_output: bool,
+    dedup_key: Option<String>,
+    min_output_bytes: Option<usize>,
+    normalize_line_endings: bool,
+    event_callback: Option<EventCallback>,
+    allow_trailing_data: bool,
+    script_progress: bool,
+    candidate_scorer: Option<CandidateScorer>,
+    candidate_score_threshold: Option<f64>,
+    transcript_sink: Option<TranscriptSink>,
+    prompt_document_chars: Option<usize>,
+    /// Whether failed scripts widen the prompt excerpt; off for the built-in model's default
+    /// excerpt size, which exists to fit its context window.
+    widen_prompt_excerpt: bool,
+    max_json_depth: Option<usize>,
+    model_error_cooldown: Option<Duration>,
+    unicode_normalization: Option<Normalization>,
+    import_allowlist: Option<Vec<String>>,
+    attempt_callback: Option<AttemptCallback>,
+    script_cache: Option<cache::ScriptCache>,
+    input_mode: InputMode,
+    resource_limits: rlimit::ResourceLimits,
+    token_sink: Option<TokenSink>,
+    system_prompt: Option<String>,
+    system_rules: Vec<String>,
+    retry_backoff: Option<backoff::Backoff>,
+    validators: Vec<OutputValidator>,
+    total_deadline: Option<Duration>,
+    output_format: OutputFormat,
+    self_verification: bool,
+    preprocessor: Option<DocumentPreprocessor>,
+    retry_predicate: Option<RetryPredicate>,
+    banned_modules: Option<Vec<String>>,
+    /// Caps concurrent subprocesses in place of `GLOBAL_SUBPROCESS_LIMIT`, e.g. in tests.
+    subprocess_limit: Option<Arc<limit::SubprocessLimit>>,
+    shadow: Option<(Arc<ParserClient>, shadow::ShadowCallback)>,
+    #[cfg(feature = "readability")]
+    readability: bool,
+}
+
+/// Per-call overrides of the client's configuration.
+#[derive(Default)]
+struct CallOptions<'a> {
+    interpreter: Option<&'a Path>,
+    serialization: Option<Serialization>,
+    /// Validates output against this structure instead of the client's output example.
+    output_example: Option<&'a serde_json::Value>,
+    /// Caller metadata recorded on the parse's tracing span and included in its events.
+    metadata: ParseMetadata,
+    max_retries: Option<usize>,
+    /// A final check on the result, whose error is fed back to the model like any rejection.
+    output_check: Option<OutputCheck>,
+    /// A JSON Schema shown to the model and enforced on every result.
+    schema: Option<&'a serde_json::Value>,
+    /// Script parameters, as sanitized environment variables.
+    env: Vec<(String, String)>,
+    /// Accepts object keys missing from the output example, e.g. ones serde ignores for the target type.
+    allow_extra_keys: bool,
+}
+
+/// Per-call overrides for `dynamic_parse_with_options`. Unset fields use the client's configuration.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Maximum generate/execute attempts for this call. Zero fails immediately with
+    /// `ParseError::RetriesExhausted`.
+    pub max_retries: Option<usize>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParseAttempt {
     attempt_number: usize,
     script: String,
+    /// The user prompt sent to the model.
+    prompt: String,
+    /// The model's raw response, or `None` if generation failed.
+    response: Option<String>,
     error: Option<String>,
     success: bool,
+    logprob: Option<f32>,
+    failure_category: Option<FailureCategory>,
+    generation_time: Duration,
+    execution_time: Option<Duration>,
+    language: ScriptLanguage,
+    command: Option<String>,
+    stderr: Option<String>,
+    verified: Option<bool>,
+}
+
+impl ParseAttempt {
+    /// The 1-based position of this attempt within its parse.
+    pub fn attempt_number(&self) -> usize {
+        self.attempt_number
+    }
+
+    /// The script the model wrote, or an empty string if generation failed.
+    pub fn script(&self) -> &str {
+        &self.script
+    }
+
+    /// Why this attempt failed, as fed back to the model, or `None` if it succeeded.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Whether this attempt produced the parse's result.
+    pub fn succeeded(&self) -> bool {
+        self.success
+    }
+
+    /// Why this attempt failed, or `None` if it succeeded.
+    pub fn failure_category(&self) -> Option<FailureCategory> {
+        self.failure_category
+    }
+
+    /// The language the attempt's script was written in.
+    pub fn language(&self) -> ScriptLanguage {
+        self.language
+    }
+
+    /// A shell-escaped command line reproducing the subprocess that ran the script, or `None` if
+    /// no script ran or a custom executor ran it. The document is read from a file named `document`.
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    /// What the script printed on stderr, e.g. deprecation warnings from a script that otherwise
+    /// succeeded, or `None` if no script ran or it was killed before exiting.
+    pub fn stderr(&self) -> Option<&str> {
+        self.stderr.as_deref()
+    }
+
+    /// The model's verdict on whether the output satisfies the instructions, with
+    /// `with_self_verification`. `None` if the output wasn't checked or the answer was unclear.
+    pub fn verified(&self) -> Option<bool> {
+        self.verified
+    }
+}
+
+/// Returns the script generated for each attempt, in order, e.g. for a side-by-side diff view.
+/// Attempts whose generation failed contribute an empty string.
+pub fn scripts(attempts: &[ParseAttempt]) -> Vec<&str> {
+    attempts.iter().map(|attempt| attempt.script.as_str()).collect()
 }
 
 impl ParserClient {
     /// Creates a new `ParserClient` and loads the AI model.
     pub async fn new() -> Result<Self> {
-        let start_time = Instant::now();
-        info!("Starting ParserClient initialization...");
-        info!("Using TinyLlama 1.1B Chat model for faster performance");
-        
-        debug!("Building Llama model with TinyLlama source...");
-        let model = Llama::builder()
-            .with_source(LlamaSource::tiny_llama_1_1b_chat()) // Use the chat version which has correct URL format
-            .build()
-            .await?;
-        
-        let elapsed = start_time.elapsed();
-        info!("✅ ParserClient initialized successfully in {:.2}s", elapsed.as_secs_f64());
-        
-        Ok(Self { model })
+        Self::builder().build().await
+    }
+
+    /// Returns a builder for configuring a `ParserClient`.
+    pub fn builder() -> ParserClientBuilder {
+        ParserClientBuilder::default()
+    }
+
+    /// Creates a `ParserClient` that writes scripts with `generator`, e.g. a remote model, instead
+    /// of loading the default model. Use `builder().with_generator(...)` to configure more.
+    pub async fn with_generator(generator: Box<dyn ScriptGenerator>) -> Result<Self> {
+        Self::builder().with_generator(generator).build().await
     }
 
     /// Dynamically parses a document using an AI-generated Python script with retry logic.
-    pub async fn dynamic_parse(&self, document: &str, instructions: &str) -> Result<String> {
-        let overall_start = Instant::now();
+    ///
+    /// Every attempt is generated in a fresh conversation with the model, not as a follow-up
+    /// message in one long chat. Retries still see what went wrong: each retry prompt lists the
+    /// earlier attempts' scripts and errors. This keeps prompts within small context windows and
+    /// lets any `ScriptGenerator` backend, including stateless HTTP APIs, drive the retry loop.
+    pub async fn dynamic_parse(&self, document: &str, instructions: &str) -> Result<String, ParseError> {
         info!("🔄 Starting dynamic parse operation");
+        Ok(self.parse_shadowed(document, instructions).await?)
+    }
+
+    /// Like `dynamic_parse`, but with per-call overrides of the client's configuration, e.g. more
+    /// retries for an expensive document.
+    pub async fn dynamic_parse_with_options(&self, document: &str, instructions: &str, parse_options: &ParseOptions) -> Result<String, ParseError> {
+        info!("🔄 Starting dynamic parse operation with options: {:?}", parse_options);
+        let options = CallOptions {
+            max_retries: parse_options.max_retries,
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        Ok(result)
+    }
+
+    /// Like `dynamic_parse`, but returns the validated output as `Bytes`, ready to hand to an HTTP
+    /// body without copying.
+    pub async fn dynamic_parse_bytes(&self, document: &str, instructions: &str) -> Result<bytes::Bytes, ParseError> {
+        let result = self.dynamic_parse(document, instructions).await?;
+        Ok(bytes::Bytes::from(result))
+    }
+
+    /// Like `dynamic_parse`, but runs the generated scripts with `interpreter` instead of the
+    /// client's default for this call only.
+    pub async fn dynamic_parse_with_interpreter(&self, document: &str, instructions: &str, interpreter: &str) -> Result<String, ParseError> {
+        info!("🔄 Starting dynamic parse operation with interpreter override: {}", interpreter);
+        let options = CallOptions {
+            interpreter: Some(Path::new(interpreter)),
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        Ok(result)
+    }
+
+    /// Like `dynamic_parse`, but classifies the result as a `ParseOutcome` so callers can `match`
+    /// on empty matches, best-effort results and total failures.
+    pub async fn dynamic_parse_outcome(&self, document: &str, instructions: &str) -> ParseOutcome {
+        info!("🔄 Starting dynamic parse operation with outcome");
+        let (result, attempts) = self.run_attempts(document, instructions, &CallOptions::default()).await;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                debug!("Parse failed: {}", e);
+                return ParseOutcome::FailedAllRetries(attempts);
+            }
+        };
+
+        if attempts.last().is_some_and(|attempt| !attempt.success) {
+            return ParseOutcome::BestEffort(result);
+        }
+        match output::parse_value(&result, self.serialization) {
+            Ok(serde_json::Value::Object(map)) if map.is_empty() => ParseOutcome::EmptyMatch,
+            Ok(serde_json::Value::Array(items)) if items.is_empty() => ParseOutcome::EmptyMatch,
+            _ => ParseOutcome::Success(result),
+        }
+    }
+
+    /// Encodes `text` with the model's tokenizer, loaded once when the client was built. Clients
+    /// whose generator has no tokenizer use a deterministic approximation instead.
+    pub fn tokenize(&self, text: &str) -> Vec<u32> {
+        self.tokenizer.tokenize(text)
+    }
+
+    /// The number of tokens `text` occupies, as counted by `tokenize`.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.tokenize(text).len()
+    }
+
+    /// Asks the model to summarize in plain English what `script` does, for review before trusting it.
+    pub async fn explain_script(&self, script: &str) -> Result<String> {
+        info!("📖 Requesting explanation for a {} character script", script.len());
+        let prompt = format!(
+            "Explain what the following Python script does, step by step, in plain English.\n\n```python\n{}\n```",
+            script
+        );
+        let explanation = self.generator.generate(EXPLAIN_SYSTEM_PROMPT, &prompt).await?;
+        let explanation = explanation.trim();
+        if explanation.is_empty() {
+            anyhow::bail!("Model returned an empty explanation");
+        }
+        debug!("Explanation length: {} characters", explanation.len());
+        Ok(explanation.to_string())
+    }
+
+    /// Asks the model whether `result` satisfies `instructions` for `document`, turning a "no"
+    /// into a rejection. Returns the verdict, or `None` if the model failed or gave no clear answer,
+    /// in which case the result is accepted.
+    async fn self_check(&self, document: &str, instructions: &str, result: String) -> (Result<String>, Option<bool>) {
+        info!("🔎 Asking the model to verify the output");
+        let prompt = format!(
+            "**Instructions:**\n{}\n\n**Document:**\n---\n{}\n---\n\n**Output:**\n{}\n\nDoes this output satisfy the instructions for this document? Answer yes or no.",
+            instructions,
+            self.prompt_excerpt(document, 0),
+            result.trim()
+        );
+        let answer = match self.generator.generate(VERIFY_SYSTEM_PROMPT, &prompt).await {
+            Ok(answer) => answer,
+            Err(e) => {
+                warn!("Self-verification failed, accepting the output: {}", e);
+                return (Ok(result), None);
+            }
+        };
+        let verdict = answer.split(|c: char| !c.is_alphanumeric()).find(|word| !word.is_empty()).map(str::to_lowercase);
+        match verdict.as_deref() {
+            Some("yes") => (Ok(result), Some(true)),
+            Some("no") => {
+                warn!("🙅 Model judged the output wrong: {}", answer.trim());
+                let reason = format!(
+                    "Self-check failed: the output does not satisfy the instructions for this document ({}). Check for empty values and wrong fields.",
+                    answer.trim()
+                );
+                (Err(ParseError::OutputRejected(reason).into()), Some(false))
+            }
+            _ => {
+                warn!("Unclear self-verification answer, accepting the output: {}", answer.trim());
+                (Ok(result), None)
+            }
+        }
+    }
+
+    /// Parses a canonical "golden" document and checks the result equals `expected` (per the
+    /// configured JSON comparator), for regression testing after changing instructions or models.
+    /// On mismatch the error lists every differing path.
+    pub async fn assert_golden(&self, document: &str, instructions: &str, expected: &serde_json::Value) -> Result<()> {
+        info!("🏅 Checking golden document against expected result");
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        let actual: serde_json::Value = serde_json::from_str(&result)?;
+
+        if !self.json_equal(expected, &actual) {
+            let diff = JsonDiff::between(expected, &actual);
+            warn!("Golden mismatch with {} differences", diff.changes.len());
+            anyhow::bail!(
+                "Golden result mismatch ({} differences, '-' expected only, '+' actual only):\n{}\nExpected: {}\nActual: {}",
+                diff.changes.len(),
+                diff,
+                expected,
+                actual
+            );
+        }
+        info!("✅ Golden result matches");
+        Ok(())
+    }
+
+    /// Compares two results with the configured JSON comparator, or exact equality when unset.
+    fn json_equal(&self, a: &serde_json::Value, b: &serde_json::Value) -> bool {
+        match &self.json_comparator {
+            Some(comparator) => comparator(a, b),
+            None => a == b,
+        }
+    }
+
+    /// Runs the generate/execute retry loop, returning the result together with every attempt made.
+    async fn parse_with_attempts(&self, document: &str, instructions: &str, options: &CallOptions<'_>) -> Result<(String, Vec<ParseAttempt>)> {
+        let (result, attempts) = self.run_attempts(document, instructions, options).await;
+        result.map(|result| (result, attempts))
+    }
+
+    /// Runs the retry loop, suppressing its info/debug/trace events unless this parse is sampled.
+    async fn run_attempts(&self, document: &str, instructions: &str, options: &CallOptions<'_>) -> (Result<String>, Vec<ParseAttempt>) {
+        let document = &*self.prepare_document(document);
+        let span = tracing::info_span!("dynamic_parse", metadata = %event::format_metadata(&options.metadata));
+        let attempts = self.attempt_loop(document, instructions, options).instrument(span);
+        let (result, attempts) = match self.log_sampling {
+            Some(rate) if !logging::sampled(rate) => attempts.with_subscriber(logging::WarningsOnly::wrap_current()).await,
+            _ => attempts.await,
+        };
+        if let Some(sink) = &self.transcript_sink {
+            sink(&ChatTranscript::from_attempts(&attempts, |attempt| self.get_system_prompt(attempt.language)));
+        }
+        (result, attempts)
+    }
+
+    /// Whether a failed attempt should be followed by another, per the retry predicate. Failures
+    /// that aren't a `ParseError` are always retried.
+    fn should_retry(&self, error: &anyhow::Error) -> bool {
+        match (error.downcast_ref::<ParseError>(), &self.retry_predicate) {
+            (None, _) => true,
+            (Some(error), Some(predicate)) => predicate(error),
+            (Some(error), None) => !matches!(error, ParseError::InterpreterNotFound { .. }),
+        }
+    }
+
+    /// Records a finished attempt and notifies the event callback.
+    fn record_attempt(&self, attempts: &mut Vec<ParseAttempt>, options: &CallOptions<'_>, attempt: ParseAttempt) {
+        self.emit(ParseEvent::AttemptFinished {
+            attempt: attempt.attempt_number,
+            success: attempt.success,
+            metadata: options.metadata.clone(),
+        });
+        if let Some(callback) = &self.attempt_callback {
+            callback(&attempt);
+        }
+        attempts.push(attempt);
+    }
+
+    /// Forgets every script remembered by `with_script_cache`.
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.script_cache {
+            cache.clear();
+        }
+    }
+
+    fn emit(&self, event: ParseEvent) {
+        if let Some(callback) = &self.event_callback {
+            callback(&event);
+        }
+    }
+
+    /// The generate/execute retry loop. Attempts are returned whether or not the parse succeeded.
+    async fn attempt_loop(&self, document: &str, instructions: &str, options: &CallOptions<'_>) -> (Result<String>, Vec<ParseAttempt>) {
+        let overall_start = Instant::now();
         info!("📄 Document length: {} characters", document.len());
         info!("📝 Instructions: {}", instructions);
         
-        debug!("Creating chat session with system prompt...");
-        let mut chat = self.model.chat().with_system_prompt(self.get_system_prompt());
+        let interpreter = options.interpreter.unwrap_or(&self.interpreter);
+        let max_retries = options.max_retries.unwrap_or(self.max_retries);
         let mut attempts: Vec<ParseAttempt> = Vec::new();
+        let mut low_confidence_result: Option<String> = None;
+        let mut rejected_outputs = 0;
+
+        let deadline = self.total_deadline.map(|limit| (limit, tokio::time::Instant::from_std(overall_start + limit)));
+        let cache_key = cache::ScriptCache::key(instructions, document);
+        if let Some(cache) = &self.script_cache
+            && let Some((script, language)) = cache.get(cache_key)
+            && max_retries > 0
+        {
+            info!("📦 Reusing cached script");
+            let exec_start = Instant::now();
+            let executable_script = self.prepare_script(&script, language);
+            let run = self.run_and_finalize(&executable_script, document, interpreter, language, options);
+            let Some((outcome, stderr)) = within_deadline(deadline, run).await else {
+                return (Err(deadline_exceeded(deadline, &attempts)), attempts);
+            };
+            match outcome {
+                Ok(result) => {
+                    self.record_attempt(&mut attempts, options, ParseAttempt {
+                        attempt_number: 1,
+                        script,
+                        prompt: String::new(),
+                        response: None,
+                        error: None,
+                        success: true,
+                        logprob: None,
+                        failure_category: None,
+                        generation_time: Duration::ZERO,
+                        execution_time: Some(exec_start.elapsed()),
+                        language,
+                        command: self.executor_for(language).is_none().then(|| shell_command_line(&executable_script, interpreter, language, self.input_mode, &options.env)),
+                        stderr,
+                        verified: None,
+                    });
+                    return (Ok(result), attempts);
+                }
+                Err(e) => {
+                    warn!("Cached script failed, generating a new one: {}", e);
+                    cache.remove(cache_key);
+                }
+            }
+        }
         
-        for attempt in 1..=MAX_RETRIES {
+        for attempt in 1..=max_retries {
+            if let Some((limit, _)) = deadline
+                && overall_start.elapsed() >= limit
+            {
+                error!("⏰ Parse deadline of {:.2}s passed before attempt {}", limit.as_secs_f64(), attempt);
+                return (low_confidence_result.ok_or_else(|| deadline_exceeded(deadline, &attempts)), attempts);
+            }
+            if let Some(backoff) = self.retry_backoff
+                && attempt > 1
+            {
+                let delay = backoff.delay(attempt - 1);
+                info!("⏳ Backing off for {:.2}s before attempt {}", delay.as_secs_f64(), attempt);
+                if within_deadline(deadline, tokio::time::sleep(delay)).await.is_none() {
+                    return (low_confidence_result.ok_or_else(|| deadline_exceeded(deadline, &attempts)), attempts);
+                }
+            }
             let attempt_start = Instant::now();
-            info!("🎯 Parsing attempt {}/{}", attempt, MAX_RETRIES);
+            let language = self.language_for(attempt);
+            info!("🎯 Parsing attempt {}/{} ({})", attempt, max_retries, language.name());
+            self.emit(ParseEvent::AttemptStarted { attempt, metadata: options.metadata.clone() });
             
             debug!("Building user prompt for attempt {}...", attempt);
-            let user_prompt = self.build_user_prompt(document, instructions, &attempts, attempt);
+            let attempt_instructions = match &self.instruction_rephraser {
+                Some(rephrase) => rephrase(instructions, attempt),
+                None => instructions.to_string(),
+            };
+            let user_prompt = self.build_user_prompt(document, &attempt_instructions, &attempts, attempt, language, options);
             trace!("User prompt length: {} characters", user_prompt.len());
             
             // Generate the script
-            info!("🤖 Generating Python script with AI model...");
+            info!("🤖 Generating {} script with AI model...", language.name());
             let script_gen_start = Instant::now();
-            let raw_script = match chat.add_message(&user_prompt).await {
-                Ok(script) => {
-                    let gen_elapsed = script_gen_start.elapsed();
+            let system_prompt = self.get_system_prompt(language);
+            let generation = async {
+                match &self.token_sink {
+                    Some(sink) => self
+                        .generator
+                        .generate_streaming(&system_prompt, &user_prompt, &**sink)
+                        .await
+                        .map(|text| Generation { text, logprob: None }),
+                    None => self.generator.generate_with_logprob(&system_prompt, &user_prompt).await,
+                }
+            };
+            let Some(generated) = within_deadline(deadline, generation).await else {
+                error!("⏰ Parse deadline passed while generating attempt {}", attempt);
+                return (low_confidence_result.ok_or_else(|| deadline_exceeded(deadline, &attempts)), attempts);
+            };
+            let gen_elapsed = script_gen_start.elapsed();
+            let (raw_script, logprob) = match generated {
+                Ok(Generation { text: script, logprob }) => {
                     info!("✅ Script generated successfully in {:.2}s", gen_elapsed.as_secs_f64());
                     debug!("Generated script length: {} characters", script.len());
                     trace!("Generated script preview: {}", 
                         script.chars().take(200).collect::<String>().replace('\n', "\\n"));
-                    script
+                    (script, logprob)
                 },
                 Err(e) => {
-                    let gen_elapsed = script_gen_start.elapsed();
                     let error_msg = format!("Failed to generate script: {}", e);
                     error!("❌ Script generation failed after {:.2}s: {}", gen_elapsed.as_secs_f64(), error_msg);
                     
-                    attempts.push(ParseAttempt {
+                    self.record_attempt(&mut attempts, options, ParseAttempt {
                         attempt_number: attempt,
                         script: String::new(),
+                        prompt: user_prompt,
+                        response: None,
                         error: Some(error_msg.clone()),
                         success: false,
+                        logprob: None,
+                        failure_category: Some(FailureCategory::Generation),
+                        generation_time: gen_elapsed,
+                        execution_time: None,
+                        language,
+                        command: None,
+                        stderr: None,
+                        verified: None,
                     });
                     
-                    if attempt == MAX_RETRIES {
+                    if let Some(result) = low_confidence_result.take() {
+                        warn!("⚠️  Retry after a low-confidence success failed; returning the low-confidence result");
+                        return (Ok(result), attempts);
+                    }
+                    let failure = anyhow::Error::new(ParseError::Generation(e.to_string()));
+                    if attempt < max_retries && !self.should_retry(&failure) {
+                        error!("🛑 Not retrying after attempt {}: the failure isn't retryable", attempt);
+                        return (Err(failure.context(format!("Attempt {} failed and is not retryable", attempt))), attempts);
+                    }
+                    if attempt == max_retries {
                         let total_elapsed = overall_start.elapsed();
                         error!("💥 All script generation attempts failed after {:.2}s", total_elapsed.as_secs_f64());
-                        anyhow::bail!("Failed to generate script after {} attempts. Last error: {}", MAX_RETRIES, error_msg);
+                        let error = retries_exhausted(&attempts, format!("Failed to generate script after {} attempts. Last error: {}", max_retries, error_msg));
+                        return (Err(error), attempts);
+                    }
+                    if let Some(cooldown) = self.model_error_cooldown {
+                        info!("🧊 Cooling down for {:.2}s before the next generation", cooldown.as_secs_f64());
+                        if within_deadline(deadline, tokio::time::sleep(cooldown)).await.is_none() {
+                            error!("⏰ Parse deadline passed while cooling down after attempt {}", attempt);
+                            return (Err(deadline_exceeded(deadline, &attempts)), attempts);
+                        }
                     }
                     continue;
                 }
             };
-            info!("✂️ Extracting Python code from raw AI response...");
-            let python_script = self.extract_python_code(raw_script.as_str()).unwrap_or(raw_script);
+            info!("✂️ Extracting {} code from raw AI response...", language.name());
+            let (python_script, rejection) = self.review_response(&raw_script, language, options);
 
             // Execute the script
-            info!("🐍 Executing Python script...");
+            info!("🐍 Executing {} script...", language.name());
             let exec_start = Instant::now();
-            match self.execute_python_script(&python_script, document).await {
+            let executable_script = self.prepare_script(&python_script, language);
+            let command = match self.executor_for(language) {
+                _ if rejection.is_some() => None,
+                Some(_) => None,
+                None => Some(shell_command_line(&executable_script, interpreter, language, self.input_mode, &options.env)),
+            };
+            let (outcome, stderr) = match rejection {
+                Some(rejection) => (Err(rejection.into()), None),
+                None => {
+                    let run = self.run_and_finalize(&executable_script, document, interpreter, language, options);
+                    match within_deadline(deadline, run).await {
+                        Some(run) => run,
+                        None => {
+                            error!("⏰ Parse deadline passed while running attempt {}", attempt);
+                            return (low_confidence_result.ok_or_else(|| deadline_exceeded(deadline, &attempts)), attempts);
+                        }
+                    }
+                }
+            };
+            let exec_elapsed = exec_start.elapsed();
+            let (outcome, verified) = match outcome {
+                Ok(result) if self.self_verification => {
+                    match within_deadline(deadline, self.self_check(document, &attempt_instructions, result)).await {
+                        Some(checked) => checked,
+                        None => {
+                            error!("⏰ Parse deadline passed while verifying attempt {}", attempt);
+                            return (low_confidence_result.ok_or_else(|| deadline_exceeded(deadline, &attempts)), attempts);
+                        }
+                    }
+                }
+                outcome => (outcome, None),
+            };
+            match outcome {
                 Ok(result) => {
-                    let exec_elapsed = exec_start.elapsed();
+                    if let (Some(threshold), Some(logprob)) = (self.confidence_threshold, logprob)
+                        && logprob < threshold
+                        && low_confidence_result.is_none()
+                        && attempt < max_retries
+                    {
+                        let error_msg = format!(
+                            "Low generation confidence: mean token logprob {:.3} is below threshold {:.3}",
+                            logprob, threshold
+                        );
+                        warn!("🤔 Attempt {} succeeded but {}; trying once more", attempt, error_msg);
+                        self.record_attempt(&mut attempts, options, ParseAttempt {
+                            attempt_number: attempt,
+                            script: python_script,
+                            prompt: user_prompt,
+                            response: Some(raw_script),
+                            error: Some(error_msg),
+                            success: false,
+                            logprob: Some(logprob),
+                            failure_category: Some(FailureCategory::LowConfidence),
+                            generation_time: gen_elapsed,
+                            execution_time: Some(exec_elapsed),
+                            language,
+                            command: command.clone(),
+                            stderr: stderr.clone(),
+                            verified,
+                        });
+                        low_confidence_result = Some(result);
+                        continue;
+                    }
+
                     let attempt_elapsed = attempt_start.elapsed();
                     let total_elapsed = overall_start.elapsed();
                     
@@ -110,16 +780,28 @@ impl ParserClient {
                     info!("📊 Result length: {} characters", result.len());
                     debug!("Result preview: {}", result.chars().take(200).collect::<String>());
                     
-                    attempts.push(ParseAttempt {
+                    if let Some(cache) = &self.script_cache {
+                        cache.insert(cache_key, python_script.clone(), language);
+                    }
+                    self.record_attempt(&mut attempts, options, ParseAttempt {
                         attempt_number: attempt,
                         script: python_script,
+                        prompt: user_prompt,
+                        response: Some(raw_script),
                         error: None,
                         success: true,
+                        logprob,
+                        failure_category: None,
+                        generation_time: gen_elapsed,
+                        execution_time: Some(exec_elapsed),
+                        language,
+                        command: command.clone(),
+                        stderr: stderr.clone(),
+                        verified,
                     });
-                    return Ok(result);
+                    return (Ok(result), attempts);
                 }
                 Err(e) => {
-                    let exec_elapsed = exec_start.elapsed();
                     let attempt_elapsed = attempt_start.elapsed();
                     let error_msg = format!("Script execution failed: {}", e);
                     
@@ -127,183 +809,711 @@ impl ParserClient {
                         attempt, attempt_elapsed.as_secs_f64(), exec_elapsed.as_secs_f64(), error_msg);
                     debug!("Failed script content: {}", python_script);
                     
-                    attempts.push(ParseAttempt {
+                    self.record_attempt(&mut attempts, options, ParseAttempt {
                         attempt_number: attempt,
                         script: python_script,
+                        prompt: user_prompt,
+                        response: Some(raw_script),
                         error: Some(error_msg.clone()),
                         success: false,
+                        logprob,
+                        failure_category: Some(FailureCategory::of(&e)),
+                        generation_time: gen_elapsed,
+                        execution_time: Some(exec_elapsed),
+                        language,
+                        command: command.clone(),
+                        stderr: stderr.clone(),
+                        verified,
                     });
                     
-                    if attempt == MAX_RETRIES {
+                    if let Some(result) = low_confidence_result.take() {
+                        warn!("⚠️  Retry after a low-confidence success failed; returning the low-confidence result");
+                        return (Ok(result), attempts);
+                    }
+                    if attempt < max_retries && !self.should_retry(&e) {
+                        error!("🛑 Not retrying after attempt {}: the failure isn't retryable", attempt);
+                        return (Err(e.context(format!("Attempt {} failed and is not retryable", attempt))), attempts);
+                    }
+                    if matches!(e.downcast_ref::<ParseError>(), Some(ParseError::OutputRejected(_))) {
+                        rejected_outputs += 1;
+                        if let Some(cap) = self.max_valid_json_attempts
+                            && rejected_outputs >= cap
+                        {
+                            error!("💥 Giving up after {} valid-but-rejected outputs", rejected_outputs);
+                            let summary = format!(
+                                "Giving up after {} attempts produced valid JSON that was rejected. Final error: {}\n\nAll attempts:\n{}",
+                                rejected_outputs,
+                                error_msg,
+                                self.format_attempt_history(&attempts)
+                            );
+                            let error = retries_exhausted(&attempts, summary);
+                            return (Err(error), attempts);
+                        }
+                    }
+                    if attempt == max_retries {
                         let total_elapsed = overall_start.elapsed();
                         error!("💥 All parsing attempts failed after {:.2}s", total_elapsed.as_secs_f64());
-                        anyhow::bail!(
-                            "All {} parsing attempts failed. Final error: {}\n\nAll attempts:\n{}", 
-                            MAX_RETRIES, 
+                        let summary = format!(
+                            "All {} parsing attempts failed. Final error: {}\n\nAll attempts:\n{}",
+                            max_retries,
                             error_msg,
                             self.format_attempt_history(&attempts)
                         );
+                        let error = retries_exhausted(&attempts, summary);
+                        return (Err(error), attempts);
                     }
                 }
             }
         }
         
-        unreachable!("Should have returned or failed within the retry loop")
+        // Only reachable when no attempts are allowed at all.
+        error!("💥 No parsing attempts were made (max retries: {})", max_retries);
+        (Err(retries_exhausted(&attempts, NO_ATTEMPTS.to_string())), attempts)
     }
 
-    /// Extracts Python code from a markdown block in the AI's response.
-    fn extract_python_code(&self, response: &str) -> Option<String> {
-        if let Some(start) = response.find("```python\n") {
-            let script_start = start + "```python\n".len();
-            if let Some(end) = response[script_start..].find("\n```") {
-                debug!("✅ Successfully extracted Python code from markdown block.");
-                return Some(response[script_start..script_start + end].to_string());
-            }
+    /// Extracts code in `language` from a markdown block in the AI's response. Prefers a block
+    /// tagged with the language, then an untagged one; prose around the block is dropped, and a
+    /// block missing its closing fence runs to the end of the response. A stray closing fence
+    /// without an opening one is stripped too.
+    fn extract_code(&self, response: &str, language: ScriptLanguage) -> Option<String> {
+        let blocks = fenced_blocks(response);
+        let tags = language.fence_tags();
+        let block = blocks
+            .iter()
+            .find(|(tag, _)| tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .or_else(|| blocks.iter().find(|(tag, _)| tag.is_empty()));
+        if let Some((_, code)) = block {
+            debug!("✅ Successfully extracted {} code from markdown block.", language.name());
+            return Some(code.clone());
+        }
+        if blocks.is_empty()
+            && let Some((code, _)) = response.trim_end().rsplit_once("\n```")
+        {
+            debug!("Stripping a trailing code fence without an opening one");
+            return Some(code.to_string());
         }
         // If no markdown block is found, assume the whole response is the script.
-        warn!("⚠️ Could not find a Python markdown block. Assuming entire response is code.");
+        warn!("⚠️ Could not find a {} markdown block. Assuming entire response is code.", language.name());
         None 
     }
 
-    /// Executes a Python script with the given document as input
-    async fn execute_python_script(&self, python_script: &str, document: &str) -> Result<String> {
+    /// Applies the configured document preprocessing before it's shown to the model or a script.
+    fn prepare_document<'d>(&self, document: &'d str) -> Cow<'d, str> {
+        #[cfg(feature = "readability")]
+        let document: Cow<'d, str> = match self.readability.then(|| readability::main_content(document)).flatten() {
+            Some(content) => {
+                debug!("Distilled HTML document from {} to {} bytes of main content", document.len(), content.len());
+                Cow::Owned(content)
+            }
+            None => Cow::Borrowed(document),
+        };
+        #[cfg(not(feature = "readability"))]
+        let document = Cow::Borrowed(document);
+
+        if self.normalize_line_endings && document.contains('\r') {
+            debug!("Normalizing CRLF/CR line endings to LF");
+            return Cow::Owned(document.replace("\r\n", "\n").replace('\r', "\n"));
+        }
+        document
+    }
+
+    /// Wraps a generated script with the configured preamble and inline timeout guard. Both are
+    /// Python code, so scripts in other languages run unwrapped.
+    fn prepare_script(&self, script: &str, language: ScriptLanguage) -> String {
+        if language != ScriptLanguage::Python {
+            return script.to_string();
+        }
+        let mut executable_script = match &self.script_preamble {
+            Some(preamble) => format!("{}\n{}", preamble, script),
+            None => script.to_string(),
+        };
+        if let Some(timeout) = self.inline_timeout {
+            executable_script = format!("{}\n{}", inline_timeout_guard(timeout), executable_script);
+        }
+        executable_script
+    }
+
+    /// Extracts the script from a model response, along with the reason not to run it, if any.
+    /// An empty response or an explanation instead of code would only fail later at execution
+    /// with a confusing error, so they're rejected up front.
+    fn review_response(&self, response: &str, language: ScriptLanguage, options: &CallOptions<'_>) -> (String, Option<ParseError>) {
+        let script = self.extract_code(response, language).unwrap_or_else(|| response.to_string());
+        let script = strip_shebang(&script).to_string();
+        let rejection = if script.trim().is_empty() {
+            warn!("🕳️ Model returned an empty response");
+            Some(ParseError::EmptyResponse)
+        } else if language.is_prose(&script) {
+            warn!("📝 Model returned prose instead of {} code", language.name());
+            Some(ParseError::ProseResponse)
+        } else {
+            self.check_imports(&script, language, options)
+        };
+        (script, rejection)
+    }
+
+    /// Rejects a Python script importing modules outside the configured allowlist.
+    fn check_imports(&self, script: &str, language: ScriptLanguage, options: &CallOptions<'_>) -> Option<ParseError> {
+        if language != ScriptLanguage::Python {
+            return None;
+        }
+        if let Some(banned) = &self.banned_modules {
+            let modules = imports::banned_uses(script, banned);
+            if !modules.is_empty() {
+                warn!("🚫 Script uses banned modules: {}", modules.join(", "));
+                return Some(ParseError::BannedModules { modules });
+            }
+        }
+        let allowlist = self.import_allowlist_for(options)?;
+        let modules = imports::disallowed_imports(script, &allowlist);
+        if modules.is_empty() {
+            return None;
+        }
+        warn!("🚫 Script imports disallowed modules: {}", modules.join(", "));
+        Some(ParseError::DisallowedImports { modules })
+    }
+
+    /// The modules a script may import for a call: the configured allowlist, plus `os` when the
+    /// call passes script parameters, since the script reads them from `os.environ`.
+    fn import_allowlist_for(&self, options: &CallOptions<'_>) -> Option<Vec<String>> {
+        let mut allowlist = self.import_allowlist.clone()?;
+        if !options.env.is_empty() && !allowlist.iter().any(|module| module == "os") {
+            allowlist.push("os".to_string());
+        }
+        Some(allowlist)
+    }
+
+    /// The language used for `attempt`, cycling through the configured fallback languages.
+    fn language_for(&self, attempt: usize) -> ScriptLanguage {
+        if self.language_fallback.is_empty() {
+            return ScriptLanguage::Python;
+        }
+        self.language_fallback[(attempt - 1) % self.language_fallback.len()]
+    }
+
+    /// Executes a script in a subprocess running `interpreter <eval_flag> <script>` with the given
+    /// document as input
+    async fn execute_subprocess(&self, script: &str, document: &str, interpreter: &Path, eval_flag: &str, options: &CallOptions<'_>) -> Result<ScriptOutput> {
         let start_time = Instant::now();
-        debug!("🐍 Starting Python script execution...");
-        debug!("Script size: {} bytes, Document size: {} bytes", python_script.len(), document.len());
+        debug!("🐍 Starting script execution with {}...", interpreter.display());
+        debug!("Script size: {} bytes, Document size: {} bytes", script.len(), document.len());
         
-        trace!("Spawning python3 process...");
-        let mut cmd = Command::new("python3")
-            .arg("-c")
-            .arg(python_script)
-            .stdin(Stdio::piped())
+        let _slot = self.subprocess_limit().acquire().await;
+        // Held until the function returns, so the file is removed however the run ends.
+        let document_file = document_file(self.input_mode, document)?;
+        trace!("Spawning {} process...", interpreter.display());
+        let mut command = Command::new(interpreter);
+        command.arg(eval_flag).arg(script).envs(options.env.iter().cloned());
+        match &document_file {
+            Some(file) => command.arg(file.path()).stdin(Stdio::null()),
+            None => command.stdin(Stdio::piped()),
+        };
+        self.resource_limits.apply(&mut command);
+        let mut cmd = command
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .spawn()?;
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| spawn_error(e, interpreter))?;
 
-        debug!("Writing document to stdin...");
-        let mut stdin = cmd.stdin.take().expect("Failed to open stdin");
-        let document_for_script = document.to_string();
-        
-        tokio::spawn(async move {
-            if let Err(e) = stdin.write_all(document_for_script.as_bytes()).await {
-                error!("Failed to write to stdin: {}", e);
-            } else {
-                trace!("Successfully wrote document to stdin");
-            }
-        });
+        debug!("Writing document to stdin while draining stdout and stderr...");
+        let stdin = cmd.stdin.take();
+        let mut stdout = cmd.stdout.take().expect("Failed to open stdout");
+        let stderr = cmd.stderr.take().expect("Failed to open stderr");
 
-        debug!("Waiting for Python process to complete...");
-        let output = cmd.wait_with_output().await?;
+        // All three pipes are serviced concurrently: a script that fills stdout or stderr
+        // before it finishes reading stdin would otherwise deadlock against our writes.
+        let write_stdin = async {
+            let Some(mut stdin) = stdin else {
+                return;
+            };
+            let mut written = 0;
+            for chunk in document.as_bytes().chunks(STDIN_CHUNK_SIZE) {
+                if let Err(e) = stdin.write_all(chunk).await {
+                    error!("Failed to write to stdin: {}", e);
+                    return;
+                }
+                written += chunk.len();
+                if let Some(progress) = &self.stdin_progress {
+                    progress(written);
+                }
+            }
+            drop(stdin);
+            trace!("Successfully wrote document to stdin");
+        };
+        let read_stdout = async {
+            let mut buffer = Vec::new();
+            stdout.read_to_end(&mut buffer).await.map(|_| buffer)
+        };
+        let read_stderr = async {
+            if !self.script_progress {
+                return read_capped(stderr, self.max_stderr_bytes).await;
+            }
+            read_capped_lines(stderr, self.max_stderr_bytes, |line| {
+                if let Some((done, total)) = event::parse_progress(line) {
+                    trace!("Script progress: {}/{}", done, total);
+                    self.emit(ParseEvent::ScriptProgress { done, total, metadata: options.metadata.clone() });
+                }
+            })
+            .await
+        };
+        let ((), stdout, stderr) = tokio::join!(write_stdin, read_stdout, read_stderr);
+        let (stderr, stderr_dropped) = stderr?;
+        let output = std::process::Output {
+            status: cmd.wait().await?,
+            stdout: stdout?,
+            stderr,
+        };
         let exec_elapsed = start_time.elapsed();
         
-        debug!("Python process completed in {:.3}s", exec_elapsed.as_secs_f64());
+        debug!("Script process completed in {:.3}s", exec_elapsed.as_secs_f64());
         debug!("Exit status: {:?}", output.status);
         debug!("Stdout length: {} bytes", output.stdout.len());
-        debug!("Stderr length: {} bytes", output.stderr.len());
+        debug!("Stderr length: {} bytes ({} bytes over the limit discarded)", output.stderr.len(), stderr_dropped);
 
         if output.status.success() {
-            trace!("Python script executed successfully");
-            let stdout = String::from_utf8(output.stdout)?;
-            
-            // Validate that we got some meaningful output
-            if stdout.trim().is_empty() {
-                warn!("Script executed successfully but produced no output");
-                anyhow::bail!("Script executed successfully but produced no output");
+            trace!("Script executed successfully");
+            Ok(ScriptOutput {
+                stdout: String::from_utf8(output.stdout).map_err(|e| ParseError::InvalidOutput(e.to_string()))?,
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            })
+        } else {
+            if let Some(limit) = self.inline_timeout
+                && output.status.code() == Some(INLINE_TIMEOUT_EXIT_CODE)
+            {
+                // The guard's report is the last line of stdout, after anything the script printed.
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let report = stdout.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or_default().to_string();
+                warn!("Script hit the inline timeout of {:.2}s: {}", limit.as_secs_f64(), report);
+                return Err(ParseError::InlineTimeout { limit, report }.into());
             }
-            
-            debug!("Validating JSON output...");
-            // Try to validate it's valid JSON
-            if let Err(e) = serde_json::from_str::<serde_json::Value>(&stdout) {
-                error!("Script output is not valid JSON: {}", e);
-                debug!("Invalid JSON output: {}", stdout);
-                anyhow::bail!("Script output is not valid JSON: {}\nOutput was: {}", e, stdout);
+
+            let mut error_message = String::from_utf8_lossy(&output.stderr).into_owned();
+            if let Some(exceeded) = self.resource_limits.exceeded(output.status, &error_message) {
+                warn!("Script hit a resource limit: {}", exceeded);
+                return Err(exceeded.into());
             }
-            
-            info!("✅ Script executed successfully and produced valid JSON");
-            Ok(stdout)
-        } else {
-            let error_message = String::from_utf8(output.stderr)?;
-            error!("Python script execution failed with exit code: {}", output.status.code().unwrap_or(-1));
+            if stderr_dropped > 0 {
+                error_message.push_str(&format!("\n[stderr truncated: {} more bytes]", stderr_dropped));
+            }
+            error!("Script execution failed with exit code: {}", output.status.code().unwrap_or(-1));
             error!("STDERR: {}", error_message);
-            debug!("Failed script:\n{}", python_script);
+            debug!("Failed script:\n{}", script);
             
-            anyhow::bail!(
-                "Python script execution failed with exit code: {}\nSTDERR: {}\nSCRIPT:\n{}", 
-                output.status.code().unwrap_or(-1),
-                error_message,
-                python_script
-            );
+            Err(ParseError::NonZeroExit {
+                code: output.status.code().unwrap_or(-1),
+                stderr: error_message,
+                script: script.to_string(),
+            }.into())
         }
     }
 
-    /// Gets the system prompt for the AI model
-    fn get_system_prompt(&self) -> &'static str {
-        debug!("Using system prompt for AI model");
-        r#"
-You are an expert Python programmer that creates parsing scripts. Your task is to write a single, complete Python script based on the user's request.
+    /// The custom executor that runs scripts in `language`, if any.
+    fn executor_for(&self, language: ScriptLanguage) -> Option<&dyn ScriptExecutor> {
+        match self.language_executors.get(&language) {
+            Some(executor) => Some(executor.as_ref()),
+            None if language == ScriptLanguage::Python => self.executor.as_deref(),
+            None => None,
+        }
+    }
 
-CRITICAL RULES:
-1. The script you write will receive the raw document text via standard input (stdin).
-2. The script must print a single, valid JSON object to standard output (stdout).
-3. The script MUST NOT use any external libraries like BeautifulSoup. Use only standard libraries like `sys`, `json`, and `re`.
-4. Your output must be ONLY the raw Python code. Do not include explanations, markdown, or code blocks.
-5. Always include proper error handling to avoid crashes.
-6. If you cannot find the requested data, return an empty JSON object {} rather than failing.
-7. Make sure your JSON output is properly formatted and valid.
+    /// Runs a script with the executor configured for `language` (a `python3` or `node` subprocess
+    /// by default) and checks that it printed valid, non-empty JSON.
+    async fn execute_script(&self, script: &str, document: &str, interpreter: &Path, language: ScriptLanguage, options: &CallOptions<'_>) -> Result<ScriptOutput> {
+        if let Some(version) = self.python_version
+            && language == ScriptLanguage::Python
+        {
+            let issues = compat::incompatible_syntax(script, version);
+            if !issues.is_empty() {
+                warn!("Script uses syntax unsupported by Python {}.{}: {}", version.0, version.1, issues.join(", "));
+                return Err(ParseError::IncompatibleSyntax { version, issues }.into());
+            }
+        }
 
-If this is a retry attempt, learn from the previous errors and fix them in your new script.
-"#
+        match self.executor_for(language) {
+            Some(executor) => executor.execute(script, document).await,
+            None => {
+                let (program, eval_flag) = subprocess_command(interpreter, language);
+                self.execute_subprocess(script, document, program, eval_flag, options).await
+            }
+        }
     }
 
-    /// Builds the user prompt, including error history for retry attempts
-    fn build_user_prompt(&self, document: &str, instructions: &str, attempts: &[ParseAttempt], current_attempt: usize) -> String {
-        debug!("Building user prompt for attempt {}", current_attempt);
-        
-        let mut prompt = format!(
-            r#"
-**Instructions:**
-{}
+    /// Executes a prepared script and post-processes its output, returning the result together
+    /// with the script's stderr when it was captured.
+    async fn run_and_finalize(&self, script: &str, document: &str, interpreter: &Path, language: ScriptLanguage, options: &CallOptions<'_>) -> (Result<String>, Option<String>) {
+        match self.execute_script(script, document, interpreter, language, options).await {
+            Ok(output) => {
+                let result = self.validate_stdout(output.stdout).and_then(|stdout| self.finalize_output(stdout, options));
+                (result, Some(output.stderr))
+            }
+            Err(e) => {
+                let stderr = match e.downcast_ref::<ParseError>() {
+                    Some(ParseError::NonZeroExit { stderr, .. }) => Some(stderr.clone()),
+                    _ => None,
+                };
+                (Err(e), stderr)
+            }
+        }
+    }
 
-**Document to Parse:**
----
-{}
----
-"#,
-            instructions, document
-        );
+    /// Checks that a script's stdout holds a JSON value, dropping trailing output if allowed.
+    fn validate_stdout(&self, mut stdout: String) -> Result<String> {
+        // Validate that we got some meaningful output
+        if stdout.trim().is_empty() {
+            warn!("Script executed successfully but produced no output");
+            return Err(ParseError::EmptyOutput.into());
+        }
 
-        // Add error history for retry attempts
-        if current_attempt > 1 && !attempts.is_empty() {
-            debug!("Adding error history from {} previous attempts", attempts.len());
-            prompt.push_str("\n**Previous Attempts and Errors:**\n");
-            for attempt in attempts {
-                prompt.push_str(&format!("Attempt {}: ", attempt.attempt_number));
-                if let Some(error) = &attempt.error {
-                    debug!("Including error from attempt {}: {}", attempt.attempt_number, error);
-                    prompt.push_str(&format!("FAILED - {}\n", error));
-                    if !attempt.script.is_empty() {
-                        prompt.push_str("Script that failed:\n```python\n");
-                        prompt.push_str(&attempt.script);
-                        prompt.push_str("\n```\n\n");
-                    }
-                } else {
-                    prompt.push_str("SUCCESS\n");
+        match self.output_format {
+            OutputFormat::Json => {}
+            OutputFormat::Raw => return Ok(stdout),
+            OutputFormat::Csv => match output::csv_error(&stdout) {
+                Some(error) => {
+                    warn!("Script output is not valid CSV: {}", error);
+                    return Err(ParseError::OutputRejected(format!(
+                        "Output is not valid CSV: {}. Print a header row followed by one row per record.",
+                        error
+                    ))
+                    .into());
                 }
-            }
-            prompt.push_str("Please learn from these errors and create a better script.\n\n");
+                None => return Ok(stdout),
+            },
         }
-
-        prompt.push_str("Provide the Python script now:");
-        trace!("Final prompt length: {} characters", prompt.len());
-        prompt
+        
+        debug!("Validating JSON output...");
+        if self.allow_trailing_data
+            && let Ok(value) = output::leading_json(&stdout)
+            && value.len() < stdout.trim_end().len()
+        {
+            debug!("Ignoring {} bytes of trailing output after the JSON value", stdout.len() - value.len());
+            stdout = value.to_string();
+        }
+        // Try to validate it's valid JSON
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(&stdout) {
+            error!("Script output is not valid JSON: {}", e);
+            debug!("Invalid JSON output: {}", stdout);
+            return Err(ParseError::InvalidJson { error: e.to_string(), output: stdout }.into());
+        }
+        
+        info!("✅ Script executed successfully and produced valid JSON");
+        Ok(stdout)
     }
 
-    /// Formats the attempt history for error reporting
-    fn format_attempt_history(&self, attempts: &[ParseAttempt]) -> String {
-        debug!("Formatting attempt history for {} attempts", attempts.len());
-        let mut history = String::new();
-        for attempt in attempts {
-            history.push_str(&format!("--- Attempt {} ---\n", attempt.attempt_number));
+    /// Applies post-processing to a script's validated JSON output to produce the returned result.
+    fn finalize_output(&self, mut stdout: String, options: &CallOptions<'_>) -> Result<String> {
+        if self.output_format != OutputFormat::Json {
+            self.check_output_size(&stdout)?;
+            self.run_validators(&stdout)?;
+            return Ok(stdout);
+        }
+        let mut value: serde_json::Value = serde_json::from_str(&stdout)?;
+
+        if let Some(key) = &self.error_key
+            && let Some(reported) = value.get(key)
+        {
+            let reported = reported.as_str().map(str::to_string).unwrap_or_else(|| reported.to_string());
+            warn!("Script output reports an error under \"{}\": {}", key, reported);
+            return Err(ParseError::OutputRejected(format!("Script reported an error under \"{}\": {}", key, reported)).into());
+        }
+
+        self.check_output_size(&stdout)?;
+
+        if let Some(max_depth) = self.max_json_depth {
+            let depth = output::nesting_depth(&value);
+            if depth > max_depth {
+                warn!("Script output is nested {} levels deep, beyond the limit of {}", depth, max_depth);
+                return Err(ParseError::OutputRejected(format!(
+                    "Output too deeply nested: {} levels, but at most {} are allowed. Flatten the structure.",
+                    depth, max_depth
+                ))
+                .into());
+            }
+        }
+
+        if self.deduplicate_output && output::deduplicate(&mut value, self.dedup_key.as_deref()) {
+            debug!("Removed duplicate records from the result");
+            stdout = serde_json::to_string(&value)?;
+        }
+
+        if let Some(example) = options.output_example.or(self.output_example.as_ref()) {
+            let mismatches = output::structure_mismatches(example, &value, options.allow_extra_keys);
+            if !mismatches.is_empty() {
+                warn!("Script output does not match the output example: {}", mismatches.join("; "));
+                return Err(ParseError::OutputRejected(format!(
+                    "Output does not match the expected structure ({}). Match this structure exactly: {}",
+                    mismatches.join("; "),
+                    example
+                ))
+                .into());
+            }
+        }
+
+        if let Some(schema) = options.schema {
+            let violations = schema::schema_violations(schema, &value);
+            if !violations.is_empty() {
+                warn!("Script output does not validate against the schema: {}", violations.join("; "));
+                return Err(ParseError::OutputRejected(format!(
+                    "Output does not match the JSON schema: {}",
+                    violations.join("; ")
+                ))
+                .into());
+            }
+        }
+
+        let mut mismatched_fields: Vec<String> = self
+            .field_patterns
+            .iter()
+            .filter_map(|(field, pattern)| {
+                let text = value.get(field)?.as_str()?;
+                (!pattern.is_match(text)).then(|| {
+                    format!("field \"{}\" didn't match pattern `{}` (got {:?})", field, pattern.as_str(), text)
+                })
+            })
+            .collect();
+        if !mismatched_fields.is_empty() {
+            mismatched_fields.sort();
+            warn!("Script output failed field patterns: {}", mismatched_fields.join("; "));
+            return Err(ParseError::OutputRejected(format!("Output failed validation: {}", mismatched_fields.join("; "))).into());
+        }
+
+        if let Some(form) = self.unicode_normalization
+            && output::normalize_strings(&mut value, form)
+        {
+            debug!("Normalized result strings to {:?}", form);
+            stdout = serde_json::to_string(&value)?;
+        }
+
+        self.run_validators(&stdout)?;
+
+        if let Some(check) = options.output_check
+            && let Err(reason) = check(&value)
+        {
+            warn!("Script output failed the caller's check: {}", reason);
+            return Err(ParseError::OutputRejected(reason).into());
+        }
+
+        let serialization = options.serialization.unwrap_or(self.serialization);
+        if serialization == Serialization::Json {
+            return Ok(stdout);
+        }
+
+        debug!("Converting result to {:?}...", serialization);
+        output::serialize_value(&value, serialization).map_err(|e| {
+            ParseError::OutputRejected(format!("Output could not be converted to {:?}: {}", serialization, e)).into()
+        })
+    }
+
+    /// Rejects output shorter than `min_output_bytes`.
+    fn check_output_size(&self, stdout: &str) -> Result<()> {
+        if let Some(min_bytes) = self.min_output_bytes
+            && stdout.trim().len() < min_bytes
+        {
+            warn!("Script output is only {} bytes, below the minimum of {}", stdout.trim().len(), min_bytes);
+            return Err(ParseError::OutputRejected(format!(
+                "Output is only {} bytes but at least {} are expected. Your output seems too small; did you miss data?",
+                stdout.trim().len(),
+                min_bytes
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Runs the configured validators in order, failing on the first rejection.
+    fn run_validators(&self, stdout: &str) -> Result<()> {
+        for validator in &self.validators {
+            if let Err(reason) = validator(stdout) {
+                warn!("Script output failed a validator: {}", reason);
+                return Err(ParseError::OutputRejected(format!("Output failed validation: {}", reason)).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the system prompt for the AI model: the configured one or the built-in one for
+    /// `language` adapted to the output format, followed by any extra rules.
+    fn get_system_prompt(&self, language: ScriptLanguage) -> Cow<'_, str> {
+        debug!("Using {} system prompt for AI model", language.name());
+        let (prompt, format_rule) = match &self.system_prompt {
+            Some(prompt) => (prompt.as_str(), None),
+            None => (language.system_prompt(self.input_mode), self.output_format.prompt_rule()),
+        };
+        if self.system_rules.is_empty() && format_rule.is_none() {
+            return Cow::Borrowed(prompt);
+        }
+        let mut prompt = prompt.trim_end().to_string();
+        if let Some(rule) = format_rule {
+            prompt.push_str(&format!("\n\nOUTPUT FORMAT:\n{}", rule));
+        }
+        if self.system_rules.is_empty() {
+            return Cow::Owned(prompt);
+        }
+        prompt.push_str("\n\nADDITIONAL RULES:\n");
+        for rule in &self.system_rules {
+            prompt.push_str(&format!("- {}\n", rule));
+        }
+        Cow::Owned(prompt)
+    }
+
+    /// The part of the document shown to the model on `attempt`, after the configured
+    /// preprocessor. With `prompt_document_chars` set, long documents are cut to a head/tail
+    /// excerpt whose size doubles `widenings` times, in case earlier attempts failed because the
+    /// relevant data was cut out.
+    fn prompt_excerpt<'d>(&self, document: &'d str, widenings: usize) -> Cow<'d, str> {
+        let document = match &self.preprocessor {
+            Some(preprocess) => {
+                let processed = preprocess(document);
+                debug!("Preprocessed document from {} to {} bytes for the prompt", document.len(), processed.len());
+                Cow::Owned(processed)
+            }
+            None => Cow::Borrowed(document),
+        };
+        let Some(chars) = self.prompt_document_chars else {
+            return document;
+        };
+        let widenings = if self.widen_prompt_excerpt { widenings } else { 0 };
+        let shift = widenings.min(usize::BITS as usize - 1) as u32;
+        let window = 1usize.checked_shl(shift).map_or(usize::MAX, |factor| chars.saturating_mul(factor));
+        debug!("Showing at most {} document characters after {} widenings", window, widenings);
+        let total = document.chars().count();
+        if total > window {
+            info!("✂️ Document has {} characters; showing the model a {}-character excerpt", total, window);
+        }
+        match document {
+            Cow::Borrowed(document) => prompt::excerpt(document, window),
+            Cow::Owned(document) => Cow::Owned(prompt::excerpt(&document, window).into_owned()),
+        }
+    }
+
+    /// The limit on concurrent subprocesses this client's scripts wait for.
+    fn subprocess_limit(&self) -> &limit::SubprocessLimit {
+        self.subprocess_limit.as_deref().unwrap_or(&limit::GLOBAL_SUBPROCESS_LIMIT)
+    }
+
+    /// Builds the user prompt, including error history for retry attempts
+    fn build_user_prompt(
+        &self,
+        document: &str,
+        instructions: &str,
+        attempts: &[ParseAttempt],
+        current_attempt: usize,
+        language: ScriptLanguage,
+        options: &CallOptions<'_>,
+    ) -> String {
+        debug!("Building user prompt for attempt {}", current_attempt);
+        
+        let mut prompt = format!(
+            r#"
+**Instructions:**
+{}
+
+**Document to Parse:**
+---
+{}
+---
+"#,
+            instructions, prompt::render_document(&self.prompt_excerpt(document, excerpt_widenings(attempts)), self.binary_prompt_mode, self.input_mode)
+        );
+
+        if self.preprocessor.is_some() {
+            prompt.push_str("The document above was simplified for display. Your script receives the original, unprocessed document, so parse that format.\n");
+        }
+
+        if let Some(reference) = &self.structure_reference {
+            prompt.push_str("\n**Reference Document (do not parse this one):**\nDocuments look like this. Write a general script that works for any document with this structure; only the document above is passed to it.\n---\n");
+            prompt.push_str(&prompt::render_document(reference, self.binary_prompt_mode, self.input_mode));
+            prompt.push_str("\n---\n");
+        }
+
+        if let Some(version) = self.python_version
+            && language == ScriptLanguage::Python
+        {
+            prompt.push_str(&format!("\n**Python Version:**\n{}\n", compat::version_hint(version)));
+        }
+
+        if let Some(example) = options.output_example.or(self.output_example.as_ref()) {
+            prompt.push_str("\n**Expected Output Structure:**\nPrint JSON with exactly these keys and value types:\n```json\n");
+            prompt.push_str(&serde_json::to_string_pretty(example).unwrap_or_else(|_| example.to_string()));
+            prompt.push_str("\n```\n");
+        }
+
+        if let Some(schema) = options.schema {
+            prompt.push_str("\n**Output JSON Schema:**\nPrint JSON that validates against this schema:\n```json\n");
+            prompt.push_str(&serde_json::to_string_pretty(schema).unwrap_or_else(|_| schema.to_string()));
+            prompt.push_str("\n```\n");
+        }
+
+        if let Some(allowlist) = self.import_allowlist_for(options)
+            && language == ScriptLanguage::Python
+        {
+            prompt.push_str(&format!("\n**Allowed Imports:**\nImport only these modules: {}. Do not import anything else.\n", allowlist.join(", ")));
+        }
+
+        if let Some(banned) = &self.banned_modules
+            && language == ScriptLanguage::Python
+        {
+            prompt.push_str(&format!("\n**Forbidden Modules:**\nNo network or process access is allowed. Do not use: {}.\n", banned.join(", ")));
+        }
+
+        if !options.env.is_empty() {
+            prompt.push_str(&params::prompt_section(&options.env, language));
+        }
+
+        if self.script_progress {
+            prompt.push_str("\n**Progress Reporting:**\nWhile working through the document, report progress by printing lines of the form `PROGRESS: n/total` (e.g. `PROGRESS: 3/10`) to standard error, flushing after each one. Never print progress to standard output.\n");
+        }
+
+        if let Some(preamble) = &self.script_preamble
+            && language == ScriptLanguage::Python
+        {
+            prompt.push_str("\n**Preamble:**\nThe following code already runs before your script. Do not repeat it; write only the extraction logic that follows it, using what it defines.\n```python\n");
+            prompt.push_str(preamble);
+            prompt.push_str("\n```\n");
+        }
+
+        // Add error history for retry attempts
+        if current_attempt > 1 && !attempts.is_empty() {
+            debug!("Adding error history from {} previous attempts", attempts.len());
+            prompt.push_str("\n**Previous Attempts and Errors:**\n");
+            for attempt in attempts {
+                prompt.push_str(&format!("Attempt {}: ", attempt.attempt_number));
+                if let Some(error) = &attempt.error {
+                    debug!("Including error from attempt {}: {}", attempt.attempt_number, error);
+                    prompt.push_str(&format!("FAILED - {}\n", error));
+                    if !attempt.script.is_empty() {
+                        prompt.push_str(&format!("Script that failed:\n```{}\n", attempt.language.fence_tags()[0]));
+                        prompt.push_str(&attempt.script);
+                        prompt.push_str("\n```\n\n");
+                    }
+                } else {
+                    prompt.push_str("SUCCESS\n");
+                }
+            }
+            prompt.push_str("Please learn from these errors and create a better script.\n\n");
+            match attempts.last().and_then(|attempt| attempt.failure_category) {
+                Some(FailureCategory::ProseResponse) => prompt.push_str(&format!(
+                    "IMPORTANT: Your last response was an explanation, not code. Reply with ONLY runnable {} code, starting with its first statement. Do not describe the script.\n\n",
+                    language.name()
+                )),
+                Some(FailureCategory::EmptyResponse) => prompt.push_str(&format!(
+                    "IMPORTANT: Your last response was empty. Reply with the complete {} script.\n\n",
+                    language.name()
+                )),
+                _ => {}
+            }
+        }
+
+        prompt.push_str(&format!("Provide the {} script now:", language.name()));
+        trace!("Final prompt length: {} characters", prompt.len());
+        prompt
+    }
+
+    /// Formats the attempt history for error reporting
+    fn format_attempt_history(&self, attempts: &[ParseAttempt]) -> String {
+        debug!("Formatting attempt history for {} attempts", attempts.len());
+        let mut history = String::new();
+        for attempt in attempts {
+            history.push_str(&format!("--- Attempt {} ---\n", attempt.attempt_number));
             if attempt.success {
                 history.push_str("Status: SUCCESS\n");
             } else {
@@ -312,6 +1522,12 @@ If this is a retry attempt, learn from the previous errors and fix them in your
                     history.push_str(&format!("Error: {}\n", error));
                 }
             }
+            if let Some(category) = attempt.failure_category {
+                history.push_str(&format!("Category: {:?}\n", category));
+            }
+            if let Some(logprob) = attempt.logprob {
+                history.push_str(&format!("Mean token logprob: {:.3}\n", logprob));
+            }
             if !attempt.script.is_empty() {
                 history.push_str("Script:\n");
                 history.push_str(&attempt.script);
@@ -322,115 +1538,289 @@ If this is a retry attempt, learn from the previous errors and fix them in your
     }
 
     /// Alternative method that returns detailed attempt information along with the result
-    pub async fn dynamic_parse_with_details(&self, document: &str, instructions: &str) -> Result<(String, Vec<ParseAttempt>)> {
-        let overall_start = Instant::now();
+    pub async fn dynamic_parse_with_details(&self, document: &str, instructions: &str) -> Result<(String, Vec<ParseAttempt>), ParseError> {
         info!("🔄 Starting dynamic parse with details");
-        info!("📄 Document length: {} characters", document.len());
-        info!("📝 Instructions: {}", instructions);
-        
-        debug!("Creating chat session with system prompt...");
-        let mut chat = self.model.chat().with_system_prompt(self.get_system_prompt());
-        let mut attempts: Vec<ParseAttempt> = Vec::new();
-        
-        for attempt in 1..=MAX_RETRIES {
-            let attempt_start = Instant::now();
-            info!("🎯 Parsing attempt {}/{}", attempt, MAX_RETRIES);
-            
-            debug!("Building user prompt for attempt {}...", attempt);
-            let user_prompt = self.build_user_prompt(document, instructions, &attempts, attempt);
-            
-            // Generate the script
-            info!("🤖 Generating Python script with AI model...");
-            let script_gen_start = Instant::now();
-            let python_script = match chat.add_message(&user_prompt).await {
-                Ok(script) => {
-                    let gen_elapsed = script_gen_start.elapsed();
-                    info!("✅ Script generated successfully in {:.2}s", gen_elapsed.as_secs_f64());
-                    debug!("Generated script length: {} characters", script.len());
-                    script
-                },
-                Err(e) => {
-                    let gen_elapsed = script_gen_start.elapsed();
-                    let error_msg = format!("Failed to generate script: {}", e);
-                    error!("❌ Script generation failed after {:.2}s: {}", gen_elapsed.as_secs_f64(), error_msg);
-                    
-                    attempts.push(ParseAttempt {
-                        attempt_number: attempt,
-                        script: String::new(),
-                        error: Some(error_msg.clone()),
-                        success: false,
-                    });
-                    
-                    if attempt == MAX_RETRIES {
-                        let total_elapsed = overall_start.elapsed();
-                        error!("💥 All script generation attempts failed after {:.2}s", total_elapsed.as_secs_f64());
-                        anyhow::bail!("Failed to generate script after {} attempts. Last error: {}", MAX_RETRIES, error_msg);
-                    }
-                    continue;
-                }
-            };
+        Ok(self.parse_with_attempts(document, instructions, &CallOptions::default()).await?)
+    }
 
-            // Execute the script
-            info!("🐍 Executing Python script...");
-            let exec_start = Instant::now();
-            match self.execute_python_script(&python_script, document).await {
-                Ok(result) => {
-                    let exec_elapsed = exec_start.elapsed();
-                    let attempt_elapsed = attempt_start.elapsed();
-                    let total_elapsed = overall_start.elapsed();
-                    
-                    info!("🎉 Successfully parsed document on attempt {}", attempt);
-                    info!("⏱️  Execution time: {:.2}s, Attempt time: {:.2}s, Total time: {:.2}s", 
-                        exec_elapsed.as_secs_f64(), attempt_elapsed.as_secs_f64(), total_elapsed.as_secs_f64());
-                    info!("📊 Result length: {} characters", result.len());
-                    debug!("Result preview: {}", result.chars().take(200).collect::<String>());
-                    
-                    attempts.push(ParseAttempt {
-                        attempt_number: attempt,
-                        script: python_script,
-                        error: None,
-                        success: true,
-                    });
-                    return Ok((result, attempts));
-                }
-                Err(e) => {
-                    let exec_elapsed = exec_start.elapsed();
-                    let attempt_elapsed = attempt_start.elapsed();
-                    let error_msg = format!("Script execution failed: {}", e);
-                    
-                    warn!("⚠️  Attempt {} failed after {:.2}s (exec: {:.2}s): {}", 
-                        attempt, attempt_elapsed.as_secs_f64(), exec_elapsed.as_secs_f64(), error_msg);
-                    debug!("Failed script content: {}", python_script);
-                    
-                    attempts.push(ParseAttempt {
-                        attempt_number: attempt,
-                        script: python_script,
-                        error: Some(error_msg.clone()),
-                        success: false,
-                    });
-                    
-                    if attempt == MAX_RETRIES {
-                        let total_elapsed = overall_start.elapsed();
-                        error!("💥 All parsing attempts failed after {:.2}s", total_elapsed.as_secs_f64());
-                        anyhow::bail!(
-                            "All {} parsing attempts failed. Final error: {}\n\nAll attempts:\n{}", 
-                            MAX_RETRIES, 
-                            error_msg,
-                            self.format_attempt_history(&attempts)
-                        );
-                    }
-                }
+    /// Generates the script for the next attempt at parsing `document` without running it, e.g. to
+    /// review or edit it before calling `run_script`. `prior_attempts` are shown to the model as in
+    /// the retry loop, so feeding back failed attempts asks it to fix them. Empty responses, prose
+    /// and disallowed imports are errors.
+    pub async fn generate_script(&self, document: &str, instructions: &str, prior_attempts: &[ParseAttempt]) -> Result<String> {
+        let document = &*self.prepare_document(document);
+        let attempt = prior_attempts.len() + 1;
+        let language = self.language_for(attempt);
+        let prompt = self.build_user_prompt(document, instructions, prior_attempts, attempt, language, &CallOptions::default());
+        info!("🤖 Generating {} script for attempt {}...", language.name(), attempt);
+        let response = self
+            .generator
+            .generate(&self.get_system_prompt(language), &prompt)
+            .await
+            .map_err(|e| ParseError::Generation(e.to_string()))?;
+        match self.review_response(&response, language, &CallOptions::default()) {
+            (_, Some(rejection)) => Err(rejection.into()),
+            (script, None) => Ok(script),
+        }
+    }
+
+    /// Dry run for iterating on instructions: runs a single generation pass for `document` and
+    /// returns the script with code fences and any shebang stripped, without executing it. Unlike
+    /// `generate_script`, a response that would be rejected (empty, prose, disallowed imports) is
+    /// still returned, with a warning, so the model's output can be inspected as is.
+    pub async fn generate_only(&self, document: &str, instructions: &str) -> Result<String> {
+        info!("🔍 Generating script without executing it");
+        let document = &*self.prepare_document(document);
+        let language = self.language_for(1);
+        let prompt = self.build_user_prompt(document, instructions, &[], 1, language, &CallOptions::default());
+        let response = self
+            .generator
+            .generate(&self.get_system_prompt(language), &prompt)
+            .await
+            .map_err(|e| ParseError::Generation(e.to_string()))?;
+        let (script, rejection) = self.review_response(&response, language, &CallOptions::default());
+        if let Some(rejection) = rejection {
+            warn!("Generated script would be rejected: {}", rejection);
+        }
+        Ok(script)
+    }
+
+    /// Runs a Python script against `document` exactly as a parse attempt would, including the
+    /// configured preamble, executor and output validation, and returns the validated result.
+    /// The model is never involved, so this also runs hand-written parsers through the same JSON
+    /// checks, timeouts and resource limits as generated ones, e.g. to mix both in a pipeline.
+    pub async fn run_script(&self, script: &str, document: &str) -> Result<String> {
+        self.run_script_as(script, ScriptLanguage::Python, document).await
+    }
+
+    /// Runs a `language` script against `document` as a parse attempt would, without the model.
+    async fn run_script_as(&self, script: &str, language: ScriptLanguage, document: &str) -> Result<String> {
+        let document = &*self.prepare_document(document);
+        let options = CallOptions::default();
+        let executable_script = self.prepare_script(strip_shebang(script), language);
+        let (result, _) = self.run_and_finalize(&executable_script, document, &self.interpreter, language, &options).await;
+        result
+    }
+
+    /// Like `dynamic_parse`, but passes `params` (e.g. `currency` = `EUR`) to the script as
+    /// environment variables named `PARAM_<NAME>`, and lists those names in the prompt so the
+    /// script reads them instead of hard-coding values. With an import allowlist, `os` is allowed
+    /// for the call so the script can read them. Fails without generating a script if two names
+    /// map to the same variable (`a-b` and `a_b`), or if a custom executor would run the script,
+    /// since executors have no way to receive them.
+    pub async fn dynamic_parse_with_params(&self, document: &str, instructions: &str, params: &HashMap<String, String>) -> Result<String, ParseError> {
+        info!("🔄 Starting dynamic parse with {} script parameters", params.len());
+        let languages = if self.language_fallback.is_empty() { &[ScriptLanguage::Python][..] } else { &self.language_fallback };
+        if !params.is_empty()
+            && let Some(language) = languages.iter().find(|language| self.executor_for(**language).is_some())
+        {
+            return Err(anyhow!("Script parameters can't be passed to the custom executor that runs {} scripts", language.name()).into());
+        }
+        let options = CallOptions {
+            env: params::script_env(params)?,
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        Ok(result)
+    }
+
+    /// Like `dynamic_parse`, but tags the parse with `metadata` (e.g. a request or tenant id). The
+    /// metadata is recorded as `key=value` pairs in the `metadata` field of the parse's tracing
+    /// span, so every log line can be correlated, and is included in every `ParseEvent`.
+    pub async fn dynamic_parse_tagged(&self, document: &str, instructions: &str, metadata: HashMap<String, String>) -> Result<String, ParseError> {
+        info!("🔄 Starting tagged dynamic parse");
+        let options = CallOptions {
+            metadata: Arc::new(metadata),
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        Ok(result)
+    }
+}
+
+/// Program and inline-source flag used to run `language` scripts in a subprocess.
+fn subprocess_command(interpreter: &Path, language: ScriptLanguage) -> (&Path, &'static str) {
+    let (default_program, eval_flag) = language.command();
+    match language {
+        ScriptLanguage::Python => (interpreter, eval_flag),
+        _ => (Path::new(default_program), eval_flag),
+    }
+}
+
+/// Reports a missing interpreter by path rather than as a bare "No such file or directory".
+fn spawn_error(error: std::io::Error, program: &Path) -> anyhow::Error {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => ParseError::InterpreterNotFound { path: program.to_path_buf() }.into(),
+        _ => ParseError::SpawnFailed { program: program.to_path_buf(), error: error.to_string() }.into(),
+    }
+}
+
+/// A copy-pasteable shell command reproducing a subprocess run, with the document in a file named
+/// `document`, piped to stdin or passed as an argument depending on `input_mode`.
+fn shell_command_line(script: &str, interpreter: &Path, language: ScriptLanguage, input_mode: InputMode, env: &[(String, String)]) -> String {
+    let (program, eval_flag) = subprocess_command(interpreter, language);
+    let cwd = std::env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| ".".to_string());
+    let input = match input_mode {
+        InputMode::Stdin => "< document",
+        InputMode::TempFile => "document",
+    };
+    let assignments: String = env.iter().map(|(name, value)| format!("{}={} ", name, shell_quote(value))).collect();
+    format!(
+        "cd {} && {}{} {} {} {}",
+        shell_quote(&cwd),
+        assignments,
+        shell_quote(&program.display().to_string()),
+        eval_flag,
+        shell_quote(script),
+        input
+    )
+}
+
+/// The temporary file holding `document` in `InputMode::TempFile`, deleted when dropped.
+fn document_file(input_mode: InputMode, document: &str) -> std::io::Result<Option<temp::TempArtifact>> {
+    match input_mode {
+        InputMode::Stdin => Ok(None),
+        InputMode::TempFile => temp::TempArtifact::with_contents(None, "txt", document.as_bytes()).map(Some),
+    }
+}
+
+/// Quotes `arg` for POSIX shells, leaving plain words untouched.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "_-./=:,@%+".contains(c));
+    if plain {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// The markdown code blocks in `text` as `(info string, contents)` pairs, in order. A block whose
+/// closing fence is missing runs to the end of `text`.
+fn fenced_blocks(text: &str) -> Vec<(String, String)> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(String, Vec<&str>)> = None;
+    for line in text.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (open.take(), fence) {
+            (None, Some(info)) => open = Some((info.trim().to_string(), Vec::new())),
+            (None, None) => {}
+            (Some((tag, lines)), Some(_)) => blocks.push((tag, lines.join("\n"))),
+            (Some((tag, mut lines)), None) => {
+                lines.push(line);
+                open = Some((tag, lines));
             }
         }
-        
-        unreachable!("Should have returned or failed within the retry loop")
+    }
+    // A fence on the last line opens nothing; it's a stray closing fence.
+    if let Some((tag, lines)) = open.filter(|(_, lines)| !lines.is_empty()) {
+        blocks.push((tag, lines.join("\n")));
+    }
+    blocks
+}
+
+/// How many times to widen the prompt excerpt: once per failed attempt whose script might have
+/// failed because the data it needed was cut from the excerpt. Generation failures don't count,
+/// since the model never produced a script.
+fn excerpt_widenings(attempts: &[ParseAttempt]) -> usize {
+    attempts
+        .iter()
+        .filter(|attempt| !attempt.success && attempt.failure_category != Some(FailureCategory::Generation))
+        .count()
+}
+
+/// Runs `future` to completion, or returns `None` if `deadline` passes first. Dropping the future
+/// kills a running script subprocess.
+async fn within_deadline<F: Future>(deadline: Option<(Duration, tokio::time::Instant)>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some((_, at)) => tokio::time::timeout_at(at, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// The error ending a parse whose total deadline passed after `attempts` completed.
+/// Displayed when `max_retries` is zero, so the retry loop never runs.
+const NO_ATTEMPTS: &str = "No parse attempts were made because max_retries is 0";
+
+/// The error for a retry loop that ended without a successful attempt.
+fn retries_exhausted(attempts: &[ParseAttempt], summary: String) -> anyhow::Error {
+    ParseError::RetriesExhausted { attempts: attempts.to_vec(), summary }.into()
+}
+
+fn deadline_exceeded(deadline: Option<(Duration, tokio::time::Instant)>, attempts: &[ParseAttempt]) -> anyhow::Error {
+    let deadline = deadline.map_or(Duration::ZERO, |(limit, _)| limit);
+    ParseError::DeadlineExceeded { deadline, attempts: attempts.len() }.into()
+}
+
+/// Removes a leading `#!` line, which is meaningless under `python3 -c` and confuses some shells.
+/// An `if __name__ == "__main__":` guard needs no handling since `-c` runs as `__main__`.
+fn strip_shebang(script: &str) -> &str {
+    let trimmed = script.trim_start();
+    if trimmed.starts_with("#!") {
+        debug!("Stripping shebang line from generated script");
+        return trimmed.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+    script
+}
+
+/// Python prologue that interrupts the script after `timeout`, printing a JSON error report on
+/// stdout and exiting with `INLINE_TIMEOUT_EXIT_CODE`. Relies on `SIGALRM`, so Unix only.
+fn inline_timeout_guard(timeout: Duration) -> String {
+    let seconds = timeout.as_secs_f64();
+    format!(
+        r#"import json as _dp_json, os as _dp_os, signal as _dp_signal, sys as _dp_sys
+def _dp_on_timeout(signum, frame):
+    _dp_sys.stdout.write("\n" + _dp_json.dumps({{"error": "timeout", "message": "Script exceeded its inline timeout of {seconds}s"}}) + "\n")
+    _dp_sys.stdout.flush()
+    _dp_os._exit({code})
+_dp_signal.signal(_dp_signal.SIGALRM, _dp_on_timeout)
+_dp_signal.setitimer(_dp_signal.ITIMER_REAL, {seconds})"#,
+        seconds = seconds,
+        code = INLINE_TIMEOUT_EXIT_CODE,
+    )
+}
+
+/// Reads `reader` to the end, keeping at most `limit` bytes and returning how many were discarded.
+/// Reading continues past the limit so the writer never blocks on a full pipe.
+async fn read_capped(mut reader: impl AsyncRead + Unpin, limit: usize) -> std::io::Result<(Vec<u8>, usize)> {
+    let mut kept = Vec::new();
+    let mut dropped = 0;
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok((kept, dropped));
+        }
+        let take = limit.saturating_sub(kept.len()).min(read);
+        kept.extend_from_slice(&buffer[..take]);
+        dropped += read - take;
+    }
+}
+
+/// Like `read_capped`, but also passes each complete line read to `on_line` as it arrives.
+async fn read_capped_lines(reader: impl AsyncRead + Unpin, limit: usize, on_line: impl Fn(&str)) -> std::io::Result<(Vec<u8>, usize)> {
+    let mut reader = tokio::io::BufReader::new(reader);
+    let mut kept = Vec::new();
+    let mut dropped = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).await?;
+        if read == 0 {
+            return Ok((kept, dropped));
+        }
+        on_line(&String::from_utf8_lossy(&line));
+        let take = limit.saturating_sub(kept.len()).min(read);
+        kept.extend_from_slice(&line[..take]);
+        dropped += read - take;
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::sync::OnceLock;
+    use async_trait::async_trait;
+    use crate::testing::ScriptedGenerator;
+    use std::sync::{Arc, Mutex, OnceLock};
 
     // This static variable will ensure the initialization logic is run only once.
     static TRACING: OnceLock<()> = OnceLock::new();
@@ -450,11 +1840,2679 @@ mod test {
         });
     }
 
+    const ECHO_OK_SCRIPT: &str = "import json\nprint(json.dumps({\"ok\": True}))";
+
     #[tokio::test]
-    async fn test_successful_parse() {
-        // Call the setup function at the beginning of each test.
+    async fn test_per_call_interpreter_override() {
         setup_tracing();
-        
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .with_python_path("/nonexistent/python-interpreter")
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        assert!(client.dynamic_parse("doc", "Extract anything.").await.is_err());
+
+        let result = client
+            .dynamic_parse_with_interpreter("doc", "Extract anything.", "python3")
+            .await
+            .expect("Per-call interpreter should be used");
+        assert_eq!(result.trim(), r#"{"ok": true}"#);
+    }
+
+    #[tokio::test]
+    async fn test_missing_interpreter_is_named_in_error() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .with_python_path("/nonexistent/python-interpreter")
+            .with_max_retries(1)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client.run_attempts("doc", "Extract anything.", &CallOptions::default()).await;
+        assert!(result.is_err());
+        let error = attempts[0].error.as_deref().unwrap_or_default();
+        assert!(error.contains("Interpreter not found at '/nonexistent/python-interpreter'"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_low_confidence_success_triggers_extra_attempt() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]).with_logprob(-4.0))
+            .with_confidence_threshold(-1.0)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client
+            .dynamic_parse_with_details("doc", "Extract anything.")
+            .await
+            .expect("Parse should succeed");
+
+        assert_eq!(result.trim(), r#"{"ok": true}"#);
+        assert_eq!(attempts.len(), 2);
+        assert!(!attempts[0].success);
+        assert!(attempts[0].error.as_deref().unwrap().contains("Low generation confidence"));
+        assert!(attempts[1].success);
+    }
+
+    #[tokio::test]
+    async fn test_zero_retries_returns_typed_error() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .with_max_retries(0)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let err = client
+            .dynamic_parse("doc", "Extract anything.")
+            .await
+            .expect_err("Zero retries should fail");
+        assert!(matches!(&err, ParseError::RetriesExhausted { attempts, .. } if attempts.is_empty()));
+        assert_eq!(err.to_string(), "No parse attempts were made because max_retries is 0");
+    }
+
+    #[tokio::test]
+    async fn test_per_call_max_retries_overrides_client() {
+        let generator = ScriptedGenerator::new(&["raise ValueError('boom')"]);
+        let client = ParserClientBuilder::for_testing()
+            .with_generator(generator.clone())
+            .with_executor(testing::FnExecutor::new(|script: &str, _: &str| {
+                Err(ParseError::NonZeroExit { code: 1, stderr: "ValueError: boom".to_string(), script: script.to_string() }.into())
+            }))
+            .with_max_retries(5)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let options = ParseOptions { max_retries: Some(2) };
+        client.dynamic_parse_with_options("doc", "Extract anything.", &options).await.expect_err("Every attempt fails");
+        assert_eq!(generator.prompts().len(), 2);
+
+        let error = client
+            .dynamic_parse_with_options("doc", "Extract anything.", &ParseOptions { max_retries: Some(0) })
+            .await
+            .expect_err("Zero retries should fail");
+        assert!(matches!(&error, ParseError::RetriesExhausted { attempts, .. } if attempts.is_empty()));
+        assert_eq!(generator.prompts().len(), 2);
+
+        client.dynamic_parse("doc", "Extract anything.").await.expect_err("Every attempt fails");
+        assert_eq!(generator.prompts().len(), 7, "the client's own limit applies without options");
+    }
+
+    #[tokio::test]
+    async fn test_parse_attempt_accessors() {
+        let client = ParserClientBuilder::for_testing()
+            .with_generator(ScriptedGenerator::new(&["raise ValueError('boom')", "print('{}')"]))
+            .with_executor(testing::FnExecutor::new(|script: &str, _: &str| {
+                if script.contains("raise") {
+                    return Err(ParseError::NonZeroExit { code: 1, stderr: "ValueError: boom".to_string(), script: script.to_string() }.into());
+                }
+                Ok(ScriptOutput { stdout: "{}".to_string(), stderr: String::new() })
+            }))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        let summary: Vec<(usize, &str, bool)> = attempts.iter().map(|a| (a.attempt_number(), a.script(), a.succeeded())).collect();
+        assert_eq!(summary, vec![(1, "raise ValueError('boom')", false), (2, "print('{}')", true)]);
+        assert!(attempts[0].error().is_some_and(|error| error.contains("ValueError: boom")));
+        assert_eq!(attempts[1].error(), None);
+    }
+
+    const PRODUCT_SCRIPT: &str = "import json\nprint(json.dumps({\"name\": \"Super Toaster\", \"price\": 49.99, \"tags\": [\"kitchen\"]}))";
+
+    async fn parse_product_as(serialization: Serialization) -> String {
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[PRODUCT_SCRIPT]))
+            .with_output_serialization(serialization)
+            .build()
+            .await
+            .expect("Failed to build client");
+        client.dynamic_parse("doc", "Extract the product.").await.expect("Parse should succeed")
+    }
+
+    #[tokio::test]
+    async fn test_output_serialization_yaml_and_toml_round_trip() {
+        setup_tracing();
+
+        let expected = serde_json::json!({"name": "Super Toaster", "price": 49.99, "tags": ["kitchen"]});
+
+        let yaml = parse_product_as(Serialization::Yaml).await;
+        let from_yaml: serde_json::Value = serde_yaml::from_str(&yaml).expect("Result should be YAML");
+        assert_eq!(from_yaml, expected);
+
+        let toml = parse_product_as(Serialization::Toml).await;
+        let from_toml: serde_json::Value = toml::from_str(&toml).expect("Result should be TOML");
+        assert_eq!(from_toml, expected);
+    }
+
+    #[tokio::test]
+    async fn test_explain_script_returns_summary() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["  Reads the document from stdin and prints {\"ok\": true} as JSON.\n"]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let explanation = client.explain_script(ECHO_OK_SCRIPT).await.expect("Explanation should succeed");
+        assert!(!explanation.is_empty());
+        assert!(explanation.starts_with("Reads the document"));
+    }
+
+    #[tokio::test]
+    async fn test_error_key_in_output_triggers_retry() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&[
+            "import json\nprint(json.dumps({\"error\": \"price not found\"}))",
+            ECHO_OK_SCRIPT,
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_error_key("error")
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client
+            .dynamic_parse_with_details("doc", "Extract the price.")
+            .await
+            .expect("Second attempt should succeed");
+
+        assert_eq!(result.trim(), r#"{"ok": true}"#);
+        assert_eq!(attempts.len(), 2);
+        let prompts = generator.prompts();
+        assert!(prompts[1].contains("price not found"));
+    }
+
+    #[tokio::test]
+    async fn test_binary_prompt_mode_renders_control_characters() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&[
+            "import sys, json\nprint(json.dumps({\"length\": len(sys.stdin.read())}))",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_binary_prompt_mode(BinaryMode::Hex)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let document = "head\u{1}\u{2}tail";
+        let result = client.dynamic_parse(document, "Count the characters.").await.expect("Parse should succeed");
+
+        let prompt = &generator.prompts()[0];
+        assert!(prompt.contains("00000000  68 65 61 64 01 02 74 61 69 6c"));
+        assert!(prompt.contains("Your script still receives the raw text on stdin."));
+        assert!(!prompt.contains(document));
+        assert_eq!(result.trim(), r#"{"length": 10}"#, "stdin should receive the raw document");
+
+        let file_client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_binary_prompt_mode(BinaryMode::Hex)
+            .with_input_mode(InputMode::TempFile)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let _ = file_client.generate_only(document, "Count the characters.").await;
+        assert!(generator.prompts()[1].contains("Your script still receives the raw text in the file named by its first argument."));
+    }
+
+    #[tokio::test]
+    async fn test_assert_golden_match_and_mismatch() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[PRODUCT_SCRIPT]))
+            .with_output_serialization(Serialization::Yaml)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let expected = serde_json::json!({"tags": ["kitchen"], "price": 49.99, "name": "Super Toaster"});
+        client.assert_golden("doc", "Extract the product.", &expected).await.expect("Golden should match");
+
+        let wrong = serde_json::json!({"name": "Super Toaster", "price": 39.99, "sku": "T-5000"});
+        let err = client
+            .assert_golden("doc", "Extract the product.", &wrong)
+            .await
+            .expect_err("Golden should not match");
+        let message = err.to_string();
+        assert!(message.contains("~ $.price: 39.99 -> 49.99"));
+        assert!(message.contains("- $.sku"));
+        assert!(message.contains("+ $.tags"));
+    }
+
+    async fn client_printing(json: &str) -> ParserClient {
+        let script = format!("print('{}')", json);
+        ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[&script]))
+            .build()
+            .await
+            .expect("Failed to build client")
+    }
+
+    #[tokio::test]
+    async fn test_ensemble_majority_wins() {
+        setup_tracing();
+
+        let first = client_printing(r#"{"name": "Toaster", "price": 49.99}"#).await;
+        let second = client_printing(r#"{"name": "Toaster", "price": 50}"#).await;
+        let third = client_printing(r#"{"price": 49.99,"name":"Toaster"}"#).await;
+
+        let result = first
+            .dynamic_parse_ensemble(&[&second, &third], "doc", "Extract the product.")
+            .await
+            .expect("Ensemble should succeed");
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(value, serde_json::json!({"name": "Toaster", "price": 49.99}));
+    }
+
+    #[tokio::test]
+    async fn test_max_valid_json_attempts_stops_early() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&["import json\nprint(json.dumps({\"error\": \"always wrong\"}))"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_error_key("error")
+            .with_max_valid_json_attempts(2)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let err = client
+            .dynamic_parse("doc", "Extract the price.")
+            .await
+            .expect_err("Rejected outputs should give up");
+        assert!(err.to_string().contains("Giving up after 2 attempts"));
+        assert_eq!(generator.prompts().len(), 2, "should stop well before the retry limit");
+    }
+
+    #[tokio::test]
+    async fn test_stdin_progress_reports_document_size() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        setup_tracing();
+
+        let reported = Arc::new(AtomicUsize::new(0));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (reported_handle, calls_handle) = (reported.clone(), calls.clone());
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[
+                "import sys, json\nprint(json.dumps({\"length\": len(sys.stdin.read())}))",
+            ]))
+            .with_stdin_progress(move |written| {
+                reported_handle.store(written, Ordering::SeqCst);
+                calls_handle.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let document = "x".repeat(1024 * 1024 + 17);
+        let result = client.dynamic_parse(&document, "Count the characters.").await.expect("Parse should succeed");
+
+        assert_eq!(result.trim(), format!(r#"{{"length": {}}}"#, document.len()));
+        assert_eq!(reported.load(Ordering::SeqCst), document.len());
+        assert!(calls.load(Ordering::SeqCst) > 1, "large documents should be written in several chunks");
+    }
+
+    /// A generator standing in for a disabled model backend.
+    struct UnavailableGenerator;
+
+    #[async_trait]
+    impl ScriptGenerator for UnavailableGenerator {
+        async fn generate(&self, _system_prompt: &str, _prompt: &str) -> Result<String> {
+            anyhow::bail!("model backend disabled")
+        }
+    }
+
+    /// A generator whose first call fails, recording when each call was made.
+    #[derive(Clone, Default)]
+    struct FlakyGenerator {
+        calls: Arc<Mutex<Vec<std::time::Instant>>>,
+    }
+
+    #[async_trait]
+    impl ScriptGenerator for FlakyGenerator {
+        async fn generate(&self, _system_prompt: &str, _prompt: &str) -> Result<String> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(std::time::Instant::now());
+            if calls.len() == 1 {
+                anyhow::bail!("CUDA out of memory");
+            }
+            Ok(ECHO_OK_SCRIPT.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attempt_callback_sees_generation_and_execution_failures() {
+        setup_tracing();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let client = ParserClient::builder()
+            .with_generator(FlakyGenerator::default())
+            .with_attempt_callback(move |attempt| {
+                sink.lock().unwrap().push((attempt.attempt_number(), attempt.succeeded(), attempt.failure_category()))
+            })
+            .build()
+            .await
+            .expect("Failed to build client");
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(*seen.lock().unwrap(), vec![(1, false, Some(FailureCategory::Generation)), (2, true, None)]);
+
+        seen.lock().unwrap().clear();
+        let sink = seen.clone();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["raise SystemExit(3)", ECHO_OK_SCRIPT]))
+            .with_attempt_callback(move |attempt| {
+                sink.lock().unwrap().push((attempt.attempt_number(), attempt.succeeded(), attempt.failure_category()))
+            })
+            .build()
+            .await
+            .expect("Failed to build client");
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(*seen.lock().unwrap(), vec![(1, false, Some(FailureCategory::RuntimeError)), (2, true, None)]);
+    }
+
+    #[tokio::test]
+    async fn test_script_cache_skips_generation_and_falls_back() {
+        setup_tracing();
+        let named = "import json\nprint(json.dumps({\"name\": \"Toaster\"}))";
+        let generator = ScriptedGenerator::new(&[ECHO_OK_SCRIPT, ECHO_OK_SCRIPT, named]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_script_cache(true)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        let (cached, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Cached parse should succeed");
+        assert_eq!(cached.trim(), r#"{"ok": true}"#);
+        assert_eq!(attempts[0].script(), ECHO_OK_SCRIPT);
+        assert_eq!(generator.prompts().len(), 1, "a cache hit should skip generation");
+
+        client.dynamic_parse("other doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(generator.prompts().len(), 2, "a different document should miss the cache");
+
+        let schema = serde_json::json!({"type": "object", "required": ["name"]});
+        let result = client.dynamic_parse_with_schema("doc", "Extract anything.", &schema).await.expect("Fallback should succeed");
+        assert!(result.contains("Toaster"), "a failing cached script should fall back to generation");
+        assert_eq!(generator.prompts().len(), 3);
+
+        client.clear_cache();
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(generator.prompts().len(), 4, "a cleared cache should regenerate");
+    }
+
+    #[tokio::test]
+    async fn test_generate_and_run_script_separately() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["```python\nimport sys\nsys.exit(2)\n```", "import sys\nsys.exit(2)", ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let script = client.generate_script("doc", "Extract anything.", &[]).await.expect("Generation should succeed");
+        assert_eq!(script, "import sys\nsys.exit(2)");
+        let error = client.run_script(&script, "doc").await.expect_err("The script exits non-zero");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::NonZeroExit { code: 2, .. })));
+
+        let edited = script.replace("sys.exit(2)", "print('[1, 2]')");
+        assert_eq!(client.run_script(&edited, "doc").await.expect("Edited script should run"), "[1, 2]\n");
+
+        let (_, attempts) = client.run_attempts("doc", "Extract anything.", &CallOptions { max_retries: Some(1), ..Default::default() }).await;
+        client.generate_script("doc", "Extract anything.", &attempts).await.expect("Generation should succeed");
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("Extract anything.") && !prompts[0].contains("sys.exit(2)"));
+        assert!(prompts[2].contains("sys.exit(2)"), "prior attempts should be fed back");
+    }
+
+    #[tokio::test]
+    async fn test_generate_only_returns_script_without_running_it() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["```python\nprint('ok')\n```", "I can't parse this document."]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        assert_eq!(client.generate_only("doc", "Extract anything.").await.expect("Generation should succeed"), "print('ok')");
+        assert_eq!(
+            client.generate_only("doc", "Extract anything.").await.expect("Prose is returned, not rejected"),
+            "I can't parse this document."
+        );
+        assert!(generator.prompts().iter().all(|p| !p.contains("Previous Attempts")), "each call is a first attempt");
+    }
+
+    #[tokio::test]
+    async fn test_attempts_capture_stderr() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[
+                "import sys\nprint('bad input', file=sys.stderr)\nsys.exit(1)",
+                "import sys\nprint('DeprecationWarning: old API', file=sys.stderr)\nprint('{}')",
+            ]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Second attempt should succeed");
+        assert_eq!(attempts[0].stderr().map(str::trim), Some("bad input"));
+        assert_eq!(attempts[1].stderr().map(str::trim), Some("DeprecationWarning: old API"));
+        assert!(attempts[1].succeeded());
+    }
+
+    #[tokio::test]
+    async fn test_total_deadline_interrupts_running_script() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["import sys\nsys.exit(1)", "import time\ntime.sleep(10)\nprint('{}')"]))
+            .with_total_deadline(Duration::from_millis(1500))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let start = Instant::now();
+        let error = client.dynamic_parse("doc", "Extract anything.").await.expect_err("The second script outlives the deadline");
+        assert!(start.elapsed() < Duration::from_secs(5), "the sleeping script should be interrupted");
+        assert!(matches!(error, ParseError::DeadlineExceeded { attempts: 1, .. }));
+    }
+
+    #[test]
+    fn test_non_zero_exit_message_is_language_neutral() {
+        let error = ParseError::NonZeroExit { code: 1, stderr: "ReferenceError: x is not defined".to_string(), script: "x".to_string() };
+        assert!(error.to_string().starts_with("Script execution failed with exit code: 1"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_compiled_parser_reuses_script() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&[
+            "import sys, json\ndocument = sys.stdin.read()\nassert 'x' not in document\nprint(json.dumps({'length': len(document)}))",
+            ECHO_OK_SCRIPT,
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let mut parser = client.compile("abc", "Count the characters.").await.expect("Compilation should succeed");
+        assert_eq!(parser.parse("hello").await.expect("Compiled script should run").trim(), r#"{"length": 5}"#);
+        assert_eq!(parser.parse("hi").await.expect("Compiled script should run").trim(), r#"{"length": 2}"#);
+        assert_eq!(generator.prompts().len(), 1, "parsing with a compiled script shouldn't invoke the model");
+
+        parser.parse("xyz").await.expect_err("The compiled script rejects documents containing x");
+        parser.recompile("xyz").await.expect("Recompilation should succeed");
+        assert_eq!(parser.parse("xyz").await.expect("Recompiled script should run").trim(), r#"{"ok": true}"#);
+    }
+
+    #[tokio::test]
+    async fn test_parse_metrics_report_timings_and_sizes() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["import sys\nsys.exit(1)", ECHO_OK_SCRIPT]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, metrics) = client.dynamic_parse_with_metrics("document", "Extract anything.").await;
+        let result = result.expect("Second attempt should succeed");
+        assert!(metrics.success);
+        assert_eq!(metrics.attempt_count(), 2);
+        assert_eq!(metrics.attempts[0].category, Some(FailureCategory::RuntimeError));
+        assert!(metrics.attempts[1].success && metrics.attempts[1].execution_time.is_some());
+        assert_eq!((metrics.document_bytes, metrics.result_bytes), (8, Some(result.len())));
+        assert!(metrics.total_time >= metrics.attempts.iter().filter_map(|attempt| attempt.execution_time).sum());
+    }
+
+    #[tokio::test]
+    async fn test_params_reach_script_as_env() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["import os, json\nprint(json.dumps({'currency': os.environ['PARAM_TARGET_CURRENCY']}))"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let params = HashMap::from([("target-currency".to_string(), "EUR\0\n".to_string())]);
+        let result = client.dynamic_parse_with_params("doc", "Extract prices.", &params).await.expect("Parse should succeed");
+        assert_eq!(result.trim(), r#"{"currency": "EUR"}"#);
+        let prompt = &generator.prompts()[0];
+        assert!(prompt.contains("environment variables: PARAM_TARGET_CURRENCY."));
+        assert!(!prompt.contains("EUR"), "values shouldn't be baked into the prompt");
+
+        let generator = ScriptedGenerator::new(&["import os, json\nprint(json.dumps({'currency': os.environ['PARAM_TARGET_CURRENCY']}))"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_import_allowlist(DEFAULT_IMPORT_ALLOWLIST.iter().copied())
+            .build()
+            .await
+            .expect("Failed to build client");
+        let result = client.dynamic_parse_with_params("doc", "Extract prices.", &params).await.expect("os is allowed with params");
+        assert_eq!(result.trim(), r#"{"currency": "EUR"}"#);
+        assert!(generator.prompts()[0].contains("Import only these modules: sys, json, re, os."));
+
+        let colliding = HashMap::from([("a-b".to_string(), "1".to_string()), ("a_b".to_string(), "2".to_string())]);
+        let error = client.dynamic_parse_with_params("doc", "Extract prices.", &colliding).await.expect_err("Names collide");
+        assert!(error.to_string().contains(r#""a-b" and "a_b" both map to PARAM_A_B"#), "{}", error);
+
+        let client = ParserClient::builder()
+            .with_generator(UnavailableGenerator)
+            .with_executor(FakeExecutor::new(&[Ok("{}")]))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let error = client.dynamic_parse_with_params("doc", "Extract prices.", &params).await.expect_err("Executors can't receive params");
+        assert!(error.to_string().contains("custom executor"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_hand_written_script_is_validated_without_model() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(UnavailableGenerator)
+            .with_validator(|output| if output.contains("price") { Ok(()) } else { Err("no price".to_string()) })
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let script = "import sys, json\nprint(json.dumps({'price': float(sys.stdin.read().strip('$'))}))";
+        assert_eq!(client.run_script(script, "$5").await.expect("Hand-written script should run").trim(), r#"{"price": 5.0}"#);
+        let error = client.run_script("print('{\"name\": 1}')", "$5").await.expect_err("Validators apply to hand-written scripts");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::OutputRejected(_))));
+        let error = client.run_script("print('not json')", "$5").await.expect_err("Output must be JSON");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::InvalidJson { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_csv_and_raw_output_formats() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["print('name,price\\nToaster')", "print('name,price\\n\"Toaster, 2-slot\",49.99')"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_output_format(OutputFormat::Csv)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let result = client.dynamic_parse("doc", "List the products.").await.expect("Second attempt prints valid CSV");
+        assert_eq!(result, "name,price\n\"Toaster, 2-slot\",49.99\n");
+        assert!(generator.prompts()[1].contains("row 2 has 1 fields but the header has 2"));
+        assert!(client.get_system_prompt(ScriptLanguage::Python).contains("OUTPUT FORMAT:\nThe rules above about JSON do not apply"));
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('Toaster costs 49.99')"]))
+            .with_output_format(OutputFormat::Raw)
+            .build()
+            .await
+            .expect("Failed to build client");
+        assert_eq!(client.dynamic_parse("doc", "Summarize.").await.expect("Raw output needs only to be non-empty"), "Toaster costs 49.99\n");
+    }
+
+    #[tokio::test]
+    async fn test_batch_parses_concurrently_in_order() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[
+                "import sys, json, time\ndocument = sys.stdin.read()\ntime.sleep(0.3)\nassert document != 'bad'\nprint(json.dumps({'length': len(document)}))",
+            ]))
+            .with_max_retries(1)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let items = [("a", "Count."), ("bad", "Count."), ("ccc", "Count."), ("dddd", "Count.")];
+        let start = Instant::now();
+        let results = client.dynamic_parse_batch(&items, 2).await;
+        assert!(start.elapsed() >= Duration::from_millis(600), "at most two parses should run at once");
+        let lengths: Vec<Option<String>> = results.into_iter().map(|result| result.ok().map(|r| r.trim().to_string())).collect();
+        assert_eq!(
+            lengths,
+            [Some(r#"{"length": 1}"#.to_string()), None, Some(r#"{"length": 3}"#.to_string()), Some(r#"{"length": 4}"#.to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compiled_parser_saves_and_loads() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["import sys, json\nprint(json.dumps({'length': len(sys.stdin.read())}))"]))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let path = std::env::temp_dir().join(format!("dyn-parse-compiled-{}.json", std::process::id()));
+        let parser = client.compile("abc", "Count the characters.").await.expect("Compilation should succeed");
+        parser.save(&path).await.expect("Saving should succeed");
+
+        let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["language"], "Python");
+        assert_eq!(saved["instructions"], "Count the characters.");
+
+        let offline = ParserClient::builder().without_model().build().await.expect("No model should be needed");
+        let loaded = CompiledParser::load(&offline, &path).await.expect("Loading should succeed");
+        assert_eq!(loaded.script(), parser.script());
+        assert_eq!(loaded.parse("hello").await.expect("Loaded script should run").trim(), r#"{"length": 5}"#);
+
+        let missing = ParserClient::builder().without_model().with_python_path("/nonexistent/python3").build().await.unwrap();
+        let error = CompiledParser::load(&missing, &path).await.err().expect("The runtime isn't installed");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::InterpreterNotFound { .. })));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_self_verification_rejects_wrong_output() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["print('{\"price\": null}')", "No, the price is missing.", "print('{\"price\": 5}')", "Yes."]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_self_verification(true)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client.dynamic_parse_with_details("Price: $5", "Extract the price.").await.expect("Second attempt passes the check");
+        assert_eq!(result.trim(), r#"{"price": 5}"#);
+        assert_eq!(attempts.iter().map(ParseAttempt::verified).collect::<Vec<_>>(), [Some(false), Some(true)]);
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::OutputRejected));
+        let prompts = generator.prompts();
+        assert!(prompts[1].contains("**Output:**\n{\"price\": null}") && prompts[1].contains("Answer yes or no."));
+        assert!(prompts[2].contains("Self-check failed"));
+    }
+
+    #[tokio::test]
+    async fn test_preprocessor_only_affects_prompt() {
+        setup_tracing();
+        let html = "<html><head><style>p { color: red }</style></head><body><p>Price: <b>$5</b></p><table><tr><td>A &amp; B</td><td>2</td></tr></table></body></html>";
+        assert_eq!(strip_html_tags(html), "Price: $5\nA & B | 2");
+
+        let generator = ScriptedGenerator::new(&["import sys, json\nprint(json.dumps({'length': len(sys.stdin.read())}))"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_preprocessor(strip_html_tags)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let result = client.dynamic_parse(html, "Extract the price.").await.expect("Parse should succeed");
+        assert_eq!(result.trim(), format!(r#"{{"length": {}}}"#, html.len()), "the script should get the raw document");
+        let prompt = &generator.prompts()[0];
+        assert!(prompt.contains("---\nPrice: $5\nA & B | 2\n---") && !prompt.contains("<b>"));
+        assert!(prompt.contains("Your script receives the original, unprocessed document"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_predicate_stops_early() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .with_python_path("/nonexistent/python-interpreter")
+            .with_max_retries(3)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let (result, attempts) = client.run_attempts("doc", "Extract anything.", &CallOptions::default()).await;
+        assert_eq!(attempts.len(), 1, "a missing interpreter isn't retried by default");
+        let error = result.expect_err("The interpreter is missing");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::InterpreterNotFound { .. })));
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('not json')"]))
+            .with_retry_predicate(|error| !matches!(error, ParseError::InvalidJson { .. }))
+            .with_max_retries(3)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let (result, attempts) = client.run_attempts("doc", "Extract anything.", &CallOptions::default()).await;
+        assert_eq!(attempts.len(), 1);
+        assert!(matches!(result.unwrap_err().downcast_ref::<ParseError>(), Some(ParseError::InvalidJson { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_boxed_generator_drives_parse() {
+        setup_tracing();
+        struct RemoteModel;
+
+        #[async_trait]
+        impl ScriptGenerator for RemoteModel {
+            async fn generate(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+                assert!(!system_prompt.is_empty() && prompt.contains("Extract anything."));
+                Ok(ECHO_OK_SCRIPT.to_string())
+            }
+        }
+
+        let generator: Box<dyn ScriptGenerator> = Box::new(RemoteModel);
+        let client = ParserClient::builder().with_generator(generator).build().await.expect("Failed to build client");
+        assert_eq!(client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed").trim(), r#"{"ok": true}"#);
+
+        let client = ParserClient::with_generator(Box::new(RemoteModel)).await.expect("Failed to build client");
+        assert_eq!(client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed").trim(), r#"{"ok": true}"#);
+    }
+
+    #[tokio::test]
+    async fn test_failures_downcast_to_parse_error() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('not json')"]))
+            .with_max_retries(2)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let error = client.dynamic_parse("doc", "Extract anything.").await.expect_err("Every attempt prints invalid JSON");
+        assert!(error.to_string().starts_with("All 2 parsing attempts failed"));
+        let ParseError::RetriesExhausted { attempts, .. } = &error else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts.iter().all(|attempt| attempt.failure_category() == Some(FailureCategory::InvalidJson)));
+
+        let client = ParserClient::builder()
+            .with_generator(UnavailableGenerator)
+            .with_max_retries(1)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let error = client.generate_script("doc", "Extract anything.", &[]).await.expect_err("The model is unavailable");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::Generation(_))));
+        let error = client.dynamic_parse("doc", "Extract anything.").await.expect_err("The model is unavailable");
+        assert!(matches!(&error, ParseError::RetriesExhausted { attempts, .. } if attempts.len() == 1));
+        let error = client.run_script("import sys\nsys.stdout.buffer.write(b'\\xff\\n')", "doc").await.expect_err("Output isn't UTF-8");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::InvalidOutput(_))), "{}", error);
+        assert_eq!(FailureCategory::of(&error), FailureCategory::InvalidJson);
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .with_python_path(std::env::temp_dir())
+            .build()
+            .await
+            .expect("Failed to build client");
+        let error = client.run_script(ECHO_OK_SCRIPT, "doc").await.expect_err("A directory can't be run");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::SpawnFailed { .. })), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_code_fences_are_stripped() {
+        setup_tracing();
+        let client = client_printing("{}").await;
+        let python = ScriptLanguage::Python;
+        let cases = [
+            ("Here is the script:\n```python\nprint(1)\n```\nIt prints 1.", "print(1)"),
+            ("```Python\nprint(1)\n```", "print(1)"),
+            ("```\nprint(1)\n```", "print(1)"),
+            ("```json\n{\"a\": 1}\n```\n```py\nprint(1)\n```", "print(1)"),
+            ("Sure!\n```python\nimport sys\nprint(1)", "import sys\nprint(1)"),
+            ("print(1)\n```", "print(1)"),
+        ];
+        for (response, expected) in cases {
+            assert_eq!(client.extract_code(response, python).as_deref(), Some(expected), "{:?}", response);
+        }
+        assert_eq!(client.extract_code("print(1)", python), None);
+    }
+
+    #[tokio::test]
+    async fn test_temp_file_input_mode() {
+        setup_tracing();
+        let script = "import sys, json\npath = sys.argv[1]\nprint(json.dumps({'path': path, 'size': len(open(path).read())}))";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .with_input_mode(InputMode::TempFile)
+            .build()
+            .await
+            .expect("Failed to build client");
+        assert!(client.get_system_prompt(ScriptLanguage::Python).contains("sys.argv[1]"));
+
+        let document = "x".repeat(4 * 1024 * 1024);
+        let (result, attempts) = client.dynamic_parse_with_details(&document, "Extract anything.").await.expect("Parse should succeed");
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(value["size"].as_u64(), Some(document.len() as u64));
+        assert!(!Path::new(value["path"].as_str().unwrap()).exists(), "the temp file should be removed after the run");
+        assert!(attempts[0].command().unwrap().ends_with(" document"));
+
+        let error = client
+            .run_script("import sys\nprint(sys.argv[1], file=sys.stderr)\nsys.exit(1)", "doc")
+            .await
+            .expect_err("The script fails");
+        let Some(ParseError::NonZeroExit { stderr, .. }) = error.downcast_ref::<ParseError>() else {
+            panic!("unexpected error: {}", error);
+        };
+        assert!(!Path::new(stderr.trim()).exists(), "the temp file should be removed after a failed run");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_resource_limits_fail_attempts_with_a_hint() {
+        setup_tracing();
+        let hog = "data = bytearray(2 * 1024 * 1024 * 1024)\nprint('{}')";
+        let generator = ScriptedGenerator::new(&[hog, ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_memory_limit(512 * 1024 * 1024)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::ResourceLimit));
+        assert!(generator.prompts()[1].contains("used too much memory (limit: 512 MiB)"));
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["while True:\n    pass"]))
+            .with_cpu_limit(Duration::from_secs(1))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let error = client.run_script("while True:\n    pass", "doc").await.expect_err("The script spins forever");
+        assert!(matches!(error.downcast_ref::<ParseError>(), Some(ParseError::CpuLimitExceeded { .. })), "{}", error);
+
+        use std::os::unix::process::ExitStatusExt;
+        let limits = rlimit::ResourceLimits { memory_bytes: None, cpu: Some(Duration::from_secs(1)) };
+        assert!(limits.exceeded(std::process::ExitStatus::from_raw(libc::SIGKILL), "").is_none());
+    }
+
+    /// Streams its script one line at a time.
+    struct LineStreamingGenerator;
+
+    #[async_trait]
+    impl ScriptGenerator for LineStreamingGenerator {
+        async fn generate(&self, _system_prompt: &str, _prompt: &str) -> Result<String> {
+            Ok(ECHO_OK_SCRIPT.to_string())
+        }
+
+        async fn generate_streaming(&self, _system_prompt: &str, _prompt: &str, on_token: &TokenCallback) -> Result<String> {
+            for line in ECHO_OK_SCRIPT.split_inclusive('\n') {
+                on_token(line);
+            }
+            Ok(ECHO_OK_SCRIPT.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_sink_receives_streamed_generation() {
+        setup_tracing();
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let sink = tokens.clone();
+        let client = ParserClient::builder()
+            .with_generator(LineStreamingGenerator)
+            .with_token_sink(move |token| sink.lock().unwrap().push(token.to_string()))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(result.trim(), r#"{"ok": true}"#);
+        let tokens = tokens.lock().unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens.concat(), ECHO_OK_SCRIPT);
+    }
+
+    #[tokio::test]
+    async fn test_custom_system_prompt_and_rules() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .add_system_rule("Never print personal data.")
+            .build()
+            .await
+            .expect("Failed to build client");
+        let prompt = client.get_system_prompt(ScriptLanguage::Python);
+        assert!(prompt.starts_with(ScriptLanguage::Python.system_prompt(InputMode::Stdin).trim_end()));
+        assert!(prompt.ends_with("ADDITIONAL RULES:\n- Never print personal data.\n"));
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .with_system_prompt("Write Python that may use lxml.")
+            .add_system_rule("Wrap results in {\"data\": ...}.")
+            .add_system_rule("Use UTF-8.")
+            .build()
+            .await
+            .expect("Failed to build client");
+        assert_eq!(
+            client.get_system_prompt(ScriptLanguage::JavaScript),
+            "Write Python that may use lxml.\n\nADDITIONAL RULES:\n- Wrap results in {\"data\": ...}.\n- Use UTF-8.\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_model_error_cooldown_delays_next_generation() {
+        setup_tracing();
+        let generator = FlakyGenerator::default();
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_model_error_cooldown(Duration::from_millis(300))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        let calls = generator.calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert!(calls[1] - calls[0] >= Duration::from_millis(300), "cooldown not observed: {:?}", calls[1] - calls[0]);
+    }
+
+    #[tokio::test]
+    async fn test_model_error_cooldown_respects_total_deadline() {
+        setup_tracing();
+        let generator = FlakyGenerator::default();
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_model_error_cooldown(Duration::from_secs(10))
+            .with_total_deadline(Duration::from_millis(300))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let start = Instant::now();
+        let error = client.dynamic_parse("doc", "Extract anything.").await.expect_err("The cooldown outlives the deadline");
+        assert!(start.elapsed() < Duration::from_secs(2), "the cooldown should be cut short: {:?}", start.elapsed());
+        assert!(matches!(error, ParseError::DeadlineExceeded { attempts: 1, .. }));
+        assert_eq!(generator.calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_backoff_delays_attempts() {
+        setup_tracing();
+        let backoff = backoff::Backoff { base: Duration::from_millis(100), multiplier: 2.0, jitter: 0.0 };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        let jittered = backoff::Backoff { jitter: 0.5, ..backoff };
+        for _ in 0..20 {
+            let delay = jittered.delay(2);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(300), "{:?}", delay);
+        }
+
+        let generator = FlakyGenerator::default();
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_retry_backoff(Duration::from_millis(250), 2.0)
+            .build()
+            .await
+            .expect("Failed to build client");
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        let calls = generator.calls.lock().unwrap();
+        assert!(calls[1] - calls[0] >= Duration::from_millis(250), "backoff not observed: {:?}", calls[1] - calls[0]);
+    }
+
+    #[tokio::test]
+    async fn test_validators_reject_output_and_feed_back() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&[
+            "print('{\"price\": 0}')",
+            "print('{\"price\": 4.5}')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_validator(|output| {
+                let value: serde_json::Value = serde_json::from_str(output).map_err(|e| e.to_string())?;
+                match value["price"].as_f64() {
+                    Some(price) if price > 0.0 => Ok(()),
+                    _ => Err("price must be a number greater than 0".to_string()),
+                }
+            })
+            .with_validator(|output| match output.contains("price") {
+                true => Ok(()),
+                false => Err("missing price".to_string()),
+            })
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client.dynamic_parse_with_details("doc", "Extract the price.").await.expect("Parse should succeed");
+        assert_eq!(result.trim(), r#"{"price": 4.5}"#);
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::OutputRejected));
+        assert!(generator.prompts()[1].contains("price must be a number greater than 0"));
+    }
+
+    #[tokio::test]
+    async fn test_cassette_records_then_replays() {
+        setup_tracing();
+
+        let path = std::env::temp_dir().join(format!("dyn-parse-cassette-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let recording = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[PRODUCT_SCRIPT]))
+            .with_cassette(&path)
+            .build()
+            .await
+            .expect("Failed to build recording client");
+        let recorded = recording.dynamic_parse("doc", "Extract the product.").await.expect("Recording parse should succeed");
+        assert!(path.exists());
+
+        let replaying = ParserClient::builder()
+            .with_generator(UnavailableGenerator)
+            .with_cassette(&path)
+            .build()
+            .await
+            .expect("Failed to build replaying client");
+        let replayed = replaying.dynamic_parse("doc", "Extract the product.").await.expect("Replay should not need the model");
+
+        assert_eq!(recorded, replayed);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_failure_categories_are_recorded() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["def broken(:\n    pass", "import sys", ECHO_OK_SCRIPT]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client
+            .dynamic_parse_with_details("doc", "Extract anything.")
+            .await
+            .expect("Third attempt should succeed");
+
+        let categories: Vec<_> = attempts.iter().map(ParseAttempt::failure_category).collect();
+        assert_eq!(
+            categories,
+            vec![Some(FailureCategory::SyntaxError), Some(FailureCategory::EmptyOutput), None]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_comparator_tolerates_float_jitter() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('{\"price\": 49.990000001, \"name\": \"Toaster\"}')"]))
+            .with_json_comparator(|a, b| json_approx_eq(a, b, 1e-6))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let expected = serde_json::json!({"name": "Toaster", "price": 49.99});
+        client
+            .assert_golden("doc", "Extract the product.", &expected)
+            .await
+            .expect("Epsilon comparator should treat the prices as equal");
+
+        let strict = client_printing(r#"{"price": 49.990000001, "name": "Toaster"}"#).await;
+        assert!(strict.assert_golden("doc", "Extract the product.", &expected).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_script_preamble_is_prepended() {
+        setup_tracing();
+
+        let preamble = "import sys, json\nDOCUMENT = sys.stdin.read()\ndef emit(result):\n    print(json.dumps(result))";
+        let generator = ScriptedGenerator::new(&["emit({\"length\": len(DOCUMENT)})"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_script_preamble(preamble)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("hello", "Count the characters.").await.expect("Parse should succeed");
+
+        assert_eq!(result.trim(), r#"{"length": 5}"#);
+        assert!(generator.prompts()[0].contains(preamble));
+    }
+
+    #[tokio::test]
+    async fn test_noisy_script_does_not_deadlock() {
+        setup_tracing();
+
+        let script = "import sys, json\n\
+                      sys.stderr.write('e' * 2_000_000)\n\
+                      sys.stdout.write(' ' * 2_000_000)\n\
+                      data = sys.stdin.read()\n\
+                      print(json.dumps({\"length\": len(data)}))";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .with_max_stderr_bytes(1024)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let document = "d".repeat(2_000_000);
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(30),
+            client.dynamic_parse(&document, "Count the characters."),
+        )
+        .await
+        .expect("Execution deadlocked")
+        .expect("Parse should succeed");
+
+        assert_eq!(result.trim(), r#"{"length": 2000000}"#);
+    }
+
+    /// A fake executor that replays canned outputs in order, repeating the last one.
+    /// `Err` entries are reported as script failures.
+    struct FakeExecutor {
+        outputs: Vec<Result<&'static str, &'static str>>,
+        calls: Mutex<usize>,
+    }
+
+    impl FakeExecutor {
+        fn new(outputs: &[Result<&'static str, &'static str>]) -> Self {
+            Self { outputs: outputs.to_vec(), calls: Mutex::new(0) }
+        }
+    }
+
+    #[async_trait]
+    impl ScriptExecutor for FakeExecutor {
+        async fn execute(&self, _script: &str, _document: &str) -> Result<ScriptOutput> {
+            let mut calls = self.calls.lock().unwrap();
+            let output = self.outputs[(*calls).min(self.outputs.len() - 1)];
+            *calls += 1;
+            match output {
+                Ok(stdout) => Ok(ScriptOutput { stdout: stdout.to_string(), stderr: String::new() }),
+                Err(stderr) => Err(ParseError::NonZeroExit { code: 1, stderr: stderr.to_string(), script: String::new() }.into()),
+            }
+        }
+    }
+
+    async fn client_with_executor(executor: FakeExecutor) -> ParserClient {
+        ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('unused')"]))
+            .with_executor(executor)
+            .with_max_retries(3)
+            .build()
+            .await
+            .expect("Failed to build client")
+    }
+
+    #[tokio::test]
+    async fn test_language_fallback_switches_prompt_and_executor() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&["print('unused')"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_language_fallback(vec![ScriptLanguage::Python, ScriptLanguage::JavaScript])
+            .with_language_executor(ScriptLanguage::Python, FakeExecutor::new(&[Err("Traceback: boom")]))
+            .with_language_executor(ScriptLanguage::JavaScript, FakeExecutor::new(&[Ok(r#"{"ok": true}"#)]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("JavaScript attempt should succeed");
+        assert_eq!(result, r#"{"ok": true}"#);
+        assert_eq!(attempts.iter().map(ParseAttempt::language).collect::<Vec<_>>(), vec![ScriptLanguage::Python, ScriptLanguage::JavaScript]);
+
+        let prompts = generator.prompts();
+        assert!(prompts[0].ends_with("Provide the Python script now:"));
+        assert!(prompts[1].ends_with("Provide the JavaScript script now:"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_outcome_variants() {
+        setup_tracing();
+
+        let client = client_with_executor(FakeExecutor::new(&[Ok(r#"{"name": "Toaster"}"#)])).await;
+        match client.dynamic_parse_outcome("doc", "Extract the name.").await {
+            ParseOutcome::Success(result) => assert_eq!(result, r#"{"name": "Toaster"}"#),
+            other => panic!("Expected Success, got {:?}", other),
+        }
+
+        let client = client_with_executor(FakeExecutor::new(&[Ok("{}")])).await;
+        assert!(matches!(client.dynamic_parse_outcome("doc", "Extract the name.").await, ParseOutcome::EmptyMatch));
+
+        let client = client_with_executor(FakeExecutor::new(&[Err("Traceback: boom")])).await;
+        match client.dynamic_parse_outcome("doc", "Extract the name.").await {
+            ParseOutcome::FailedAllRetries(attempts) => {
+                assert_eq!(attempts.len(), 3);
+                assert!(attempts.iter().all(|attempt| !attempt.success));
+            }
+            other => panic!("Expected FailedAllRetries, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_instruction_rephraser_applies_per_attempt() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&["import sys", ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_instruction_rephraser(|instructions, attempt| match attempt {
+                1 => instructions.to_string(),
+                _ => format!("{} Only print a JSON object with an \"ok\" field.", instructions),
+            })
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Second attempt should succeed");
+
+        let prompts = generator.prompts();
+        assert!(!prompts[0].contains("Only print a JSON object"));
+        assert!(prompts[1].contains("Extract anything. Only print a JSON object with an \"ok\" field."));
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_reports_latency_percentiles() {
+        setup_tracing();
+
+        let client = client_with_executor(FakeExecutor::new(&[Ok(r#"{"ok": true}"#)])).await;
+        let samples = [("doc one", "Extract anything."), ("doc two", "Extract anything.")];
+        let report = client.benchmark(&samples, 3).await;
+
+        assert_eq!(report.runs, 6);
+        assert_eq!(report.failures, 0);
+        for stats in [report.generation, report.execution, report.total] {
+            assert!(stats.min <= stats.mean && stats.mean <= stats.max);
+            assert!(stats.min <= stats.p95 && stats.p95 <= stats.max);
+        }
+        assert!(report.total.p95 > std::time::Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_clients_with_different_generators_share_a_vec() {
+        setup_tracing();
+
+        let boxed: Box<dyn ScriptGenerator> = Box::new(ScriptedGenerator::new(&[PRODUCT_SCRIPT]));
+        let clients: Vec<ParserClient> = vec![
+            client_printing(r#"{"ok": true}"#).await,
+            ParserClient::builder().with_generator(boxed).build().await.expect("Failed to build client"),
+            client_with_executor(FakeExecutor::new(&[Ok(r#"{"ok": false}"#)])).await,
+        ];
+
+        let mut results = Vec::new();
+        for client in &clients {
+            results.push(client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed"));
+        }
+        assert_eq!(results[0].trim(), r#"{"ok": true}"#);
+        assert!(results[1].contains("Super Toaster"));
+        assert_eq!(results[2], r#"{"ok": false}"#);
+    }
+
+    #[tokio::test]
+    async fn test_output_example_rejects_mismatched_structure() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&["print('{\"title\": \"Toaster\"}')", "print('{\"name\": \"Toaster\", \"price\": 49.99}')"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_output_example(serde_json::json!({"name": "Example", "price": 1.0}))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("doc", "Extract the product.").await.expect("Second attempt should match");
+        assert!(result.contains("\"price\": 49.99"));
+
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("**Expected Output Structure:**"));
+        assert!(prompts[1].contains("Match this structure"));
+        assert!(prompts[1].contains("$.title is not in the example"));
+    }
+
+    #[tokio::test]
+    async fn test_python_version_hint_and_walrus_check() {
+        setup_tracing();
+
+        let walrus = "import sys, json\nif (data := sys.stdin.read()):\n    print(json.dumps({'ok': True}))";
+        let generator = ScriptedGenerator::new(&[walrus, ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_python_version((3, 6))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Second attempt should succeed");
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::SyntaxError));
+        assert!(attempts[0].error.as_deref().unwrap().contains("walrus"));
+
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("Target Python 3.6."));
+        assert!(prompts[0].contains("the walrus operator"));
+
+        assert_eq!(compat::incompatible_syntax("print(f'{x=}')", (3, 7)), vec!["f-string `=` specifiers (Python 3.8+)"]);
+        assert!(compat::incompatible_syntax("print(f'{a == b}')", (3, 7)).is_empty());
+        assert_eq!(compat::incompatible_syntax("print(rf'''{x=}''')", (3, 7)), vec!["f-string `=` specifiers (Python 3.8+)"]);
+        assert!(compat::incompatible_syntax("flag = 'f'\nprint(flag)", (3, 5)).is_empty());
+        assert!(compat::incompatible_syntax("note = \"a := b\"  # x := y\nmatch = \"{x=}\"", (3, 5)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_parse_quantity_coerces_value_and_normalizes_unit() {
+        setup_tracing();
+
+        let script = "import json\nprint(json.dumps({'value': '$49.99', 'unit': '$'}))";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let quantity = client.dynamic_parse_quantity("Price: $49.99", "Extract the price.").await.expect("Parse should succeed");
+        assert_eq!(quantity, Quantity { value: 49.99, unit: "USD".to_string() });
+
+        let ambiguous = "import json\nprint(json.dumps({'value': '1.299,00', 'unit': 'EUR'}))";
+        let grouped = "import json\nprint(json.dumps({'value': '€1,299.00', 'unit': '€'}))";
+        let generator = ScriptedGenerator::new(&[ambiguous, grouped]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+        let quantity = client.dynamic_parse_quantity("Preis: 1.299,00 €", "Extract the price.").await.expect("Second attempt should succeed");
+        assert_eq!(quantity, Quantity { value: 1299.0, unit: "EUR".to_string() });
+        assert!(generator.prompts()[1].contains("not an unambiguous number"));
+    }
+
+    /// Counts events per level, for asserting which log lines a parse emits.
+    #[derive(Clone, Default)]
+    struct CountingSubscriber {
+        counts: Arc<Mutex<HashMap<tracing::Level, usize>>>,
+    }
+
+    impl tracing::Subscriber for CountingSubscriber {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+        fn event(&self, event: &tracing::Event<'_>) {
+            *self.counts.lock().unwrap().entry(*event.metadata().level()).or_default() += 1;
+        }
+        fn enter(&self, _span: &tracing::span::Id) {}
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[tokio::test]
+    async fn test_log_sampling_zero_keeps_only_warnings_and_errors() {
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["import sys", ECHO_OK_SCRIPT]))
+            .with_log_sampling(0.0)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let subscriber = CountingSubscriber::default();
+        let (result, _) = client
+            .run_attempts("doc", "Extract anything.", &CallOptions::default())
+            .with_subscriber(subscriber.clone())
+            .await;
+        result.expect("Second attempt should succeed");
+
+        let counts = subscriber.counts.lock().unwrap();
+        assert!(counts.get(&tracing::Level::WARN).copied().unwrap_or(0) > 0);
+        for level in [tracing::Level::INFO, tracing::Level::DEBUG, tracing::Level::TRACE] {
+            assert_eq!(counts.get(&level), None, "{} events should be sampled out", level);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_structure_reference_is_prompt_only() {
+        setup_tracing();
+
+        let script = "import sys, json\nprint(json.dumps({'stdin': sys.stdin.read()}))";
+        let generator = ScriptedGenerator::new(&[script]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_structure_reference("name: Reference Kettle\nprice: 19.99")
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("name: Toaster\nprice: 49.99", "Extract the name.").await.expect("Parse should succeed");
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(value["stdin"], "name: Toaster\nprice: 49.99");
+
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("**Reference Document (do not parse this one):**"));
+        assert!(prompts[0].contains("name: Reference Kettle"));
+    }
+
+    #[tokio::test]
+    async fn test_attempt_captures_command_line() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('{\"it\": \"isn\\'t\"}')"]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        let command = attempts[0].command().expect("Subprocess command should be captured");
+        assert!(command.contains("python3 -c "));
+        assert!(command.contains(r#"'print('\''{"it": "isn\'\''t"}'\'')'"#), "unexpected command: {}", command);
+    }
+
+    #[tokio::test]
+    async fn test_field_patterns_reject_and_hint_retry() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&[
+            "print('{\"name\": \"Ada\", \"phone\": \"call me\"}')",
+            "print('{\"name\": \"Ada\", \"phone\": \"555-0100\"}')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_field_patterns(HashMap::from([("phone".to_string(), regex::Regex::new(r"^\d{3}-\d{4}$").unwrap())]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("Ada, 555-0100", "Extract the name and phone.").await.expect("Retry should match");
+        assert!(result.contains("555-0100"));
+        assert!(generator.prompts()[1].contains(r#"field "phone" didn't match pattern"#));
+    }
+
+    #[tokio::test]
+    async fn test_deduplicate_output_removes_duplicate_records() {
+        setup_tracing();
+
+        let records = r#"[{"sku": "A1", "name": "Toaster"}, {"sku": "B2", "name": "Kettle"}, {"sku": "A1", "name": "Toaster"}, {"sku": "B2", "name": "Kettle (refurbished)"}]"#;
+        let script = format!("print('{}')", records);
+        let build = |dedup_key: Option<&str>| {
+            let builder = ParserClient::builder()
+                .with_generator(ScriptedGenerator::new(&[&script]))
+                .with_deduplicate_output(true);
+            match dedup_key {
+                Some(key) => builder.with_dedup_key(key),
+                None => builder,
+            }
+            .build()
+        };
+
+        let client = build(None).await.expect("Failed to build client");
+        let result: serde_json::Value = serde_json::from_str(&client.dynamic_parse("doc", "Extract the products.").await.unwrap()).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 3);
+
+        let client = build(Some("sku")).await.expect("Failed to build client");
+        let result: serde_json::Value = serde_json::from_str(&client.dynamic_parse("doc", "Extract the products.").await.unwrap()).unwrap();
+        assert_eq!(result, serde_json::json!([{"sku": "A1", "name": "Toaster"}, {"sku": "B2", "name": "Kettle"}]));
+    }
+
+    #[tokio::test]
+    async fn test_prose_response_fails_fast_with_reminder() {
+        setup_tracing();
+
+        let prose = "Here's a script that reads the document and extracts the name. It uses regular expressions to find the product title and returns it as JSON.";
+        let generator = ScriptedGenerator::new(&[prose, ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_executor(FakeExecutor::new(&[Ok(r#"{"ok": true}"#)]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract the name.").await.expect("Second attempt should succeed");
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::ProseResponse));
+        assert!(attempts[0].command().is_none());
+        assert!(generator.prompts()[1].contains("Your last response was an explanation, not code."));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_records_arrive_incrementally() {
+        use futures::StreamExt;
+        setup_tracing();
+
+        let script = "import json, sys, time\nfor i in range(3):\n    print(json.dumps({'id': i}))\n    sys.stdout.flush()\n    time.sleep(0.3)";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let start = std::time::Instant::now();
+        let mut arrivals = Vec::new();
+        let mut records = Box::pin(client.dynamic_parse_streaming_records("doc", "Extract the records."));
+        while let Some(record) = records.next().await {
+            arrivals.push((record.expect("Record should be valid JSON"), start.elapsed()));
+        }
+
+        let ids: Vec<_> = arrivals.iter().map(|(record, _)| record["id"].clone()).collect();
+        assert_eq!(ids, vec![serde_json::json!(0), serde_json::json!(1), serde_json::json!(2)]);
+        assert!(arrivals[2].1 - arrivals[0].1 >= std::time::Duration::from_millis(500), "records were buffered: {:?}", arrivals);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_records_respect_total_deadline() {
+        use futures::StreamExt;
+        setup_tracing();
+        let script = "import json, sys, time\nprint(json.dumps({'id': 0}))\nsys.stdout.flush()\ntime.sleep(30)";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .with_total_deadline(Duration::from_millis(1500))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let start = std::time::Instant::now();
+        let records: Vec<_> = client.dynamic_parse_streaming_records("doc", "Extract the records.").collect().await;
+        assert!(start.elapsed() < Duration::from_secs(5), "a stalled script should be killed at the deadline");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_ref().expect("First record should arrive")["id"], 0);
+        let error = records[1].as_ref().expect_err("The stream should end with the deadline");
+        assert!(matches!(error, ParseError::DeadlineExceeded { .. }));
+
+        let javascript = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .with_language(ScriptLanguage::JavaScript)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let records: Vec<_> = javascript.dynamic_parse_streaming_records("doc", "Extract the records.").collect().await;
+        assert_eq!(records.len(), 1);
+        assert!(records[0].as_ref().unwrap_err().to_string().contains("only supports Python"));
+    }
+
+    #[tokio::test]
+    async fn test_min_output_bytes_retries_tiny_output() {
+        setup_tracing();
+
+        let generator = ScriptedGenerator::new(&["print('{\"n\": 1}')", PRODUCT_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_min_output_bytes(20)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("doc", "Extract the product.").await.expect("Second attempt should be large enough");
+        assert!(result.contains("Super Toaster"));
+        assert!(generator.prompts()[1].contains("Your output seems too small; did you miss data?"));
+    }
+
+    #[tokio::test]
+    async fn test_scripts_lists_each_attempt_in_order() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('first')", "print('second')", "print('third')"]))
+            .with_executor(FakeExecutor::new(&[Err("Traceback: boom"), Ok("not json"), Ok(r#"{"ok": true}"#)]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Third attempt should succeed");
+        assert_eq!(scripts(&attempts), vec!["print('first')", "print('second')", "print('third')"]);
+        assert_eq!(attempts.iter().filter(|attempt| attempt.success).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_two_stage_pipeline() {
+        setup_tracing();
+
+        let split = "import sys, json\nprint(json.dumps([line for line in sys.stdin.read().splitlines() if line]))";
+        let refine = "import sys, json\nname, price = sys.stdin.read().rsplit(' - ', 1)\nprint(json.dumps({'name': name, 'price': float(price)}))";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[split, refine]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client
+            .pipeline()
+            .stage("List each product line.")
+            .stage_each("Extract the name and price.")
+            .run("Toaster - 49.99\nKettle - 19.5\n")
+            .await
+            .expect("Pipeline should succeed");
+
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(value, serde_json::json!([{"name": "Toaster", "price": 49.99}, {"name": "Kettle", "price": 19.5}]));
+    }
+
+    #[tokio::test]
+    async fn test_normalize_line_endings() {
+        setup_tracing();
+
+        let script = "import sys, json\nprint(json.dumps({'stdin': sys.stdin.read()}))";
+        let generator = ScriptedGenerator::new(&[script]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_normalize_line_endings(true)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("name: Toaster\r\nprice: 49.99\rstock: 3", "Extract anything.").await.expect("Parse should succeed");
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(value["stdin"], "name: Toaster\nprice: 49.99\nstock: 3");
+        assert!(!generator.prompts()[0].contains('\r'));
+    }
+
+    #[tokio::test]
+    async fn test_builder_default_from_env() {
+        setup_tracing();
+
+        let env = HashMap::from([
+            (ENV_PYTHON_PATH, "/opt/python3.12/bin/python3"),
+            (ENV_MAX_RETRIES, "4"),
+            (ENV_SCRIPT_TIMEOUT_SECS, "2.5"),
+        ]);
+        let lookup = |env: &HashMap<&str, &str>, name: &str| env.get(name).map(std::ffi::OsString::from);
+        let builder = ParserClientBuilder::from_env_with(|name| lookup(&env, name));
+
+        let client = builder
+            .expect("Environment should parse")
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .build()
+            .await
+            .expect("Failed to build client");
+        assert_eq!(client.interpreter, PathBuf::from("/opt/python3.12/bin/python3"));
+        assert_eq!(client.max_retries, 4);
+        #[cfg(unix)]
+        assert_eq!(client.inline_timeout, Some(std::time::Duration::from_millis(2500)));
+
+        let invalid = HashMap::from([(ENV_MAX_RETRIES, "many")]);
+        let error = ParserClientBuilder::from_env_with(|name| lookup(&invalid, name)).err().expect("An unparsable value should fail");
+        assert!(error.to_string().contains("Invalid DYN_PARSE_MAX_RETRIES"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_failure_report_serializes_every_attempt() {
+        setup_tracing();
+
+        let client = client_with_executor(FakeExecutor::new(&[Err("Traceback: boom"), Ok("not json")])).await;
+        let report = client.dynamic_parse_or_report("doc", "Extract anything.").await.expect_err("Every attempt should fail");
+
+        let json = serde_json::to_value(&report).expect("Report should serialize");
+        assert!(json["error"].as_str().unwrap().contains("All 3 parsing attempts failed"));
+        let attempts = json["attempts"].as_array().unwrap();
+        assert_eq!(attempts.len(), 3);
+        assert_eq!(attempts[0]["category"], "RuntimeError");
+        assert_eq!(attempts[1]["category"], "InvalidJson");
+        for attempt in attempts {
+            assert_eq!(attempt["script"], "print('unused')");
+            assert!(attempt["error"].is_string());
+            assert!(attempt["generation_ms"].is_u64());
+            assert!(attempt["execution_ms"].is_u64());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_global_subprocess_limit_serializes_clients() {
+        setup_tracing();
+
+        let script = "import json, time\ntime.sleep(0.4)\nprint(json.dumps({'ok': True}))";
+        let mut first = ParserClient::builder().with_generator(ScriptedGenerator::new(&[script])).build().await.unwrap();
+        let mut second = ParserClient::builder().with_generator(ScriptedGenerator::new(&[script])).build().await.unwrap();
+        // A limit shared by just these clients, so other tests running in parallel aren't throttled.
+        let shared = Arc::new(limit::SubprocessLimit::new());
+        shared.set(1);
+        first.subprocess_limit = Some(shared.clone());
+        second.subprocess_limit = Some(shared);
+
+        let start = std::time::Instant::now();
+        let (a, b) = tokio::join!(
+            first.dynamic_parse("doc", "Extract anything."),
+            second.dynamic_parse("doc", "Extract anything.")
+        );
+        let elapsed = start.elapsed();
+
+        a.expect("First parse should succeed");
+        b.expect("Second parse should succeed");
+        assert!(elapsed >= std::time::Duration::from_millis(800), "scripts overlapped: {:?}", elapsed);
+    }
+
+    /// Records the fields of every span created, for asserting span metadata.
+    #[derive(Clone, Default)]
+    struct SpanCapture {
+        fields: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl tracing::Subscriber for SpanCapture {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            struct Visitor<'a>(&'a mut Vec<String>);
+            impl tracing::field::Visit for Visitor<'_> {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                    self.0.push(format!("{}={:?}", field.name(), value));
+                }
+            }
+            let mut fields = self.fields.lock().unwrap();
+            span.record(&mut Visitor(&mut fields));
+            tracing::span::Id::from_u64(fields.len() as u64 + 1)
+        }
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+        fn event(&self, _event: &tracing::Event<'_>) {}
+        fn enter(&self, _span: &tracing::span::Id) {}
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[tokio::test]
+    async fn test_tagged_parse_records_metadata_on_span_and_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .with_event_callback(move |event| sink.lock().unwrap().push(event.clone()))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let capture = SpanCapture::default();
+        let metadata = HashMap::from([("request_id".to_string(), "req-42".to_string()), ("tenant".to_string(), "acme".to_string())]);
+        client
+            .dynamic_parse_tagged("doc", "Extract anything.", metadata)
+            .with_subscriber(capture.clone())
+            .await
+            .expect("Parse should succeed");
+
+        let fields = capture.fields.lock().unwrap();
+        assert!(fields.contains(&"metadata=request_id=req-42 tenant=acme".to_string()), "span fields: {:?}", fields);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        for event in events.iter() {
+            assert_eq!(event.metadata().get("request_id").map(String::as_str), Some("req-42"));
+        }
+    }
+
+    /// A model backend that records which interface was used and the prompt it received.
+    #[derive(Default)]
+    struct RecordingBackend {
+        calls: Mutex<Vec<(&'static str, String)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ModelBackend for RecordingBackend {
+        async fn chat(&self, _system_prompt: &str, prompt: &str) -> Result<String> {
+            self.calls.lock().unwrap().push(("chat", prompt.to_string()));
+            Ok(ECHO_OK_SCRIPT.to_string())
+        }
+
+        async fn complete(&self, prompt: &str) -> Result<String> {
+            self.calls.lock().unwrap().push(("complete", prompt.to_string()));
+            Ok(ECHO_OK_SCRIPT.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_model_interface_selects_backend_api() {
+        let backend = Arc::new(RecordingBackend::default());
+        let chat = LlamaGenerator::new(backend.clone());
+        chat.generate("SYSTEM", "REQUEST").await.expect("chat should succeed");
+        let completion = LlamaGenerator::new(backend.clone()).with_interface(ModelInterface::Completion);
+        completion.generate("SYSTEM", "REQUEST").await.expect("completion should succeed");
+
+        let calls = backend.calls.lock().unwrap();
+        assert_eq!(calls[0], ("chat", "REQUEST".to_string()));
+        assert_eq!(calls[1], ("complete", completion_prompt("SYSTEM", "REQUEST")));
+        assert!(calls[1].1.starts_with("<|system|>\nSYSTEM"));
+        assert!(calls[1].1.ends_with("<|assistant|>\n"));
+    }
+
+    /// A model backend reporting a fixed mean token log-probability, like a sampler that saw
+    /// every generated token with that probability.
+    struct UnsureBackend {
+        logprob: f32,
+    }
+
+    #[async_trait::async_trait]
+    impl ModelBackend for UnsureBackend {
+        async fn chat(&self, _system_prompt: &str, _prompt: &str) -> Result<String> {
+            Ok(ECHO_OK_SCRIPT.to_string())
+        }
+
+        async fn complete(&self, _prompt: &str) -> Result<String> {
+            Ok(ECHO_OK_SCRIPT.to_string())
+        }
+
+        async fn chat_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+            let text = self.chat(system_prompt, prompt).await?;
+            Ok(Generation { text, logprob: Some(self.logprob) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_llama_generator_reports_backend_logprob() {
+        setup_tracing();
+        let generator = LlamaGenerator::new(UnsureBackend { logprob: -3.0 });
+        let generation = generator.generate_with_logprob("SYSTEM", "REQUEST").await.expect("chat should succeed");
+        assert_eq!(generation.logprob, Some(-3.0));
+        let completion = LlamaGenerator::new(UnsureBackend { logprob: -3.0 }).with_interface(ModelInterface::Completion);
+        assert_eq!(completion.generate_with_logprob("SYSTEM", "REQUEST").await.unwrap().logprob, None);
+
+        let client = ParserClient::builder()
+            .with_generator(generator)
+            .with_confidence_threshold(-1.0)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let (result, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(result.trim(), r#"{"ok": true}"#);
+        assert_eq!(attempts.len(), 2, "a low-confidence success should get one more attempt");
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::LowConfidence));
+        assert_eq!(attempts[0].logprob, Some(-3.0));
+    }
+
+    #[tokio::test]
+    async fn test_count_tokens_is_plausible_and_stable() {
+        let client = client_printing("{}").await;
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let count = client.count_tokens(text);
+        assert!((9..=20).contains(&count), "implausible token count {}", count);
+        assert_eq!(client.count_tokens(text), count);
+        assert_eq!(client.tokenize(text), client.tokenize(text));
+        assert_eq!(client.count_tokens(""), 0);
+        assert_eq!(client.count_tokens("..."), 3, "each punctuation character is its own token");
+        assert_eq!(client.count_tokens("abcdefghij"), 3, "words split into chunks of at most four characters");
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_parse_bytes_matches_string_result() {
+        let client = client_printing(r#"{"name": "Toaster"}"#).await;
+        let text = client.dynamic_parse("doc", "Extract the name.").await.expect("Parse should succeed");
+        let bytes = client.dynamic_parse_bytes("doc", "Extract the name.").await.expect("Parse should succeed");
+        assert_eq!(bytes, text.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_allow_trailing_data_keeps_leading_json_value() {
+        setup_tracing();
+        let script = "print('{\"name\": \"Toaster\"}')\nprint('INFO done parsing')";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .with_max_retries(1)
+            .with_allow_trailing_data(true)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let result = client.dynamic_parse("doc", "Extract the name.").await.expect("Parse should succeed");
+        assert_eq!(result, r#"{"name": "Toaster"}"#);
+
+        let strict = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .with_max_retries(1)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let error = strict.dynamic_parse("doc", "Extract the name.").await.expect_err("Trailing text should be rejected");
+        assert!(format!("{:#}", error).contains("not valid JSON"), "unexpected error: {:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_script_progress_lines_become_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let script = "import sys\nfor i in range(1, 4):\n    print(f'PROGRESS: {i}/3', file=sys.stderr, flush=True)\nprint('not progress', file=sys.stderr)\nprint('{\"ok\": true}')";
+        let generator = ScriptedGenerator::new(&[script]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_script_progress(true)
+            .with_event_callback(move |event| {
+                if let ParseEvent::ScriptProgress { done, total, .. } = event {
+                    sink.lock().unwrap().push((*done, *total));
+                }
+            })
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(*events.lock().unwrap(), vec![(1, 3), (2, 3), (3, 3)]);
+        assert!(generator.prompts()[0].contains("PROGRESS: n/total"));
+    }
+
+    #[tokio::test]
+    async fn test_candidates_are_sorted_by_score() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[
+                "print('{\"name\": \"Toaster\", \"price\": null}')",
+                "print('{\"name\": \"Toaster\", \"price\": 49.99}')",
+                "print('{\"name\": \"\", \"price\": null}')",
+            ]))
+            .with_max_retries(1)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let candidates = client.dynamic_parse_candidates("doc", "Extract the product.", 3).await.expect("Parse should succeed");
+        let scores: Vec<f64> = candidates.iter().map(|(_, score)| *score).collect();
+        assert_eq!(scores, vec![1.0, 0.5, 0.0]);
+        assert_eq!(candidates[0].0, serde_json::json!({"name": "Toaster", "price": 49.99}));
+
+        let custom = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('{\"n\": 1}')", "print('{\"n\": 3}')", "print('{\"n\": 2}')"]))
+            .with_max_retries(1)
+            .with_candidate_scorer(|value| value["n"].as_f64().unwrap_or_default())
+            .build()
+            .await
+            .expect("Failed to build client");
+        let ranked: Vec<f64> = custom
+            .dynamic_parse_candidates("doc", "Extract n.", 3)
+            .await
+            .expect("Parse should succeed")
+            .into_iter()
+            .map(|(_, score)| score)
+            .collect();
+        assert_eq!(ranked, vec![3.0, 2.0, 1.0]);
+    }
+
+    #[tokio::test]
+    async fn test_transcript_sink_receives_every_turn() {
+        let transcripts = Arc::new(Mutex::new(Vec::new()));
+        let sink = transcripts.clone();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["import sys\nsys.exit(1)", ECHO_OK_SCRIPT]))
+            .with_transcript_sink(move |transcript| sink.lock().unwrap().push(transcript.clone()))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        let transcripts = transcripts.lock().unwrap();
+        assert_eq!(transcripts.len(), 1);
+        let turns = &transcripts[0].turns;
+        assert_eq!(turns.len(), 1 + 2 * attempts.len());
+        assert_eq!(turns[0].role, ChatRole::System);
+        let roles: Vec<ChatRole> = turns[1..].iter().map(|turn| turn.role).collect();
+        assert_eq!(roles, vec![ChatRole::User, ChatRole::Assistant, ChatRole::User, ChatRole::Assistant]);
+        assert!(turns[3].content.contains("Previous Attempts and Errors"));
+        assert_eq!(turns[4].content, ECHO_OK_SCRIPT);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_excerpt_expands_on_retry() {
+        setup_tracing();
+        let document = format!("{}SKU-MIDDLE{}", "a".repeat(100), "z".repeat(100));
+        let generator = ScriptedGenerator::new(&["import sys\nsys.exit(1)", "import sys\nsys.exit(1)", ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_prompt_document_chars(60)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        client.dynamic_parse(&document, "Extract the SKU.").await.expect("Parse should succeed");
+        let prompts = generator.prompts();
+        assert_eq!(prompts.len(), 3);
+        assert!(prompts[0].contains("characters omitted") && !prompts[0].contains("SKU-MIDDLE"));
+        assert!(prompts[1].contains("characters omitted") && !prompts[1].contains("SKU-MIDDLE"));
+        assert!(prompts[2].contains("SKU-MIDDLE"), "third attempt should show the whole document");
+        let shown = |prompt: &str| prompt.matches('a').count() + prompt.matches('z').count();
+        assert!(shown(&prompts[0]) < shown(&prompts[1]));
+    }
+
+    #[tokio::test]
+    async fn test_default_prompt_excerpt_is_capped() {
+        assert_eq!(ParserClient::builder().prompt_window(true), (Some(DEFAULT_PROMPT_DOCUMENT_CHARS), false));
+        assert_eq!(ParserClient::builder().prompt_window(false), (None, false));
+        assert_eq!(ParserClient::builder().with_prompt_document_chars(60).prompt_window(true), (Some(60), true));
+
+        let mut client = client_printing("{}").await;
+        client.prompt_document_chars = Some(DEFAULT_PROMPT_DOCUMENT_CHARS);
+        client.widen_prompt_excerpt = false;
+        let document = "x".repeat(50_000);
+        let excerpt = client.prompt_excerpt(&document, 3);
+        assert!(excerpt.contains("[... 47000 characters omitted"), "the default excerpt should not widen on retries");
+    }
+
+    #[tokio::test]
+    async fn test_generation_failure_does_not_widen_excerpt() {
+        setup_tracing();
+        #[derive(Clone)]
+        struct FailsFirst(ScriptedGenerator);
+
+        #[async_trait]
+        impl ScriptGenerator for FailsFirst {
+            async fn generate(&self, system_prompt: &str, prompt: &str) -> Result<String> {
+                let response = self.0.generate(system_prompt, prompt).await?;
+                if self.0.prompts().len() == 1 {
+                    anyhow::bail!("CUDA out of memory");
+                }
+                Ok(response)
+            }
+        }
+
+        let document = format!("{}SKU-MIDDLE{}", "a".repeat(100), "z".repeat(100));
+        let generator = ScriptedGenerator::new(&["", "import sys\nsys.exit(1)", ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(FailsFirst(generator.clone()))
+            .with_prompt_document_chars(60)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        client.dynamic_parse(&document, "Extract the SKU.").await.expect("Parse should succeed");
+        let prompts = generator.prompts();
+        let omitted = |prompt: &str| prompt.split("[... ").nth(1).and_then(|rest| rest.split(' ').next()).map(|n| n.parse::<usize>().unwrap());
+        assert_eq!(omitted(&prompts[0]), Some(150));
+        assert_eq!(omitted(&prompts[1]), Some(150), "a generation failure should not widen the excerpt");
+        assert_eq!(omitted(&prompts[2]), Some(90), "a failed script should widen the excerpt");
+        assert_eq!(client.prompt_excerpt(&document, usize::MAX), document, "huge widenings saturate instead of overflowing");
+    }
+
+    #[test]
+    fn test_temp_artifact_is_removed_on_drop() {
+        let artifact = temp::TempArtifact::with_contents(None, "txt", b"scratch").expect("Failed to create artifact");
+        let other = temp::TempArtifact::with_contents(None, "txt", b"other").expect("Failed to create artifact");
+        let path = artifact.path().to_path_buf();
+        assert_ne!(path, other.path());
+        assert!(path.starts_with(std::env::temp_dir()));
+        assert_eq!(std::fs::read(&path).expect("Artifact should exist"), b"scratch");
+
+        drop(artifact);
+        assert!(!path.exists(), "artifact should be deleted on drop");
+        assert!(other.path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_paginated_parse_aggregates_every_page() {
+        setup_tracing();
+        // Each page's first line names the next page; the remaining lines are records.
+        let script = "import sys, json\nlines = sys.stdin.read().splitlines()\nprint(json.dumps({'next': lines[0] or None, 'records': lines[1:]}))";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let pages = HashMap::from([("page-2", "\nC\nD")]);
+        let result = client
+            .dynamic_parse_paginated("page-2\nA\nB", "Extract the records and the next page.", |page| {
+                page["next"].as_str().and_then(|next| pages.get(next)).map(|content| content.to_string())
+            })
+            .await
+            .expect("Parse should succeed");
+
+        let records: Vec<&str> = result
+            .as_array()
+            .expect("Result should be an array")
+            .iter()
+            .flat_map(|page| page["records"].as_array().unwrap())
+            .filter_map(serde_json::Value::as_str)
+            .collect();
+        assert_eq!(records, vec!["A", "B", "C", "D"]);
+    }
+
+    #[tokio::test]
+    async fn test_max_json_depth_rejects_and_retries() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["print('{\"a\": {\"b\": {\"c\": [1]}}}')", "print('{\"a\": {\"b\": 1}}')"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_max_json_depth(2)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client.dynamic_parse_with_details("doc", "Extract a.").await.expect("Parse should succeed");
+        assert_eq!(result.trim(), r#"{"a": {"b": 1}}"#);
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::OutputRejected));
+        assert!(generator.prompts()[1].contains("Output too deeply nested: 4 levels"));
+    }
+
+    #[tokio::test]
+    async fn test_composite_parse_returns_a_key_per_task() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&[
+            "print('{\"title\": \"Toaster\"}')",
+            "print('{\"title\": \"Toaster\", \"price\": 49.99}')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let tasks = HashMap::from([
+            ("title".to_string(), "Extract the product title.".to_string()),
+            ("price".to_string(), "Extract the price as a number.".to_string()),
+        ]);
+        let result = client.dynamic_parse_composite("doc", tasks).await.expect("Parse should succeed");
+        assert_eq!(result, serde_json::json!({"title": "Toaster", "price": 49.99}));
+
+        let prompts = generator.prompts();
+        assert_eq!(prompts.len(), 2, "output missing a task key should be retried");
+        assert!(prompts[0].contains("Task \"price\": Extract the price as a number."));
+        assert!(prompts[0].contains("Task \"title\": Extract the product title."));
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_blocking_wrappers_share_a_runtime() {
+        setup_tracing();
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .build_blocking()
+            .expect("Failed to build client");
+        for _ in 0..2 {
+            let result = client.dynamic_parse_blocking("doc", "Extract anything.").expect("Parse should succeed");
+            assert_eq!(result.trim(), r#"{"ok": true}"#);
+        }
+
+        let current_thread = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let error = current_thread.block_on(async { client.dynamic_parse_blocking("doc", "Extract anything.") }).unwrap_err();
+        assert!(error.to_string().contains("current-thread"), "{}", error);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_blocking_parse_inside_multi_thread_runtime() {
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[ECHO_OK_SCRIPT]))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let result = client.dynamic_parse_blocking("doc", "Extract anything.").expect("Parse should succeed");
+        assert_eq!(result.trim(), r#"{"ok": true}"#);
+    }
+
+    #[cfg(feature = "readability")]
+    #[tokio::test]
+    async fn test_readability_strips_page_boilerplate() {
+        setup_tracing();
+        let page = r#"<!DOCTYPE html><html><head><style>body { color: red }</style></head><body>
+<nav><a href="/">Home</a> <a href="/deals">Deals</a></nav>
+<main><h1>Toaster</h1><p class="price">$49.99</p></main>
+<footer>Copyright Example Corp</footer><script>track()</script></body></html>"#;
+        let generator = ScriptedGenerator::new(&["import sys, json\nprint(json.dumps({'doc': sys.stdin.read()}))"]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_readability(true)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse(page, "Extract the product.").await.expect("Parse should succeed");
+        let seen = serde_json::from_str::<serde_json::Value>(&result).unwrap()["doc"].as_str().unwrap().to_string();
+        for text in [seen.as_str(), generator.prompts()[0].as_str()] {
+            assert!(text.contains("<h1>Toaster</h1>") && text.contains("$49.99"));
+            assert!(!text.contains("Deals") && !text.contains("Copyright") && !text.contains("track()"), "boilerplate left in: {}", text);
+        }
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct TypedProduct {
+        name: String,
+        price: f64,
+        tags: Vec<String>,
+        sku: Option<String>,
+    }
+
+    #[tokio::test]
+    async fn test_parse_into_macro_extracts_typed_struct() {
+        setup_tracing();
+        assert_eq!(
+            shape::shape_of::<TypedProduct>(),
+            Some(serde_json::json!({"name": "", "price": 0.0, "tags": [""], "sku": null}))
+        );
+
+        let generator = ScriptedGenerator::new(&[
+            "print('{\"name\": \"Toaster\", \"price\": \"49.99\", \"tags\": [], \"sku\": null}')",
+            "print('{\"name\": \"Toaster\", \"price\": 49.99, \"tags\": [\"kitchen\"], \"sku\": \"T-1\"}')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let product = crate::parse_into!(client, "doc", "Extract the product.", TypedProduct).await.expect("Parse should succeed");
+        assert_eq!(product, TypedProduct {
+            name: "Toaster".to_string(),
+            price: 49.99,
+            tags: vec!["kitchen".to_string()],
+            sku: Some("T-1".to_string()),
+        });
+        let prompts = generator.prompts();
+        assert_eq!(prompts.len(), 2, "a price printed as a string should be retried");
+        assert!(prompts[0].contains("**Expected Output Structure:**") && prompts[0].contains("\"tags\""));
+    }
+
+    #[tokio::test]
+    async fn test_typed_parse_retries_results_that_do_not_deserialize() {
+        setup_tracing();
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Stock {
+            name: String,
+            count: i32,
+        }
+
+        let generator = ScriptedGenerator::new(&[
+            "print('{\"name\": \"Toaster\", \"count\": 2.5}')",
+            "print('{\"name\": \"Toaster\", \"count\": 3, \"warehouse\": \"B\"}')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let stock: Stock = client.dynamic_parse_typed("doc", "Extract the stock.").await.expect("Parse should succeed");
+        assert_eq!(stock, Stock { name: "Toaster".to_string(), count: 3 });
+        let prompts = generator.prompts();
+        assert_eq!(prompts.len(), 2, "a float for an integer field should be retried");
+        assert!(prompts[1].contains("Output doesn't fit the expected type"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_into_retries_with_serde_error() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&[
+            "print('{\"name\": \"Toaster\", \"price\": 49.99}')",
+            "print('{\"name\": \"Toaster\", \"price\": 49.99, \"tags\": [], \"sku\": null}')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let product: TypedProduct = client.dynamic_parse_into("doc", "Extract the product.").await.expect("Parse should succeed");
+        assert_eq!(product.tags, Vec::<String>::new());
+        let prompts = generator.prompts();
+        assert_eq!(prompts.len(), 2);
+        assert!(!prompts[0].contains("**Expected Output Structure:**"), "no shape is derived");
+        assert!(prompts[1].contains("missing field `tags`"), "the serde error should be fed back");
+    }
+
+    #[tokio::test]
+    async fn test_schema_is_prompted_and_enforced() {
+        setup_tracing();
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "price": {"type": "number"},
+                "currency": {"enum": ["USD", "EUR"]}
+            },
+            "required": ["name", "price"],
+            "additionalProperties": false
+        });
+        let generator = ScriptedGenerator::new(&[
+            "print('{\"name\": \"Toaster\", \"price\": \"49.99\", \"currency\": \"usd\"}')",
+            "print('{\"name\": \"Toaster\", \"price\": 49.99, \"currency\": \"USD\"}')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse_with_schema("doc", "Extract the product.", &schema).await.expect("Parse should succeed");
+        assert!(result.contains("\"price\": 49.99"));
+
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("**Output JSON Schema:**") && prompts[0].contains("\"additionalProperties\": false"));
+        assert!(prompts[1].contains("$.price should be of type number but is string"));
+        assert!(prompts[1].contains("$.currency is \"usd\" but must be one of"));
+    }
+
+    #[test]
+    fn test_schema_violations() {
+        let schema = serde_json::json!({
+            "type": "array",
+            "items": {"type": "object", "required": ["id"], "properties": {"id": {"type": "integer"}}}
+        });
+        assert!(schema::schema_violations(&schema, &serde_json::json!([{"id": 1}, {"id": 2, "extra": true}])).is_empty());
+        assert_eq!(
+            schema::schema_violations(&schema, &serde_json::json!([{"id": 1.5}, {}])),
+            vec!["$[0].id should be of type integer but is number", "$[1].id is required but missing"]
+        );
+        assert_eq!(schema::schema_violations(&schema, &serde_json::json!({})), vec!["$ should be of type array but is object"]);
+    }
+
+    #[test]
+    fn test_disallowed_imports_are_detected() {
+        let allowlist: Vec<String> = DEFAULT_IMPORT_ALLOWLIST.iter().map(|m| m.to_string()).collect();
+        let script = "import sys, json as j\nimport os.path  # paths\nfrom subprocess import run\nfrom re import sub; import socket\nx = __import__('shutil')\ny = __import__(name)\n# import ctypes";
+        assert_eq!(
+            imports::disallowed_imports(script, &allowlist),
+            vec!["os.path", "subprocess", "socket", "shutil", "<dynamic>"]
+        );
+        assert!(imports::disallowed_imports(ECHO_OK_SCRIPT, &allowlist).is_empty());
+    }
+
+    #[test]
+    fn test_banned_modules_are_detected_through_aliases() {
+        let banned: Vec<String> = DEFAULT_BANNED_MODULES.iter().map(|m| m.to_string()).collect();
+        let script = "import json, socket as s\nfrom urllib import request as r\nimport os as o\no.path.join('a')\no . system('ls')\nfrom os import popen as p\nimport importlib\nm = importlib.import_module('http.client')\nn = __import__(name)";
+        assert_eq!(imports::banned_uses(script, &banned), ["socket", "urllib", "os.popen", "http.client", "<dynamic>", "os.system"]);
+        assert!(imports::banned_uses("import sys, json, re\nos = 'not a module'\nprint(os)", &banned).is_empty());
+        assert!(imports::banned_uses(ECHO_OK_SCRIPT, &banned).is_empty());
+        assert!(imports::banned_uses("import re\npattern = re.compile(r'\\d+')  # no sockets here", &banned).is_empty());
+
+        let hidden_by_string = "s = \"#\"; import socket; socket.create_connection(('example.com', 80))";
+        assert_eq!(imports::banned_uses(hidden_by_string, &banned), ["socket"]);
+        assert_eq!(imports::disallowed_imports(hidden_by_string, &banned[..0]), ["socket"]);
+        let hidden_by_triple_quotes = "doc = '''it's # not\na comment'''; import json\ntry: import subprocess\nexcept ImportError: pass";
+        assert_eq!(imports::banned_uses(hidden_by_triple_quotes, &banned), ["subprocess"]);
+        let continued = "import json, \\\n    socket\nfrom urllib import (\n    parse,\n)";
+        assert_eq!(imports::banned_uses(continued, &banned), ["socket", "urllib"]);
+        assert_eq!(imports::banned_uses("import os\ngetattr(os, 'sys' + 'tem')('ls')", &banned), ["<dynamic>"]);
+        assert_eq!(imports::banned_uses("import os\ngetattr(os, 'popen')('ls')", &banned), ["os.popen"]);
+        assert_eq!(imports::banned_uses("import os\nprint(getattr(os, 'sep'))", &banned), Vec::<String>::new());
+        assert_eq!(imports::banned_uses("exec('import ' + 'socket')", &banned), ["<dynamic>"]);
+        assert_eq!(imports::banned_uses("__import__('os').system('ls')", &banned), ["<dynamic>"]);
+        assert_eq!(imports::banned_uses("__import__('sock' 'et')", &banned), ["<dynamic>"]);
+    }
+
+    #[tokio::test]
+    async fn test_script_using_network_is_not_run() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["import urllib.request as u\nprint(u.urlopen('http://example.com').read())", ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_banned_modules(DEFAULT_BANNED_MODULES.iter().copied())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::DisallowedImport));
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("No network or process access is allowed. Do not use: socket, urllib"));
+        assert!(prompts[1].contains("Script uses urllib.request: no network or process access allowed"));
+    }
+
+    #[tokio::test]
+    async fn test_script_with_disallowed_import_is_not_run() {
+        setup_tracing();
+        let marker = std::env::temp_dir().join(format!("dyn-parse-import-{}", std::process::id()));
+        let forbidden = format!("import os\nos.system('touch {}')\nprint('{{}}')", marker.display());
+        let generator = ScriptedGenerator::new(&[&forbidden, ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_import_allowlist(DEFAULT_IMPORT_ALLOWLIST.iter().copied())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::DisallowedImport));
+        assert!(!marker.exists(), "the rejected script must not run");
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("Import only these modules: sys, json, re."));
+        assert!(prompts[1].contains("not allowed: os"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_array_into_collects_records() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&[
+            "print('[{\"name\": \"Toaster\", \"price\": 49.99, \"tags\": [], \"sku\": null}, {\"name\": \"Kettle\"}]')",
+            "print('[{\"name\": \"Toaster\", \"price\": 49.99, \"tags\": [], \"sku\": null}, {\"name\": \"Kettle\", \"price\": 20, \"tags\": [\"tea\"], \"sku\": null}]')",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let products: Vec<TypedProduct> = client.dynamic_parse_array_into("doc", "Extract the products.").await.expect("Parse should succeed");
+        assert_eq!(products.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["Toaster", "Kettle"]);
+        let prompts = generator.prompts();
+        assert!(prompts[0].contains("print a JSON array with one element per record"));
+        assert!(prompts[1].contains("Record 1 doesn't fit the expected type: missing field `price`"));
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["print('{\"name\": \"Toaster\", \"price\": 1, \"tags\": [], \"sku\": null}')"]))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let products: Vec<TypedProduct> = client.dynamic_parse_array_into("doc", "Extract the products.").await.expect("Parse should succeed");
+        assert_eq!(products.len(), 1, "a single object should be wrapped");
+    }
+
+    #[tokio::test]
+    async fn test_each_attempt_prompt_carries_earlier_attempts() {
+        setup_tracing();
+        let first = "raise ValueError('first failure')";
+        let second = "raise KeyError('second failure')";
+        let generator = ScriptedGenerator::new(&[first, second, ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        client.dynamic_parse("doc", "Extract anything.").await.expect("Third attempt should succeed");
+        let prompts = generator.prompts();
+        assert_eq!(prompts.len(), 3, "every attempt is its own generate call");
+        assert!(!prompts[0].contains("first failure"));
+        assert!(prompts[1].contains(first) && prompts[1].contains("ValueError: first failure"));
+        assert!(prompts[2].contains(first) && prompts[2].contains(second) && prompts[2].contains("KeyError: 'second failure'"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_generation_is_retried_with_hint() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&["  \n", ECHO_OK_SCRIPT]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::EmptyResponse));
+        assert_eq!(attempts[0].command(), None, "an empty response should not be executed");
+        let retry_prompt = &generator.prompts()[1];
+        assert!(retry_prompt.contains("Model returned an empty response"));
+        assert!(retry_prompt.contains("IMPORTANT: Your last response was empty."));
+    }
+
+    #[tokio::test]
+    async fn test_best_candidate_short_circuits_at_threshold() {
+        setup_tracing();
+        let slow = "import time\ntime.sleep(10)\nprint('{\"name\": null}')";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[slow, "print('{\"name\": \"Toaster\"}')", slow]))
+            .with_max_retries(1)
+            .with_candidate_score_threshold(0.9)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let start = std::time::Instant::now();
+        let (value, score) = client.dynamic_parse_best_candidate("doc", "Extract the name.", 3).await.expect("Parse should succeed");
+        assert_eq!(value, serde_json::json!({"name": "Toaster"}));
+        assert_eq!(score, 1.0);
+        assert!(start.elapsed() < Duration::from_secs(5), "slow candidates should have been cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_for_testing_drives_retries_in_process() {
+        let generator = ScriptedGenerator::new(&["raise ValueError('boom')", "print(len(document))"]);
+        let client = ParserClientBuilder::for_testing()
+            .with_generator(generator.clone())
+            .with_executor(testing::FnExecutor::new(|script: &str, document: &str| {
+                if script.contains("raise") {
+                    return Err(ParseError::NonZeroExit { code: 1, stderr: "ValueError: boom".to_string(), script: script.to_string() }.into());
+                }
+                Ok(ScriptOutput { stdout: format!("{{\"length\": {}}}", document.len()), stderr: String::new() })
+            }))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let start = std::time::Instant::now();
+        let (result, attempts) = client.dynamic_parse_with_details("four", "Count the characters.").await.expect("Parse should succeed");
+        assert_eq!(result, r#"{"length": 4}"#);
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].failure_category(), Some(FailureCategory::RuntimeError));
+        assert!(generator.prompts()[1].contains("ValueError: boom"));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let default = ParserClientBuilder::for_testing().build().await.expect("Failed to build client");
+        assert_eq!(default.dynamic_parse("doc", "Extract anything.").await.expect("Parse should succeed"), "{}");
+    }
+
+    #[tokio::test]
+    async fn test_unicode_normalization_composes_to_nfc() {
+        let decomposed = "Cafe\u{301}";
+        let output = serde_json::json!({"name": decomposed, "tags": [decomposed]}).to_string();
+        let client = ParserClientBuilder::for_testing()
+            .with_executor(testing::FnExecutor::new(move |_: &str, _: &str| Ok(ScriptOutput { stdout: output.clone(), stderr: String::new() })))
+            .with_unicode_normalization(Normalization::Nfc)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("doc", "Extract the name.").await.expect("Parse should succeed");
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(value["name"], "Caf\u{e9}");
+        assert_eq!(value["tags"][0], "Caf\u{e9}");
+    }
+
+    #[tokio::test]
+    async fn test_shadow_reports_both_results_without_changing_production() {
+        let printing = |stdout: &'static str| {
+            testing::FnExecutor::new(move |_: &str, _: &str| Ok(ScriptOutput { stdout: stdout.to_string(), stderr: String::new() }))
+        };
+        let shadow = ParserClientBuilder::for_testing()
+            .with_executor(printing(r#"{"name": "Toaster", "price": 49.99}"#))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let (sender, mut comparisons) = tokio::sync::mpsc::unbounded_channel();
+        let client = ParserClientBuilder::for_testing()
+            .with_executor(printing(r#"{"name": "Toaster"}"#))
+            .with_shadow(shadow, move |comparison| sender.send(comparison).unwrap())
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("doc", "Extract the product.").await.expect("Parse should succeed");
+        assert_eq!(result, r#"{"name": "Toaster"}"#);
+        let comparison = comparisons.recv().await.expect("The shadow task reports a comparison");
+        assert_eq!(comparison.production.as_deref(), Ok(r#"{"name": "Toaster"}"#));
+        assert_eq!(comparison.shadow.as_deref(), Ok(r#"{"name": "Toaster", "price": 49.99}"#));
+        assert!(!comparison.matches);
+
+        // A shadow that never finishes doesn't hold up production.
+        let hanging = ParserClient::builder()
+            .with_generator(HangingGenerator)
+            .build()
+            .await
+            .expect("Failed to build client");
+        let client = ParserClientBuilder::for_testing()
+            .with_executor(printing(r#"{"name": "Toaster"}"#))
+            .with_shadow(hanging, |_| panic!("The shadow never finishes"))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let result = tokio::time::timeout(Duration::from_secs(5), client.dynamic_parse("doc", "Extract the product."))
+            .await
+            .expect("Production shouldn't wait for the shadow");
+        assert_eq!(result.expect("Parse should succeed"), r#"{"name": "Toaster"}"#);
+    }
+
+    /// A generator that never answers.
+    struct HangingGenerator;
+
+    #[async_trait]
+    impl ScriptGenerator for HangingGenerator {
+        async fn generate(&self, _system_prompt: &str, _prompt: &str) -> Result<String> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shebang_is_stripped() {
+        setup_tracing();
+
+        let script = format!("#!/usr/bin/env python3\n{}", ECHO_OK_SCRIPT);
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[&script]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (_, attempts) = client.dynamic_parse_with_details("doc", "Extract anything.").await.expect("Parse should succeed");
+        assert!(!attempts[0].script.starts_with("#!"));
+        assert_eq!(attempts[0].script, ECHO_OK_SCRIPT);
+    }
+
+    #[tokio::test]
+    async fn test_main_guard_runs_under_dash_c() {
+        setup_tracing();
+
+        let script = "import json\n\ndef main():\n    print(json.dumps({'ok': True}))\n\nif __name__ == \"__main__\":\n    main()";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let result = client.dynamic_parse("doc", "Extract anything.").await.expect("Guarded script should run");
+        assert_eq!(result.trim(), r#"{"ok": true}"#);
+    }
+
+    #[tokio::test]
+    async fn test_session_diffs_successive_results() {
+        setup_tracing();
+
+        let script = "import sys, json\nprint(json.dumps({'name': 'Toaster', 'price': float(sys.stdin.read())}))";
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&[script]))
+            .build()
+            .await
+            .expect("Failed to build client");
+        let mut session = client.session("Extract the name and price.");
+
+        let (first, diff) = session.parse_diff("10").await.expect("First parse should succeed");
+        assert_eq!(diff.changes, vec![JsonChange::Added { path: "$".to_string(), value: first }]);
+
+        let (second, diff) = session.parse_diff("12.5").await.expect("Second parse should succeed");
+        assert_eq!(second["price"], 12.5);
+        assert_eq!(
+            diff.changes,
+            vec![JsonChange::Changed { path: "$.price".to_string(), old: serde_json::json!(10.0), new: serde_json::json!(12.5) }]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_inline_timeout_interrupts_runaway_script() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_generator(ScriptedGenerator::new(&["while True:\n    pass"]))
+            .with_inline_timeout(std::time::Duration::from_millis(200))
+            .with_max_retries(1)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        match client.dynamic_parse_outcome("doc", "Extract anything.").await {
+            ParseOutcome::FailedAllRetries(attempts) => {
+                assert_eq!(attempts[0].failure_category(), Some(FailureCategory::Timeout));
+                let error = attempts[0].error.as_deref().unwrap_or_default();
+                assert!(error.contains(r#""error": "timeout""#), "unexpected error: {}", error);
+            }
+            other => panic!("Expected FailedAllRetries, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_successful_parse() {
+        // Call the setup function at the beginning of each test.
+        setup_tracing();
+        
         println!("🧪 Starting test_successful_parse");
         info!("Test: test_successful_parse started");
         
@@ -488,6 +4546,59 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    #[ignore] // requires node
+    async fn test_node_runtime_runs_javascript() {
+        setup_tracing();
+        let generator = ScriptedGenerator::new(&[
+            "```js\nlet data = '';\nprocess.stdin.on('data', chunk => data += chunk);\nprocess.stdin.on('end', () => console.log(JSON.stringify({ length: data.length })));\n```",
+        ]);
+        let client = ParserClient::builder()
+            .with_generator(generator.clone())
+            .with_language(ScriptLanguage::JavaScript)
+            .build()
+            .await
+            .expect("Failed to build client");
+
+        let (result, attempts) = client.dynamic_parse_with_details("hello", "Count the characters.").await.expect("Node should run the script");
+        assert_eq!(result.trim(), r#"{"length":5}"#);
+        assert_eq!(attempts[0].language(), ScriptLanguage::JavaScript);
+        assert!(attempts[0].command().is_some_and(|command| command.contains(" node -e ")));
+        assert!(generator.prompts()[0].ends_with("Provide the JavaScript script now:"));
+    }
+
+    #[tokio::test]
+    #[ignore] // downloads a 7B model
+    async fn test_custom_model_source_is_loaded() {
+        setup_tracing();
+
+        let client = ParserClient::builder()
+            .with_model_source(LlamaSource::llama_7b_chat())
+            .build()
+            .await
+            .expect("Custom model should load");
+        let result = client.dynamic_parse("<p>Price: $5</p>", "Extract the price as a number").await;
+        info!("Result with custom model: {:?}", result);
+    }
+
+    #[tokio::test]
+    #[ignore] // downloads the model
+    async fn test_model_cache_dir_is_used_and_reused() {
+        setup_tracing();
+
+        let cache_dir = std::env::temp_dir().join(format!("dyn-parse-model-cache-{}", std::process::id()));
+        ParserClient::builder().with_model_cache_dir(&cache_dir).build().await.expect("First load should succeed");
+        let cached_files = std::fs::read_dir(&cache_dir).expect("Cache dir should exist").count();
+        assert!(cached_files > 0, "model files should be cached in the configured directory");
+
+        let start = std::time::Instant::now();
+        ParserClient::builder().with_model_cache_dir(&cache_dir).build().await.expect("Second load should succeed");
+        assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), cached_files);
+        info!("Second construction from cache took {:.2}s", start.elapsed().as_secs_f64());
+
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
     #[tokio::test]
 #[ignore]
     async fn test_parse_with_details() {
@@ -0,0 +1,49 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info};
+
+/// Process-wide cap on concurrently running script subprocesses, shared by every client.
+pub(crate) static GLOBAL_SUBPROCESS_LIMIT: SubprocessLimit = SubprocessLimit::new();
+
+/// An optional cap on concurrently running script subprocesses. Clients share
+/// `GLOBAL_SUBPROCESS_LIMIT` unless given their own.
+pub(crate) struct SubprocessLimit(Mutex<Option<Arc<Semaphore>>>);
+
+impl SubprocessLimit {
+    /// A limit that starts out unset, letting any number of subprocesses run.
+    pub(crate) const fn new() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    /// Allows at most `limit` subprocesses at once; 0 is treated as 1.
+    pub(crate) fn set(&self, limit: usize) {
+        *self.0.lock().unwrap() = Some(Arc::new(Semaphore::new(limit.max(1))));
+    }
+
+    /// Removes the limit.
+    pub(crate) fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+
+    /// Waits for a subprocess slot, returning a permit to hold while the subprocess runs, or
+    /// `None` when no limit is set.
+    pub(crate) async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.0.lock().unwrap().clone()?;
+        debug!("Waiting for a subprocess slot ({} available)", semaphore.available_permits());
+        // The semaphore is never closed, so acquiring can't fail.
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+/// Caps how many script subprocesses may run at once across every `ParserClient` in the process.
+/// Executions beyond the limit wait for a running script to finish before spawning. Changing the
+/// limit doesn't affect scripts that are already running. A limit of 0 is treated as 1.
+pub fn set_global_subprocess_limit(limit: usize) {
+    info!("Limiting concurrent script subprocesses to {}", limit.max(1));
+    GLOBAL_SUBPROCESS_LIMIT.set(limit);
+}
+
+/// Removes the process-wide subprocess limit set by `set_global_subprocess_limit`.
+pub fn clear_global_subprocess_limit() {
+    GLOBAL_SUBPROCESS_LIMIT.clear();
+}
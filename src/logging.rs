@@ -0,0 +1,79 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Level, Metadata, Subscriber};
+
+/// Returns true with probability `rate`, deciding whether a parse logs at full verbosity.
+pub(crate) fn sampled(rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+    // `RandomState` is freshly keyed on every call, which is random enough for log sampling.
+    let roll = RandomState::new().hash_one(0u8) as f64 / u64::MAX as f64;
+    roll < rate
+}
+
+/// Wraps another dispatcher, dropping events below `WARN` and forwarding everything else.
+/// Installed for the duration of a parse that wasn't sampled for verbose logging.
+pub(crate) struct WarningsOnly(Dispatch);
+
+impl WarningsOnly {
+    /// Wraps the dispatcher that is current for the calling thread.
+    pub(crate) fn wrap_current() -> Dispatch {
+        let inner = tracing::dispatcher::get_default(Dispatch::clone);
+        Dispatch::new(WarningsOnly(inner))
+    }
+}
+
+impl Subscriber for WarningsOnly {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if metadata.is_event() && *metadata.level() > Level::WARN {
+            // Interest is cached globally, so verbose events must stay dynamic rather than `never`.
+            return Interest::sometimes();
+        }
+        self.0.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if metadata.is_event() && *metadata.level() > Level::WARN {
+            return false;
+        }
+        self.0.enabled(metadata)
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        self.0.new_span(span)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        self.0.record(span, values)
+    }
+
+    fn record_follows_from(&self, span: &Id, follows: &Id) {
+        self.0.record_follows_from(span, follows)
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        self.0.event(event)
+    }
+
+    fn enter(&self, span: &Id) {
+        self.0.enter(span)
+    }
+
+    fn exit(&self, span: &Id) {
+        self.0.exit(span)
+    }
+
+    fn clone_span(&self, id: &Id) -> Id {
+        self.0.clone_span(id)
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        self.0.try_close(id)
+    }
+}
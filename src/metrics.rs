@@ -0,0 +1,66 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::{CallOptions, FailureCategory, ParseAttempt, ParseError, ParserClient};
+
+/// Timings and sizes of a single parse, for exporting to a metrics system instead of scraping
+/// log lines.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseMetrics {
+    pub attempts: Vec<AttemptMetrics>,
+    /// Wall-clock time of the whole parse, including document preparation and retries.
+    pub total_time: Duration,
+    pub success: bool,
+    /// Length of the input document in bytes.
+    pub document_bytes: usize,
+    /// Length of the returned result in bytes, or `None` if the parse failed.
+    pub result_bytes: Option<usize>,
+}
+
+/// One attempt within `ParseMetrics`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AttemptMetrics {
+    pub generation_time: Duration,
+    /// `None` when no script ran.
+    pub execution_time: Option<Duration>,
+    pub success: bool,
+    pub category: Option<FailureCategory>,
+}
+
+impl ParseMetrics {
+    /// How many attempts the parse made.
+    pub fn attempt_count(&self) -> usize {
+        self.attempts.len()
+    }
+}
+
+impl From<&ParseAttempt> for AttemptMetrics {
+    fn from(attempt: &ParseAttempt) -> Self {
+        Self {
+            generation_time: attempt.generation_time,
+            execution_time: attempt.execution_time,
+            success: attempt.success,
+            category: attempt.failure_category,
+        }
+    }
+}
+
+impl ParserClient {
+    /// Like `dynamic_parse`, but also returns the parse's `ParseMetrics`, whether or not it
+    /// succeeded.
+    pub async fn dynamic_parse_with_metrics(&self, document: &str, instructions: &str) -> (Result<String, ParseError>, ParseMetrics) {
+        info!("🔄 Starting dynamic parse with metrics");
+        let start = Instant::now();
+        let (result, attempts) = self.run_attempts(document, instructions, &CallOptions::default()).await;
+        let metrics = ParseMetrics {
+            attempts: attempts.iter().map(AttemptMetrics::from).collect(),
+            total_time: start.elapsed(),
+            success: result.is_ok(),
+            document_bytes: document.len(),
+            result_bytes: result.as_ref().ok().map(String::len),
+        };
+        (result.map_err(ParseError::from), metrics)
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::Result;
+use serde_json::Value;
+use unicode_normalization::UnicodeNormalization;
+
+/// Format of the string returned by a successful parse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Serialization {
+    /// The script's JSON output, returned verbatim.
+    #[default]
+    Json,
+    Yaml,
+    /// Requires the result to be a JSON object without `null` values.
+    Toml,
+}
+
+/// What a script prints on stdout, which decides how its output is validated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A single JSON value, checked and post-processed by the client's JSON options.
+    #[default]
+    Json,
+    /// Any non-empty text, returned verbatim.
+    Raw,
+    /// CSV with a header row, where every row has as many fields as the header.
+    Csv,
+}
+
+impl OutputFormat {
+    /// System prompt section replacing the built-in prompts' JSON output rules, or `None` for JSON.
+    pub(crate) fn prompt_rule(self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Json => None,
+            OutputFormat::Raw => Some(
+                "The rules above about JSON do not apply. Print the extracted data to standard output as plain text, in exactly the form the user asks for.",
+            ),
+            OutputFormat::Csv => Some(
+                "The rules above about JSON do not apply. Print the extracted data to standard output as CSV: a header row, then one row per record, every row with the same number of fields. Quote fields that contain commas, quotes or newlines.",
+            ),
+        }
+    }
+}
+
+/// Describes why `output` isn't well-formed CSV, or returns `None` if it is: quotes must be
+/// balanced and every row must have as many fields as the header. Blank lines are ignored.
+pub(crate) fn csv_error(output: &str) -> Option<String> {
+    let mut rows: Vec<usize> = Vec::new();
+    let (mut fields, mut in_quotes, mut blank) = (1, false, true);
+    let mut chars = output.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields += 1,
+            '\n' if !in_quotes => {
+                if !blank {
+                    rows.push(fields);
+                }
+                (fields, blank) = (1, true);
+                continue;
+            }
+            '\r' if !in_quotes => continue,
+            _ => {}
+        }
+        blank = false;
+    }
+    if in_quotes {
+        return Some("a quoted field is never closed".to_string());
+    }
+    if !blank {
+        rows.push(fields);
+    }
+    let header = *rows.first()?;
+    rows.iter()
+        .enumerate()
+        .find(|(_, fields)| **fields != header)
+        .map(|(index, fields)| format!("row {} has {} fields but the header has {}", index + 1, fields, header))
+}
+
+/// Unicode normalization form applied to the strings of a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// Canonical composition, e.g. `e` + combining acute becomes `é`.
+    Nfc,
+    /// Canonical decomposition.
+    Nfd,
+    /// Compatibility composition, which also folds variants such as `ﬁ` into `fi`.
+    Nfkc,
+    /// Compatibility decomposition.
+    Nfkd,
+}
+
+impl Normalization {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Normalization::Nfc => text.nfc().collect(),
+            Normalization::Nfd => text.nfd().collect(),
+            Normalization::Nfkc => text.nfkc().collect(),
+            Normalization::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+/// Normalizes every string value in `value` to `form`. Object keys are left alone. Returns
+/// whether anything changed.
+pub(crate) fn normalize_strings(value: &mut Value, form: Normalization) -> bool {
+    match value {
+        Value::String(text) => {
+            let normalized = form.apply(text);
+            let changed = normalized != *text;
+            *text = normalized;
+            changed
+        }
+        Value::Array(items) => items.iter_mut().fold(false, |changed, item| normalize_strings(item, form) | changed),
+        Value::Object(map) => map.values_mut().fold(false, |changed, item| normalize_strings(item, form) | changed),
+        _ => false,
+    }
+}
+
+/// Converts a validated JSON result into `serialization`.
+pub(crate) fn serialize_value(value: &Value, serialization: Serialization) -> Result<String> {
+    match serialization {
+        Serialization::Json => Ok(serde_json::to_string(value)?),
+        Serialization::Yaml => Ok(serde_yaml::to_string(value)?),
+        Serialization::Toml => Ok(toml::to_string(value)?),
+    }
+}
+
+/// Parses a result previously produced by `serialize_value` back into JSON.
+pub(crate) fn parse_value(result: &str, serialization: Serialization) -> Result<Value> {
+    match serialization {
+        Serialization::Json => Ok(serde_json::from_str(result)?),
+        Serialization::Yaml => Ok(serde_yaml::from_str(result)?),
+        Serialization::Toml => Ok(toml::from_str(result)?),
+    }
+}
+
+/// Returns the first JSON value in `output`, ignoring anything printed after it, e.g. a stray
+/// log line. Leading whitespace is skipped; errors if `output` doesn't start with a valid value.
+pub(crate) fn leading_json(output: &str) -> Result<&str, serde_json::Error> {
+    let mut values = serde_json::Deserializer::from_str(output).into_iter::<Value>();
+    match values.next() {
+        Some(Ok(_)) => Ok(output[..values.byte_offset()].trim_start()),
+        Some(Err(e)) => Err(e),
+        None => serde_json::from_str::<Value>(output).map(|_| output),
+    }
+}
+
+/// How deeply `value` nests: 0 for scalars, 1 for a flat object or array, and so on.
+pub(crate) fn nesting_depth(value: &Value) -> usize {
+    match value {
+        Value::Object(map) => 1 + map.values().map(nesting_depth).max().unwrap_or(0),
+        Value::Array(items) => 1 + items.iter().map(nesting_depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Removes later duplicates from a top-level array, keeping the first occurrence. Elements are
+/// duplicates when their `key` fields are equal, or when they're structurally equal if no `key`
+/// is given or an element lacks it. Returns whether anything was removed; non-arrays are untouched.
+pub(crate) fn deduplicate(value: &mut Value, key: Option<&str>) -> bool {
+    let Value::Array(items) = value else {
+        return false;
+    };
+    let before = items.len();
+    let mut seen_keys: Vec<Value> = Vec::new();
+    let mut seen_items: Vec<Value> = Vec::new();
+    items.retain(|item| {
+        let (seen, identity) = match key.and_then(|key| item.get(key)) {
+            Some(id) => (&mut seen_keys, id),
+            None => (&mut seen_items, item),
+        };
+        if seen.contains(identity) {
+            return false;
+        }
+        seen.push(identity.clone());
+        true
+    });
+    items.len() != before
+}
+
+/// Lists where `value` departs from the structure of `example`: object keys must match exactly
+/// (or, with `allow_extra_keys`, include the example's), scalars must have the same JSON type,
+/// and array items must match the example's first item. `null` on either side matches anything,
+/// so examples can mark optional fields.
+pub(crate) fn structure_mismatches(example: &Value, value: &Value, allow_extra_keys: bool) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    collect_mismatches("$", example, value, allow_extra_keys, &mut mismatches);
+    mismatches
+}
+
+fn collect_mismatches(path: &str, example: &Value, value: &Value, allow_extra_keys: bool, mismatches: &mut Vec<String>) {
+    match (example, value) {
+        (Value::Null, _) | (_, Value::Null) => {}
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected_value) in expected {
+                let child = format!("{}.{}", path, key);
+                match actual.get(key) {
+                    Some(actual_value) => collect_mismatches(&child, expected_value, actual_value, allow_extra_keys, mismatches),
+                    None => mismatches.push(format!("{} is missing", child)),
+                }
+            }
+            for key in actual.keys().filter(|key| !allow_extra_keys && !expected.contains_key(*key)) {
+                mismatches.push(format!("{}.{} is not in the example", path, key));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if let Some(item) = expected.first() {
+                for (index, actual_item) in actual.iter().enumerate() {
+                    collect_mismatches(&format!("{}[{}]", path, index), item, actual_item, allow_extra_keys, mismatches);
+                }
+            }
+        }
+        _ if json_type(example) != json_type(value) => {
+            mismatches.push(format!("{} should be {} but is {}", path, json_type(example), json_type(value)));
+        }
+        _ => {}
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// The result of `dynamic_parse_outcome`, for exhaustive matching instead of inspecting errors.
+#[derive(Debug)]
+pub enum ParseOutcome {
+    /// A script extracted data.
+    Success(String),
+    /// A script ran successfully but found nothing (an empty object or array).
+    EmptyMatch,
+    /// The best available result, returned after a later attempt failed, e.g. a low-confidence
+    /// success kept by the confidence gate.
+    BestEffort(String),
+    /// Every attempt failed.
+    FailedAllRetries(Vec<crate::ParseAttempt>),
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::{CallOptions, ParseError, ParserClient, Serialization};
+
+impl ParserClient {
+    /// Parses a multi-page source one page at a time. Each page is parsed with `instructions`,
+    /// then `fetch_next` is called with that page's result and returns the next page's content,
+    /// e.g. by downloading a "next" link the script extracted, or `None` after the last page.
+    ///
+    /// Results are aggregated into one JSON array: a page result that is an array contributes its
+    /// items, any other result is added as a single item. Fails on the first page that fails.
+    pub async fn dynamic_parse_paginated(
+        &self,
+        first_page: &str,
+        instructions: &str,
+        fetch_next: impl Fn(&Value) -> Option<String>,
+    ) -> Result<Value, ParseError> {
+        info!("📚 Starting paginated parse");
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            ..Default::default()
+        };
+
+        let mut aggregated = Vec::new();
+        let mut page = first_page.to_string();
+        let mut pages = 0;
+        loop {
+            pages += 1;
+            debug!("Parsing page {} ({} characters)", pages, page.len());
+            let (result, _) = self
+                .parse_with_attempts(&page, instructions, &options)
+                .await
+                .map_err(|e| e.context(format!("Failed to parse page {}", pages)))?;
+            let value: Value = serde_json::from_str(&result)?;
+            let next = fetch_next(&value);
+            match value {
+                Value::Array(items) => aggregated.extend(items),
+                other => aggregated.push(other),
+            }
+            match next {
+                Some(next) => page = next,
+                None => break,
+            }
+        }
+
+        info!("✅ Paginated parse finished after {} pages with {} items", pages, aggregated.len());
+        Ok(Value::Array(aggregated))
+    }
+}
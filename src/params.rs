@@ -0,0 +1,51 @@
+use anyhow::{Result, bail};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ScriptLanguage;
+
+/// Prefix of the environment variables that carry script parameters, so they can't clobber
+/// variables the interpreter relies on such as `PATH` or `PYTHONPATH`.
+const ENV_PREFIX: &str = "PARAM_";
+
+/// Converts caller parameters into environment variables, sorted by name. Names are uppercased,
+/// characters other than ASCII letters, digits and `_` become `_`, and the result is prefixed
+/// with `PARAM_`, so `"currency"` becomes `PARAM_CURRENCY`. Control characters, including the NUL
+/// bytes environment values can't hold, are removed from values. Fails if two names map to the
+/// same variable, such as `a-b` and `a_b`.
+pub(crate) fn script_env(params: &HashMap<String, String>) -> Result<Vec<(String, String)>> {
+    let mut env: BTreeMap<String, (&str, String)> = BTreeMap::new();
+    for (name, value) in params {
+        if let Some((other, _)) = env.insert(env_name(name), (name, env_value(value))) {
+            let (first, second) = if other < name.as_str() { (other, name.as_str()) } else { (name.as_str(), other) };
+            bail!("Script parameters {:?} and {:?} both map to {}", first, second, env_name(name));
+        }
+    }
+    Ok(env.into_iter().map(|(name, (_, value))| (name, value)).collect())
+}
+
+fn env_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("{}{}", ENV_PREFIX, name)
+}
+
+fn env_value(value: &str) -> String {
+    value.chars().filter(|c| !c.is_control() || *c == '\t').collect()
+}
+
+/// Prompt section telling the model which parameters it can read, by name only, so the script
+/// branches on them rather than hard-coding this call's values.
+pub(crate) fn prompt_section(env: &[(String, String)], language: ScriptLanguage) -> String {
+    let names: Vec<&str> = env.iter().map(|(name, _)| name.as_str()).collect();
+    let example = match language {
+        ScriptLanguage::Python => format!("os.environ[\"{}\"]", names[0]),
+        ScriptLanguage::JavaScript => format!("process.env.{}", names[0]),
+    };
+    format!(
+        "\n**Script Parameters:**\nThe script receives these parameters as environment variables: {}. Read them (e.g. `{}`) instead of hard-coding their values.\n",
+        names.join(", "),
+        example
+    )
+}
@@ -0,0 +1,84 @@
+use anyhow::{Result, bail};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::{CallOptions, ParserClient, Serialization};
+
+/// How a pipeline stage consumes the previous stage's output.
+enum StageMode {
+    /// Parse the whole input once.
+    Whole,
+    /// Parse each element of an array input separately, collecting the results into an array.
+    Each,
+}
+
+struct Stage {
+    instructions: String,
+    mode: StageMode,
+}
+
+/// A sequence of parses where each stage's JSON output becomes the next stage's input document.
+/// Created with `ParserClient::pipeline`.
+pub struct ParsePipeline<'a> {
+    client: &'a ParserClient,
+    stages: Vec<Stage>,
+}
+
+impl ParserClient {
+    /// Starts an empty multi-stage pipeline that runs every stage with this client.
+    pub fn pipeline(&self) -> ParsePipeline<'_> {
+        ParsePipeline { client: self, stages: Vec::new() }
+    }
+}
+
+impl ParsePipeline<'_> {
+    /// Adds a stage that parses the previous stage's whole output with `instructions`.
+    pub fn stage(mut self, instructions: impl Into<String>) -> Self {
+        self.stages.push(Stage { instructions: instructions.into(), mode: StageMode::Whole });
+        self
+    }
+
+    /// Adds a stage that parses each element of the previous stage's array output separately with
+    /// `instructions`, producing an array of the results. Fails if the input isn't an array.
+    pub fn stage_each(mut self, instructions: impl Into<String>) -> Self {
+        self.stages.push(Stage { instructions: instructions.into(), mode: StageMode::Each });
+        self
+    }
+
+    /// Runs every stage in order, starting from `document`, and returns the final stage's JSON result.
+    pub async fn run(&self, document: &str) -> Result<String> {
+        if self.stages.is_empty() {
+            bail!("Pipeline has no stages");
+        }
+        info!("🧩 Running {}-stage parse pipeline", self.stages.len());
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            ..Default::default()
+        };
+
+        let mut input = document.to_string();
+        for (index, stage) in self.stages.iter().enumerate() {
+            debug!("Running pipeline stage {}", index + 1);
+            input = match stage.mode {
+                StageMode::Whole => self.client.parse_with_attempts(&input, &stage.instructions, &options).await?.0,
+                StageMode::Each => {
+                    let Value::Array(items) = serde_json::from_str(&input)? else {
+                        bail!("Pipeline stage {} expects an array from the previous stage", index + 1);
+                    };
+                    let mut results = Vec::with_capacity(items.len());
+                    for item in items {
+                        // String items are passed as their text rather than as a quoted JSON string.
+                        let item_document = match item {
+                            Value::String(text) => text,
+                            other => other.to_string(),
+                        };
+                        let (result, _) = self.client.parse_with_attempts(&item_document, &stage.instructions, &options).await?;
+                        results.push(serde_json::from_str::<Value>(&result)?);
+                    }
+                    serde_json::to_string(&results)?
+                }
+            };
+        }
+        Ok(input)
+    }
+}
@@ -0,0 +1,112 @@
+use base64::Engine;
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::sync::LazyLock;
+
+use crate::InputMode;
+
+/// How a document containing non-printable characters is shown to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryMode {
+    /// A hex dump of the document's bytes, 16 per line.
+    Hex,
+    /// The document's bytes encoded as standard base64.
+    Base64,
+    /// The text with each non-printable character replaced by an escape sequence.
+    Printable,
+}
+
+/// Elements dropped with their content, and comments.
+static INVISIBLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<!--.*?-->|<(script|style|noscript|template)\b[^>]*>.*?</(script|style|noscript|template)\s*>").unwrap());
+/// Tags that end a line of text.
+static LINE_BREAK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<br\s*/?>|</(p|div|li|tr|h[1-6]|table|ul|ol|section|article|header|footer|title)\s*>").unwrap());
+/// Tags that end a table cell.
+static CELL_BREAK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)</t[dh]\s*>").unwrap());
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+
+/// Reduces HTML to its visible text, for use with `with_preprocessor`: scripts, styles and
+/// comments are dropped, block elements and `<br>` become line breaks, table cells are separated
+/// by ` | `, common entities are decoded, and whitespace is collapsed, leaving one line per block.
+pub fn strip_html_tags(html: &str) -> String {
+    let text = INVISIBLE.replace_all(html, "");
+    let text = LINE_BREAK.replace_all(&text, "\n");
+    let text = CELL_BREAK.replace_all(&text, " | ");
+    let text = TAG.replace_all(&text, " ");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .map(|line| line.trim_end_matches(" |").to_string())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns true for characters that would confuse the model if shown raw.
+fn is_non_printable(c: char) -> bool {
+    c.is_control() && !matches!(c, '\n' | '\r' | '\t')
+}
+
+/// Shortens a document longer than `max_chars` characters to its first and last `max_chars / 2`
+/// characters, with a marker in between telling the model how much was left out.
+pub(crate) fn excerpt(document: &str, max_chars: usize) -> Cow<'_, str> {
+    let total = document.chars().count();
+    if total <= max_chars {
+        return Cow::Borrowed(document);
+    }
+    let head_chars = max_chars / 2;
+    let tail_chars = max_chars - head_chars;
+    let byte_at = |chars: usize| document.char_indices().nth(chars).map_or(document.len(), |(index, _)| index);
+    let head = &document[..byte_at(head_chars)];
+    let tail = &document[byte_at(total - tail_chars)..];
+    Cow::Owned(format!(
+        "{}\n[... {} characters omitted from this excerpt; your script receives the full document ...]\n{}",
+        head,
+        total - max_chars,
+        tail
+    ))
+}
+
+/// Renders the document excerpt shown in the prompt. Documents without non-printable
+/// characters, or when no `mode` is set, are shown unchanged; otherwise a note says where the
+/// script finds the raw text under `input`.
+pub(crate) fn render_document(document: &str, mode: Option<BinaryMode>, input: InputMode) -> Cow<'_, str> {
+    let Some(mode) = mode else {
+        return Cow::Borrowed(document);
+    };
+    if !document.chars().any(is_non_printable) {
+        return Cow::Borrowed(document);
+    }
+
+    let rendered = match mode {
+        BinaryMode::Hex => {
+            let mut dump = String::new();
+            for (line, chunk) in document.as_bytes().chunks(16).enumerate() {
+                let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                let _ = writeln!(dump, "{:08x}  {}", line * 16, bytes.join(" "));
+            }
+            dump
+        }
+        BinaryMode::Base64 => base64::engine::general_purpose::STANDARD.encode(document.as_bytes()),
+        BinaryMode::Printable => document
+            .chars()
+            .map(|c| if is_non_printable(c) { c.escape_default().to_string() } else { c.to_string() })
+            .collect(),
+    };
+    let source = match input {
+        InputMode::Stdin => "on stdin",
+        InputMode::TempFile => "in the file named by its first argument",
+    };
+    Cow::Owned(format!(
+        "(The document contains non-printable characters and is shown here as {:?}. Your script still receives the raw text {}.)\n{}",
+        mode, source, rendered
+    ))
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use regex::Regex;
+use serde_json::Value;
+use std::sync::OnceLock;
+use tracing::{debug, info};
+
+use crate::{CallOptions, ParseError, ParserClient, Serialization};
+
+/// A numeric value with its unit, as returned by `dynamic_parse_quantity`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    /// Normalized unit: ISO 4217 codes for currencies (`USD`), lowercase symbols otherwise (`kg`).
+    pub unit: String,
+}
+
+/// Appended to the caller's instructions so the script prints a value/unit pair.
+const QUANTITY_INSTRUCTIONS: &str = "Print a JSON object with exactly two keys: \"value\", the number as a JSON number without currency symbols or thousands separators, and \"unit\", the unit or currency as a string (e.g. \"USD\", \"kg\"). For example: {\"value\": 1299.0, \"unit\": \"USD\"}";
+
+/// Currency symbols and unit spellings mapped to their normalized form.
+const UNIT_ALIASES: &[(&str, &str)] = &[
+    ("$", "USD"), ("us$", "USD"), ("usd", "USD"), ("dollar", "USD"), ("dollars", "USD"),
+    ("€", "EUR"), ("eur", "EUR"), ("euro", "EUR"), ("euros", "EUR"),
+    ("£", "GBP"), ("gbp", "GBP"), ("pound sterling", "GBP"),
+    ("¥", "JPY"), ("jpy", "JPY"), ("yen", "JPY"),
+    ("kilogram", "kg"), ("kilograms", "kg"), ("kgs", "kg"),
+    ("gram", "g"), ("grams", "g"),
+    ("pound", "lb"), ("pounds", "lb"), ("lbs", "lb"),
+    ("ounce", "oz"), ("ounces", "oz"),
+    ("meter", "m"), ("meters", "m"), ("metre", "m"), ("metres", "m"),
+    ("centimeter", "cm"), ("centimeters", "cm"), ("centimetre", "cm"), ("centimetres", "cm"),
+    ("millimeter", "mm"), ("millimeters", "mm"),
+    ("inch", "in"), ("inches", "in"), ("\"", "in"),
+    ("foot", "ft"), ("feet", "ft"),
+    ("liter", "l"), ("liters", "l"), ("litre", "l"), ("litres", "l"),
+    ("milliliter", "ml"), ("milliliters", "ml"), ("millilitre", "ml"), ("millilitres", "ml"),
+    ("percent", "%"),
+];
+
+impl ParserClient {
+    /// Extracts a single numeric value with its unit, e.g. a price or a measurement. Numbers
+    /// printed as strings (`"1,299.00"`) are coerced, and units are normalized (`$` becomes
+    /// `USD`, `Kilograms` becomes `kg`). A value that isn't a number, or whose separators are
+    /// ambiguous (`"1.299,00"`), fails the attempt and is retried.
+    pub async fn dynamic_parse_quantity(&self, document: &str, instructions: &str) -> Result<Quantity, ParseError> {
+        info!("🔄 Starting quantity parse");
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            output_check: Some(is_quantity),
+            ..Default::default()
+        };
+        let instructions = format!("{}\n{}", instructions, QUANTITY_INSTRUCTIONS);
+        let (result, _) = self.parse_with_attempts(document, &instructions, &options).await?;
+        let value: Value = serde_json::from_str(&result)?;
+
+        let quantity = Quantity {
+            value: coerce_number(&value["value"]).map_err(ParseError::OutputRejected)?,
+            unit: normalize_unit(value["unit"].as_str().unwrap_or_default()),
+        };
+        debug!("Parsed quantity: {:?}", quantity);
+        Ok(quantity)
+    }
+}
+
+/// Accepts a value/unit pair whose value `coerce_number` can read.
+fn is_quantity(value: &Value) -> std::result::Result<(), String> {
+    coerce_number(&value["value"])?;
+    match value["unit"] {
+        Value::String(_) => Ok(()),
+        _ => Err(format!("Quantity unit is not a string: {}", value["unit"])),
+    }
+}
+
+/// Reads a JSON number, or a string holding one with symbols and comma thousands separators
+/// around it (`"$1,299.00"`). Strings whose separators could be read more than one way, such as
+/// `"1.299,00"` or `"1,5"`, are rejected rather than guessed at.
+fn coerce_number(value: &Value) -> std::result::Result<f64, String> {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    let number = NUMBER.get_or_init(|| Regex::new(r"^-?(\d{1,3}(,\d{3})+|\d+)(\.\d+)?$").unwrap());
+    let invalid = || format!("Quantity value is not an unambiguous number: {}. Print it as a JSON number", value);
+    match value {
+        Value::Number(number) => number.as_f64().ok_or_else(invalid),
+        Value::String(text) => {
+            let digits: String = text.chars().filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-')).collect();
+            if !number.is_match(&digits) {
+                return Err(invalid());
+            }
+            digits.replace(',', "").parse().map_err(|_| invalid())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn normalize_unit(unit: &str) -> String {
+    let trimmed = unit.trim();
+    let lower = trimmed.to_lowercase();
+    match UNIT_ALIASES.iter().find(|(alias, _)| *alias == lower) {
+        Some((_, normalized)) => normalized.to_string(),
+        // Three-letter alphabetic units are most likely currency codes.
+        None if trimmed.len() == 3 && trimmed.chars().all(|c| c.is_ascii_alphabetic()) && trimmed != lower => trimmed.to_uppercase(),
+        None => lower,
+    }
+}
@@ -0,0 +1,41 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Elements removed along with everything inside them: page chrome and non-content markup.
+static BOILERPLATE: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    let mut patterns = vec![Regex::new(r"(?s)<!--.*?-->").unwrap()];
+    for tag in ["script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form", "iframe"] {
+        patterns.push(Regex::new(&format!(r"(?is)<{tag}\b[^>]*>.*?</{tag}\s*>")).unwrap());
+    }
+    patterns
+});
+
+/// The first element explicitly marking the page's main content.
+static MAIN_CONTENT: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    ["main", "article"]
+        .into_iter()
+        .map(|tag| Regex::new(&format!(r"(?is)<{tag}\b[^>]*>(.*)</{tag}\s*>")).unwrap())
+        .collect()
+});
+
+static HTML_MARKER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<(!doctype\s+html|html|body)\b").unwrap());
+
+/// Distills an HTML page down to its main content: boilerplate elements such as navigation,
+/// headers, footers, sidebars, forms and scripts are dropped, and if the page marks its content
+/// with `<main>` or `<article>`, only that element's inner HTML is kept. The result is still
+/// HTML, so scripts can keep relying on tags. Returns `None` for documents that aren't HTML pages.
+pub(crate) fn main_content(document: &str) -> Option<String> {
+    if !HTML_MARKER.is_match(document) {
+        return None;
+    }
+    let mut html = document.to_string();
+    for pattern in BOILERPLATE.iter() {
+        html = pattern.replace_all(&html, "").into_owned();
+    }
+    for pattern in MAIN_CONTENT.iter() {
+        if let Some(content) = pattern.captures(&html).and_then(|captures| captures.get(1)) {
+            return Some(content.as_str().trim().to_string());
+        }
+    }
+    Some(html.trim().to_string())
+}
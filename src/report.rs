@@ -0,0 +1,64 @@
+use serde::Serialize;
+use std::fmt;
+use tracing::info;
+
+use crate::{CallOptions, FailureCategory, ParseAttempt, ParserClient, ScriptLanguage};
+
+/// Structured context for a failed parse, serializable for storage instead of scraping the
+/// formatted attempt history.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureReport {
+    /// The final error message.
+    pub error: String,
+    pub attempts: Vec<AttemptReport>,
+}
+
+/// One attempt within a `FailureReport`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AttemptReport {
+    pub attempt_number: usize,
+    pub language: ScriptLanguage,
+    pub script: String,
+    pub error: Option<String>,
+    pub category: Option<FailureCategory>,
+    pub generation_ms: u128,
+    /// `None` when no script ran.
+    pub execution_ms: Option<u128>,
+    pub command: Option<String>,
+}
+
+impl From<&ParseAttempt> for AttemptReport {
+    fn from(attempt: &ParseAttempt) -> Self {
+        Self {
+            attempt_number: attempt.attempt_number,
+            language: attempt.language,
+            script: attempt.script.clone(),
+            error: attempt.error.clone(),
+            category: attempt.failure_category,
+            generation_ms: attempt.generation_time.as_millis(),
+            execution_ms: attempt.execution_time.map(|elapsed| elapsed.as_millis()),
+            command: attempt.command.clone(),
+        }
+    }
+}
+
+impl fmt::Display for FailureReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Parse failed after {} attempts: {}", self.attempts.len(), self.error)
+    }
+}
+
+impl std::error::Error for FailureReport {}
+
+impl ParserClient {
+    /// Like `dynamic_parse`, but a failure carries a `FailureReport` with every attempt's script,
+    /// error, category and timings.
+    pub async fn dynamic_parse_or_report(&self, document: &str, instructions: &str) -> Result<String, FailureReport> {
+        info!("🔄 Starting dynamic parse with failure report");
+        let (result, attempts) = self.run_attempts(document, instructions, &CallOptions::default()).await;
+        result.map_err(|error| FailureReport {
+            error: error.to_string(),
+            attempts: attempts.iter().map(AttemptReport::from).collect(),
+        })
+    }
+}
@@ -0,0 +1,92 @@
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::ParseError;
+
+/// Operating-system limits applied to script subprocesses. Enforced with `setrlimit` on Unix and
+/// ignored elsewhere.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ResourceLimits {
+    /// Maximum address space, in bytes.
+    pub(crate) memory_bytes: Option<u64>,
+    /// Maximum CPU time; rounded up to whole seconds.
+    pub(crate) cpu: Option<Duration>,
+}
+
+impl ResourceLimits {
+    fn is_empty(&self) -> bool {
+        self.memory_bytes.is_none() && self.cpu.is_none()
+    }
+
+    fn cpu_seconds(&self) -> Option<u64> {
+        self.cpu.map(|cpu| cpu.as_secs() + u64::from(cpu.subsec_nanos() > 0))
+    }
+
+    /// Makes `command` apply the limits in the child before it runs the interpreter.
+    #[cfg(unix)]
+    pub(crate) fn apply(&self, command: &mut Command) {
+        if self.is_empty() {
+            return;
+        }
+        let memory_bytes = self.memory_bytes;
+        let cpu_seconds = self.cpu_seconds();
+        // SAFETY: the hook only calls `setrlimit` with valid `rlimit` values, which is
+        // async-signal-safe, and allocates nothing.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(bytes) = memory_bytes {
+                    check(libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes, bytes)))?;
+                }
+                if let Some(seconds) = cpu_seconds {
+                    // The soft limit sends SIGXCPU, which terminates the script; the hard limit one
+                    // second later is a SIGKILL backstop for scripts that catch it.
+                    check(libc::setrlimit(libc::RLIMIT_CPU, &rlimit(seconds, seconds + 1)))?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn apply(&self, _command: &mut Command) {
+        if !self.is_empty() {
+            tracing::warn!("Resource limits are only supported on Unix; running the script without them");
+        }
+    }
+
+    /// The limit a failed script ran into, judged from how it exited: killed by `SIGXCPU` under a
+    /// CPU limit, or a Python `MemoryError` under a memory limit. A `SIGKILL` isn't attributed to
+    /// the CPU limit, since out-of-memory kills and the crate's own timeouts end scripts the same
+    /// way; the rare script that catches `SIGXCPU` and then hits the hard limit is reported as a
+    /// plain failure.
+    pub(crate) fn exceeded(&self, status: ExitStatus, stderr: &str) -> Option<ParseError> {
+        #[cfg(unix)]
+        if let Some(limit) = self.cpu
+            && let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status)
+            && signal == libc::SIGXCPU
+        {
+            return Some(ParseError::CpuLimitExceeded { limit });
+        }
+        #[cfg(not(unix))]
+        let _ = status;
+
+        match self.memory_bytes {
+            Some(limit_bytes) if stderr.contains("MemoryError") => Some(ParseError::MemoryLimitExceeded { limit_bytes }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn rlimit(soft: u64, hard: u64) -> libc::rlimit {
+    libc::rlimit { rlim_cur: soft as libc::rlim_t, rlim_max: hard as libc::rlim_t }
+}
+
+#[cfg(unix)]
+fn check(result: libc::c_int) -> std::io::Result<()> {
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use serde_json::Value;
+use tracing::info;
+
+use crate::{CallOptions, ParseError, ParserClient};
+
+impl ParserClient {
+    /// Parses a document into JSON matching `schema`. The schema is shown to the model, and every
+    /// result is validated against it; violations are recorded as the attempt's error and fed back
+    /// on the next attempt.
+    ///
+    /// Supports the common structural subset of JSON Schema: `type` (a name or a list of names),
+    /// `enum`, `properties`, `required`, `additionalProperties: false` and `items`. Other keywords
+    /// are ignored.
+    pub async fn dynamic_parse_with_schema(&self, document: &str, instructions: &str, schema: &Value) -> Result<String, ParseError> {
+        info!("🔄 Starting dynamic parse operation with a JSON schema");
+        let options = CallOptions {
+            schema: Some(schema),
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        Ok(result)
+    }
+}
+
+/// Lists where `value` violates `schema`, with JSONPath-like locations as in `structure_mismatches`.
+pub(crate) fn schema_violations(schema: &Value, value: &Value) -> Vec<String> {
+    let mut violations = Vec::new();
+    collect_violations("$", schema, value, &mut violations);
+    violations
+}
+
+fn collect_violations(path: &str, schema: &Value, value: &Value, violations: &mut Vec<String>) {
+    let Value::Object(schema) = schema else {
+        // `true` and other non-object schemas accept anything.
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !names.is_empty() && !names.iter().any(|name| has_type(value, name)) {
+            violations.push(format!("{} should be of type {} but is {}", path, names.join(" or "), type_name(value)));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(value)
+    {
+        violations.push(format!("{} is {} but must be one of {}", path, value, Value::Array(allowed.clone())));
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        violations.push(format!("{}.{} is required but missing", path, key));
+                    }
+                }
+            }
+            for (key, item) in map {
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property) => collect_violations(&format!("{}.{}", path, key), property, item, violations),
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        violations.push(format!("{}.{} is not allowed by the schema", path, key));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    collect_violations(&format!("{}[{}]", path, index), item_schema, item, violations);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        _ => type_name(value) == name,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::{CallOptions, JsonChange, JsonDiff, ParserClient, Serialization};
+
+/// Parses successive versions of a document with the same instructions, remembering the last
+/// successful result so each parse can report what changed. Created with `ParserClient::session`.
+pub struct ParseSession<'a> {
+    client: &'a ParserClient,
+    instructions: String,
+    previous: Option<Value>,
+}
+
+impl ParserClient {
+    /// Starts a session that parses documents with `instructions` and diffs successive results.
+    pub fn session(&self, instructions: impl Into<String>) -> ParseSession<'_> {
+        ParseSession { client: self, instructions: instructions.into(), previous: None }
+    }
+}
+
+impl ParseSession<'_> {
+    /// Parses `document` and returns the result together with its diff from the previous
+    /// successful result. The first parse reports the whole result as added at `$`. Failed
+    /// parses leave the previous result untouched.
+    pub async fn parse_diff(&mut self, document: &str) -> Result<(Value, JsonDiff)> {
+        info!("🔁 Parsing next document in session");
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            ..Default::default()
+        };
+        let (result, _) = self.client.parse_with_attempts(document, &self.instructions, &options).await?;
+        let value: Value = serde_json::from_str(&result)?;
+
+        let diff = match &self.previous {
+            Some(previous) => JsonDiff::between(previous, &value),
+            None => JsonDiff { changes: vec![JsonChange::Added { path: "$".to_string(), value: value.clone() }] },
+        };
+        debug!("Session diff has {} changes", diff.changes.len());
+        self.previous = Some(value.clone());
+        Ok((value, diff))
+    }
+
+    /// The most recent successful result, if any.
+    pub fn previous(&self) -> Option<&Value> {
+        self.previous.as_ref()
+    }
+}
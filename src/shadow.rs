@@ -0,0 +1,70 @@
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+
+use crate::{CallOptions, ParserClient, output};
+
+/// Receives the outcome of every shadowed parse
+pub(crate) type ShadowCallback = Arc<dyn Fn(ShadowComparison) + Send + Sync>;
+
+/// The results of one `dynamic_parse` under the production configuration and the shadow one.
+/// Failures are reported as their error messages.
+#[derive(Debug, Clone)]
+pub struct ShadowComparison {
+    /// What `dynamic_parse` returned to the caller.
+    pub production: Result<String, String>,
+    /// What the shadow client produced for the same document and instructions.
+    pub shadow: Result<String, String>,
+    /// Whether both succeeded with equal results, compared as JSON using the production client's
+    /// JSON comparator when one is configured.
+    pub matches: bool,
+}
+
+impl ParserClient {
+    /// Runs a parse with this client and, in a background task, with the shadow client if one is
+    /// configured. The production outcome is returned as soon as it's ready; the task reports
+    /// both outcomes to the shadow callback once the shadow parse finishes too. If the caller
+    /// stops waiting before production finishes, nothing is reported.
+    pub(crate) async fn parse_shadowed(&self, document: &str, instructions: &str) -> Result<String> {
+        let options = CallOptions::default();
+        let Some((shadow_client, callback)) = &self.shadow else {
+            let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+            return Ok(result);
+        };
+
+        info!("👥 Running shadow parse in the background");
+        let (production_sender, production_receiver) = oneshot::channel::<Result<String, String>>();
+        let shadow_client = shadow_client.clone();
+        let callback = callback.clone();
+        let (document_owned, instructions_owned) = (document.to_string(), instructions.to_string());
+        let serialization = self.serialization;
+        let comparator = self.json_comparator.clone();
+        tokio::spawn(async move {
+            let shadow = shadow_client
+                .parse_with_attempts(&document_owned, &instructions_owned, &CallOptions::default())
+                .await
+                .map(|(result, _)| result)
+                .map_err(|e| e.to_string());
+            let Ok(production) = production_receiver.await else {
+                debug!("Production parse was cancelled; dropping the shadow result");
+                return;
+            };
+            let matches = match (&production, &shadow) {
+                (Ok(production), Ok(shadow)) => {
+                    match (output::parse_value(production, serialization), output::parse_value(shadow, shadow_client.serialization)) {
+                        (Ok(production), Ok(shadow)) => comparator.map_or(production == shadow, |comparator| comparator(&production, &shadow)),
+                        _ => production == shadow,
+                    }
+                }
+                _ => false,
+            };
+            debug!("Shadow result {} production", if matches { "matches" } else { "differs from" });
+            callback(ShadowComparison { production, shadow, matches });
+        });
+
+        let production = self.parse_with_attempts(document, instructions, &options).await.map(|(result, _)| result);
+        let _ = production_sender.send(production.as_ref().cloned().map_err(|e| e.to_string()));
+        production
+    }
+}
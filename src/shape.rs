@@ -0,0 +1,254 @@
+use serde::de::value::{Error, StrDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value, json};
+use tracing::{debug, info};
+
+use crate::{CallOptions, ParseError, ParserClient, Serialization};
+
+/// Parses a document straight into a Rust type, deriving the expected JSON shape from the type:
+/// `parse_into!(client, document, instructions, Product)` is
+/// `client.dynamic_parse_typed::<Product>(document, instructions)` and evaluates to a future of
+/// `anyhow::Result<Product>`.
+#[macro_export]
+macro_rules! parse_into {
+    ($client:expr, $document:expr, $instructions:expr, $ty:ty $(,)?) => {
+        $client.dynamic_parse_typed::<$ty>($document, $instructions)
+    };
+}
+
+impl ParserClient {
+    /// Parses a document into `T`. The JSON shape `T` deserializes from is shown to the model as
+    /// the expected output structure and enforced on every attempt, so the extraction contract
+    /// follows the Rust type; keys `T` doesn't have are allowed, since serde ignores them. Each
+    /// result must also deserialize into `T`, with serde errors fed back into retries as in
+    /// `dynamic_parse_into`. Types whose shape can't be derived are parsed without one.
+    pub async fn dynamic_parse_typed<T: DeserializeOwned>(&self, document: &str, instructions: &str) -> Result<T, ParseError> {
+        info!("🔄 Starting typed parse into {}", std::any::type_name::<T>());
+        let shape = shape_of::<T>();
+        debug!("Derived output shape: {:?}", shape);
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            output_example: shape.as_ref(),
+            output_check: Some(deserializes_into::<T>),
+            allow_extra_keys: true,
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        Ok(serde_json::from_str(&result)?)
+    }
+
+    /// Parses a document and deserializes the result into `T`. A result that doesn't deserialize
+    /// counts as a failed attempt, and the serde error is shown to the model on the next one.
+    /// Unlike `dynamic_parse_typed`, no expected structure is put in the prompt.
+    pub async fn dynamic_parse_into<T: DeserializeOwned>(&self, document: &str, instructions: &str) -> Result<T, ParseError> {
+        info!("🔄 Starting parse into {}", std::any::type_name::<T>());
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            output_check: Some(deserializes_into::<T>),
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, instructions, &options).await?;
+        Ok(serde_json::from_str(&result)?)
+    }
+
+    /// Parses a list of records, e.g. the rows of a product table, into a `Vec<T>`. The model is
+    /// asked for a JSON array, and every element must deserialize into `T`; a single object is
+    /// accepted as a one-record list. Serde errors are fed back into retries as in
+    /// `dynamic_parse_into`.
+    pub async fn dynamic_parse_array_into<T: DeserializeOwned>(&self, document: &str, instructions: &str) -> Result<Vec<T>, ParseError> {
+        info!("🔄 Starting parse into a list of {}", std::any::type_name::<T>());
+        let instructions = format!("{}\n{}", instructions, ARRAY_INSTRUCTIONS);
+        let options = CallOptions {
+            serialization: Some(Serialization::Json),
+            output_check: Some(records_deserialize_into::<T>),
+            ..Default::default()
+        };
+        let (result, _) = self.parse_with_attempts(document, &instructions, &options).await?;
+        match serde_json::from_str(&result)? {
+            Value::Array(records) => Ok(records.into_iter().map(serde_json::from_value).collect::<Result<_, _>>()?),
+            record => {
+                debug!("Model returned a single record instead of an array; wrapping it");
+                Ok(vec![serde_json::from_value(record)?])
+            }
+        }
+    }
+}
+
+/// Appended to the caller's instructions by `dynamic_parse_array_into`.
+const ARRAY_INSTRUCTIONS: &str = "The document may contain any number of records. Iterate over all of them and print a JSON array with one element per record, or an empty array if there are none.";
+
+/// Checks that every element of an array, or a single object standing in for one, deserializes
+/// into `T`.
+fn records_deserialize_into<T: DeserializeOwned>(value: &Value) -> Result<(), String> {
+    match value {
+        Value::Array(records) => records.iter().enumerate().try_for_each(|(index, record)| {
+            T::deserialize(record)
+                .map(drop)
+                .map_err(|e| format!("Record {} doesn't fit the expected type: {}. Fix the JSON shape.", index, e))
+        }),
+        record => deserializes_into::<T>(record),
+    }
+}
+
+/// Checks that `value` deserializes into `T`, describing the serde error for the model if not.
+fn deserializes_into<T: DeserializeOwned>(value: &Value) -> Result<(), String> {
+    T::deserialize(value)
+        .map(drop)
+        .map_err(|e| format!("Output doesn't fit the expected type: {}. Fix the JSON shape.", e))
+}
+
+/// Derives the JSON shape `T` deserializes from, as an output example: struct fields become keys,
+/// strings `""`, numbers `0`, booleans `false`, sequences a one-item array, and anything whose
+/// shape can't be pinned down (options, maps, enums, `serde_json::Value`) `null`, which matches
+/// any value. The shape is discovered by running `T`'s `Deserialize` impl against placeholder
+/// data, so it returns `None` for types that reject placeholders (e.g. validated newtypes).
+pub(crate) fn shape_of<T: DeserializeOwned>() -> Option<Value> {
+    let mut shape = Value::Null;
+    T::deserialize(Probe { shape: &mut shape }).ok()?;
+    Some(shape)
+}
+
+/// A deserializer feeding placeholder values to a visitor while recording what it was asked for.
+struct Probe<'a> {
+    shape: &'a mut Value,
+}
+
+macro_rules! probe_scalar {
+    ($($method:ident => $visit:ident($placeholder:expr), $shape:expr;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                *self.shape = $shape;
+                visitor.$visit($placeholder)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Probe<'_> {
+    type Error = Error;
+
+    probe_scalar! {
+        deserialize_bool => visit_bool(false), json!(false);
+        deserialize_i8 => visit_i8(0), json!(0);
+        deserialize_i16 => visit_i16(0), json!(0);
+        deserialize_i32 => visit_i32(0), json!(0);
+        deserialize_i64 => visit_i64(0), json!(0);
+        deserialize_i128 => visit_i128(0), json!(0);
+        deserialize_u8 => visit_u8(0), json!(0);
+        deserialize_u16 => visit_u16(0), json!(0);
+        deserialize_u32 => visit_u32(0), json!(0);
+        deserialize_u64 => visit_u64(0), json!(0);
+        deserialize_u128 => visit_u128(0), json!(0);
+        deserialize_f32 => visit_f32(0.0), json!(0.0);
+        deserialize_f64 => visit_f64(0.0), json!(0.0);
+        deserialize_char => visit_char(' '), json!("");
+        deserialize_str => visit_str(""), json!("");
+        deserialize_string => visit_str(""), json!("");
+        deserialize_bytes => visit_bytes(&[]), Value::Null;
+        deserialize_byte_buf => visit_bytes(&[]), Value::Null;
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.shape = Value::Null;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.shape = Value::Null;
+        visitor.visit_none()
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_tuple(1, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        *self.shape = Value::Array(vec![Value::Null; len]);
+        let Value::Array(items) = self.shape else { unreachable!() };
+        visitor.visit_seq(ProbeSeq { items: items.iter_mut() })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // Keys are data, not structure, so any object matches.
+        *self.shape = Value::Null;
+        visitor.visit_map(ProbeStruct { fields: [].iter(), shape: &mut Map::new(), pending: None })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        *self.shape = Value::Object(Map::new());
+        let Value::Object(shape) = self.shape else { unreachable!() };
+        visitor.visit_map(ProbeStruct { fields: fields.iter(), shape, pending: None })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        *self.shape = Value::Null;
+        let variant: StrDeserializer<'_, Error> = variants.first().copied().unwrap_or_default().into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+}
+
+/// Yields one probed element per placeholder slot.
+struct ProbeSeq<'a> {
+    items: std::slice::IterMut<'a, Value>,
+}
+
+impl<'de> SeqAccess<'de> for ProbeSeq<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        match self.items.next() {
+            Some(shape) => seed.deserialize(Probe { shape }).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Yields every declared field of a struct with a probed value.
+struct ProbeStruct<'a> {
+    fields: std::slice::Iter<'static, &'static str>,
+    shape: &'a mut Map<String, Value>,
+    pending: Option<&'static str>,
+}
+
+impl<'de> MapAccess<'de> for ProbeStruct<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        let Some(field) = self.fields.next() else {
+            return Ok(None);
+        };
+        self.pending = Some(field);
+        let key: StrDeserializer<'_, Error> = field.into_deserializer();
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let field = self.pending.take().ok_or_else(|| de::Error::custom("value requested before key"))?;
+        let shape = self.shape.entry(field).or_insert(Value::Null);
+        seed.deserialize(Probe { shape })
+    }
+}
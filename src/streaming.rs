@@ -0,0 +1,182 @@
+use anyhow::{Result, anyhow};
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::{
+    CallOptions, NO_ATTEMPTS, ParseError, ParserClient, ScriptLanguage, deadline_exceeded, document_file, read_capped,
+    retries_exhausted, spawn_error, subprocess_command, within_deadline,
+};
+use crate::temp::TempArtifact;
+
+/// Appended to the caller's instructions so the script emits records as it finds them.
+const STREAMING_INSTRUCTIONS: &str = "Print each record as soon as it is found, as one JSON object per line (NDJSON), and call sys.stdout.flush() after every line. Do not collect the records into a list or print anything else.";
+
+enum RecordStream {
+    Pending,
+    Running(Box<RunningScript>),
+    Finished,
+}
+
+struct RunningScript {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+    stderr: JoinHandle<std::io::Result<(Vec<u8>, usize)>>,
+    script: String,
+    /// The document, in `InputMode::TempFile`; deleted when the stream is dropped.
+    _document_file: Option<TempArtifact>,
+    /// Held until the stream is dropped so the script counts against the global subprocess limit.
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl ParserClient {
+    /// Generates a script that prints one JSON record per line and yields each record as soon as
+    /// the script prints it, without buffering the whole output. Script generation is retried as
+    /// usual, but the script itself runs once: a line that isn't valid JSON or a non-zero exit ends
+    /// the stream with an error. The client's total deadline, counted from this call, covers
+    /// generation and the whole run; when it passes, the script is killed and the stream ends with
+    /// `ParseError::DeadlineExceeded`.
+    ///
+    /// Scripts are always Python, run in a subprocess of the client's interpreter even when a
+    /// custom executor is configured. Clients whose languages (see `with_language_fallback`) don't
+    /// include Python fail immediately.
+    pub fn dynamic_parse_streaming_records<'a>(
+        &'a self,
+        document: &'a str,
+        instructions: &'a str,
+    ) -> impl Stream<Item = Result<Value, ParseError>> + 'a {
+        let deadline = self.total_deadline.map(|limit| (limit, tokio::time::Instant::now() + limit));
+        let records = futures::stream::unfold(RecordStream::Pending, move |mut state| async move {
+            loop {
+                state = match state {
+                    RecordStream::Pending => match within_deadline(deadline, self.spawn_record_script(document, instructions)).await {
+                        Some(Ok(running)) => running,
+                        Some(Err(e)) => return Some((Err(e), RecordStream::Finished)),
+                        None => {
+                            warn!("⏰ Parse deadline passed before the record stream started");
+                            return Some((Err(deadline_exceeded(deadline, &[])), RecordStream::Finished));
+                        }
+                    },
+                    RecordStream::Running(mut running) => {
+                        let Some(line) = within_deadline(deadline, running.lines.next_line()).await else {
+                            warn!("⏰ Parse deadline passed while streaming records; killing the script");
+                            let _ = running.child.start_kill();
+                            return Some((Err(deadline_exceeded(deadline, &[])), RecordStream::Finished));
+                        };
+                        match line {
+                            Ok(Some(line)) if line.trim().is_empty() => RecordStream::Running(running),
+                            Ok(Some(line)) => {
+                                let record = serde_json::from_str(&line).map_err(|e| {
+                                    anyhow!(ParseError::InvalidJson { error: e.to_string(), output: line.clone() })
+                                });
+                                let next = match record {
+                                    Ok(_) => RecordStream::Running(running),
+                                    Err(_) => RecordStream::Finished,
+                                };
+                                return Some((record, next));
+                            }
+                            Ok(None) => {
+                                let status = match within_deadline(deadline, running.child.wait()).await {
+                                    Some(Ok(status)) => status,
+                                    Some(Err(e)) => return Some((Err(e.into()), RecordStream::Finished)),
+                                    None => {
+                                        warn!("⏰ Parse deadline passed waiting for the record script to exit; killing it");
+                                        let _ = running.child.start_kill();
+                                        return Some((Err(deadline_exceeded(deadline, &[])), RecordStream::Finished));
+                                    }
+                                };
+                                if status.success() {
+                                    info!("✅ Record stream finished");
+                                    return None;
+                                }
+                                let RunningScript { stderr, script, .. } = *running;
+                                let (kept, _) = stderr.await.ok().and_then(Result::ok).unwrap_or_default();
+                                let error = ParseError::NonZeroExit {
+                                    code: status.code().unwrap_or(-1),
+                                    stderr: String::from_utf8_lossy(&kept).into_owned(),
+                                    script,
+                                };
+                                warn!("Record stream script failed: {}", error);
+                                return Some((Err(error.into()), RecordStream::Finished));
+                            }
+                            Err(e) => return Some((Err(e.into()), RecordStream::Finished)),
+                        }
+                    }
+                    RecordStream::Finished => return None,
+                };
+            }
+        });
+        records.map(|record| record.map_err(ParseError::from))
+    }
+
+    /// Generates a streaming script and starts it, feeding the document to stdin in the background.
+    async fn spawn_record_script(&self, document: &str, instructions: &str) -> Result<RecordStream> {
+        info!("🌊 Starting streaming record parse");
+        if !self.language_fallback.is_empty() && !self.language_fallback.contains(&ScriptLanguage::Python) {
+            let languages: Vec<&str> = self.language_fallback.iter().map(|language| language.name()).collect();
+            anyhow::bail!("Streaming record parsing only supports Python scripts, but this client writes {}", languages.join(", "));
+        }
+        let document = &*self.prepare_document(document);
+        let instructions = format!("{}\n{}", instructions, STREAMING_INSTRUCTIONS);
+        let language = ScriptLanguage::Python;
+
+        let mut last_error = retries_exhausted(&[], NO_ATTEMPTS.to_string());
+        let mut script = None;
+        for attempt in 1..=self.max_retries {
+            let prompt = self.build_user_prompt(document, &instructions, &[], attempt, language, &CallOptions::default());
+            match self.generator.generate(&self.get_system_prompt(language), &prompt).await {
+                Ok(response) => match self.review_response(&response, language, &CallOptions::default()) {
+                    (_, Some(rejection)) => {
+                        warn!("Rejected the model's response on attempt {}: {}", attempt, rejection);
+                        last_error = rejection.into();
+                    }
+                    (code, None) => {
+                        script = Some(code);
+                        break;
+                    }
+                },
+                Err(e) => {
+                    warn!("Script generation failed on attempt {}: {}", attempt, e);
+                    last_error = ParseError::Generation(e.to_string()).into();
+                }
+            }
+        }
+        let script = self.prepare_script(&script.ok_or(last_error)?, language);
+
+        let slot = self.subprocess_limit().acquire().await;
+        let document_file = document_file(self.input_mode, document)?;
+        let (program, eval_flag) = subprocess_command(&self.interpreter, language);
+        debug!("Spawning {} for record stream...", program.display());
+        let mut command = Command::new(program);
+        command.arg(eval_flag).arg(&script);
+        match &document_file {
+            Some(file) => command.arg(file.path()).stdin(Stdio::null()),
+            None => command.stdin(Stdio::piped()),
+        };
+        self.resource_limits.apply(&mut command);
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| spawn_error(e, program))?;
+
+        let stdout = child.stdout.take().expect("Failed to open stdout");
+        let stderr = child.stderr.take().expect("Failed to open stderr");
+        if let Some(mut stdin) = child.stdin.take() {
+            let document = document.to_string();
+            tokio::spawn(async move {
+                // A script may stop reading early; a broken pipe here isn't an error worth reporting.
+                let _ = stdin.write_all(document.as_bytes()).await;
+            });
+        }
+        let stderr = tokio::spawn(read_capped(stderr, self.max_stderr_bytes));
+
+        Ok(RecordStream::Running(Box::new(RunningScript { child, lines: BufReader::new(stdout).lines(), stderr, script, _document_file: document_file, _slot: slot })))
+    }
+}
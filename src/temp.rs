@@ -0,0 +1,61 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{trace, warn};
+
+/// Distinguishes artifacts created by this process within the same clock tick.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A uniquely named temporary file that is deleted when the guard is dropped. Every feature that
+/// needs a scratch file goes through this, so names never collide across processes or threads
+/// and nothing is left behind when a parse fails halfway.
+///
+/// Files are named `dyn-parse-<pid>-<nanos>-<counter>.<extension>` and created exclusively, so an
+/// existing file is never reused.
+#[derive(Debug)]
+pub(crate) struct TempArtifact {
+    path: PathBuf,
+}
+
+impl TempArtifact {
+    /// Creates an empty artifact in `dir`, or the system temp directory when `None`.
+    pub(crate) fn create(dir: Option<&Path>, extension: &str) -> io::Result<(Self, File)> {
+        let dir = dir.map_or_else(std::env::temp_dir, Path::to_path_buf);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos());
+        loop {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("dyn-parse-{}-{}-{}.{}", std::process::id(), nanos, id, extension));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    trace!("Created temp artifact {}", path.display());
+                    return Ok((Self { path }, file));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Creates an artifact holding `contents`.
+    pub(crate) fn with_contents(dir: Option<&Path>, extension: &str, contents: &[u8]) -> io::Result<Self> {
+        let (artifact, mut file) = Self::create(dir, extension)?;
+        io::Write::write_all(&mut file, contents)?;
+        Ok(artifact)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempArtifact {
+    fn drop(&mut self) {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => trace!("Removed temp artifact {}", self.path.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove temp artifact {}: {}", self.path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! In-process fakes for testing code built on `ParserClient` without a model or an interpreter.
+//! `ParserClientBuilder::for_testing` wires both in; swap in your own with `with_generator` and
+//! `with_executor` to script each attempt.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+use crate::{Generation, ParserClientBuilder, ScriptExecutor, ScriptGenerator, ScriptOutput};
+
+/// A generator that returns canned responses in order, repeating the last one once they run out,
+/// and records every prompt it receives. Clones share the recorded prompts, so a test can keep a
+/// handle after handing one to the builder.
+#[derive(Clone)]
+pub struct ScriptedGenerator {
+    responses: Vec<String>,
+    prompts: Arc<Mutex<Vec<String>>>,
+    logprob: Option<f32>,
+}
+
+impl ScriptedGenerator {
+    /// Replays `responses`, which must not be empty.
+    pub fn new(responses: &[&str]) -> Self {
+        assert!(!responses.is_empty(), "ScriptedGenerator needs at least one response");
+        Self {
+            responses: responses.iter().map(|response| response.to_string()).collect(),
+            prompts: Arc::new(Mutex::new(Vec::new())),
+            logprob: None,
+        }
+    }
+
+    /// Reports `logprob` as the mean token log-probability of every response, e.g. to exercise
+    /// `with_confidence_threshold`.
+    pub fn with_logprob(mut self, logprob: f32) -> Self {
+        self.logprob = Some(logprob);
+        self
+    }
+
+    /// Every user prompt received so far, one per attempt.
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl ScriptGenerator for ScriptedGenerator {
+    async fn generate(&self, _system_prompt: &str, prompt: &str) -> Result<String> {
+        let mut prompts = self.prompts.lock().unwrap();
+        prompts.push(prompt.to_string());
+        let index = (prompts.len() - 1).min(self.responses.len() - 1);
+        Ok(self.responses[index].clone())
+    }
+
+    async fn generate_with_logprob(&self, system_prompt: &str, prompt: &str) -> Result<Generation> {
+        let text = self.generate(system_prompt, prompt).await?;
+        Ok(Generation { text, logprob: self.logprob })
+    }
+}
+
+/// An executor that "runs" a script by calling a closure with the script and the document, so
+/// tests decide what each script prints or how it fails.
+pub struct FnExecutor<F> {
+    run: F,
+}
+
+impl<F> FnExecutor<F>
+where
+    F: Fn(&str, &str) -> Result<ScriptOutput> + Send + Sync,
+{
+    pub fn new(run: F) -> Self {
+        Self { run }
+    }
+}
+
+#[async_trait]
+impl<F> ScriptExecutor for FnExecutor<F>
+where
+    F: Fn(&str, &str) -> Result<ScriptOutput> + Send + Sync,
+{
+    async fn execute(&self, script: &str, document: &str) -> Result<ScriptOutput> {
+        (self.run)(script, document)
+    }
+}
+
+impl ParserClientBuilder {
+    /// A builder that needs neither a model nor an interpreter: the generator always answers
+    /// `print('{}')` and the executor prints `{}` for any script. Override either with
+    /// `with_generator` (e.g. a `ScriptedGenerator`) and `with_executor` (e.g. an `FnExecutor`).
+    pub fn for_testing() -> Self {
+        Self::default()
+            .with_generator(ScriptedGenerator::new(&["print('{}')"]))
+            .with_executor(FnExecutor::new(|_script: &str, _document: &str| {
+                Ok(ScriptOutput { stdout: "{}".to_string(), stderr: String::new() })
+            }))
+    }
+}
@@ -0,0 +1,61 @@
+use kalosm::language::Tokenizer;
+use std::sync::Arc;
+
+/// Splits text into model token ids.
+pub trait TextTokenizer: Send + Sync {
+    /// Encodes `text` without adding special tokens.
+    fn tokenize(&self, text: &str) -> Vec<u32>;
+}
+
+/// The tokenizer of a loaded kalosm model.
+pub(crate) struct ModelTokenizer(pub(crate) Arc<Tokenizer>);
+
+impl TextTokenizer for ModelTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<u32> {
+        match self.0.encode(text, false) {
+            Ok(encoding) => encoding.get_ids().to_vec(),
+            Err(_) => ApproximateTokenizer.tokenize(text),
+        }
+    }
+}
+
+/// Fallback used when the generator has no real tokenizer, e.g. a remote or fake backend.
+/// Mimics subword tokenizers closely enough for budgeting: each punctuation character is one
+/// token, and so is each chunk of at most four characters of a word. Ids are stable FNV-1a
+/// hashes of the pieces.
+pub(crate) struct ApproximateTokenizer;
+
+/// Longest word fragment treated as a single token.
+const MAX_PIECE_CHARS: usize = 4;
+
+impl TextTokenizer for ApproximateTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<u32> {
+        let mut ids = Vec::new();
+        for word in text.split_whitespace() {
+            let mut piece = String::new();
+            for c in word.chars() {
+                let boundary = !c.is_alphanumeric()
+                    || piece.chars().count() == MAX_PIECE_CHARS
+                    || piece.chars().last().is_some_and(|last| !last.is_alphanumeric());
+                if boundary && !piece.is_empty() {
+                    ids.push(piece_id(&piece));
+                    piece.clear();
+                }
+                piece.push(c);
+            }
+            if !piece.is_empty() {
+                ids.push(piece_id(&piece));
+            }
+        }
+        ids
+    }
+}
+
+fn piece_id(piece: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in piece.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
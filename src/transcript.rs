@@ -0,0 +1,51 @@
+use serde::Serialize;
+use std::borrow::Cow;
+
+use crate::ParseAttempt;
+
+/// Who produced a turn of a `ChatTranscript`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+/// One message exchanged with the model.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatTurn {
+    pub role: ChatRole,
+    pub content: String,
+    /// The attempt this turn belongs to; system turns belong to the first attempt that used them.
+    pub attempt: usize,
+}
+
+/// Every message exchanged with the model during one parse, in order, for auditing model
+/// behavior. Passed to the sink configured with `ParserClientBuilder::with_transcript_sink`.
+///
+/// The transcript opens with the system prompt, and repeats it only when a later attempt uses a
+/// different one (e.g. after a language fallback). Each attempt then adds the user prompt and,
+/// unless generation failed, the model's raw response.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChatTranscript {
+    pub turns: Vec<ChatTurn>,
+}
+
+impl ChatTranscript {
+    pub(crate) fn from_attempts<'a>(attempts: &[ParseAttempt], system_prompt: impl Fn(&ParseAttempt) -> Cow<'a, str>) -> Self {
+        let mut turns: Vec<ChatTurn> = Vec::new();
+        let mut current_system: Option<Cow<'a, str>> = None;
+        for attempt in attempts {
+            let system = system_prompt(attempt);
+            if current_system.as_ref() != Some(&system) {
+                turns.push(ChatTurn { role: ChatRole::System, content: system.to_string(), attempt: attempt.attempt_number });
+                current_system = Some(system);
+            }
+            turns.push(ChatTurn { role: ChatRole::User, content: attempt.prompt.clone(), attempt: attempt.attempt_number });
+            if let Some(response) = &attempt.response {
+                turns.push(ChatTurn { role: ChatRole::Assistant, content: response.clone(), attempt: attempt.attempt_number });
+            }
+        }
+        Self { turns }
+    }
+}